@@ -0,0 +1,47 @@
+// Full-table scan throughput through the row iterator.
+//
+//     cargo bench --bench scan [rows]
+use std::fs;
+use std::time::Instant;
+
+use codecrafters_sqlite::database::{AccessPattern, Database};
+use codecrafters_sqlite::fixture::table_fixture;
+
+const ITERATIONS: usize = 5;
+
+fn main() -> anyhow::Result<()> {
+    let rows: usize = std::env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(100_000);
+    let image = table_fixture(rows);
+    let path = std::env::temp_dir().join(format!("scan-bench-{}.db", std::process::id()));
+    fs::write(&path, &image)?;
+    println!("{} rows, {} pages", rows, image.len() / codecrafters_sqlite::fixture::PAGE_SIZE);
+
+    bench("in-memory", rows, || Database::from_bytes(image.clone()))?;
+    bench("buffered file", rows, || Database::open(path.to_str().unwrap_or_default()))?;
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    bench("mmap", rows, || {
+        let db = Database::open_mmap(path.to_str().unwrap_or_default())?;
+        db.set_access_pattern(AccessPattern::Sequential)?;
+        Ok(db)
+    })?;
+
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+fn bench(label: &str, rows: usize, open: impl Fn() -> anyhow::Result<Database>) -> anyhow::Result<()> {
+    let mut best = f64::MAX;
+    for _ in 0..ITERATIONS {
+        let mut db = open()?;
+        let start = Instant::now();
+        let mut scanned = 0;
+        for row in db.rows("t")? {
+            row?;
+            scanned += 1;
+        }
+        assert_eq!(scanned, rows);
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    println!("{:<14} {:>12.0} rows/s (best of {})", label, rows as f64 / best, ITERATIONS);
+    Ok(())
+}
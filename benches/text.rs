@@ -0,0 +1,29 @@
+// Decoding a large text value: decode_text against the old per-char push.
+//
+//     cargo bench --bench text [bytes]
+use std::hint::black_box;
+use std::time::Instant;
+
+use codecrafters_sqlite::value::{decode_text, TextEncoding};
+
+const ITERATIONS: usize = 5;
+
+fn main() {
+    let size: usize = std::env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(16 << 20);
+    let text: Vec<u8> = b"the quick brown fox jumps over the lazy dog ".iter().copied().cycle().take(size).collect();
+    println!("{} bytes of text", size);
+
+    #[allow(deprecated)]
+    bench("convert_from_ascii", size, || codecrafters_sqlite::convert_from_ascii(&text).len());
+    bench("decode_text", size, || decode_text(&text, TextEncoding::Utf8).len());
+}
+
+fn bench(label: &str, size: usize, decode: impl Fn() -> usize) {
+    let mut best = f64::MAX;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        assert_eq!(black_box(decode()), size);
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+    println!("{:<20} {:>10.1} MB/s (best of {})", label, size as f64 / best / 1e6, ITERATIONS);
+}
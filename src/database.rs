@@ -0,0 +1,2745 @@
+use anyhow::{bail, Context, Result};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom};
+use std::io::Cursor;
+use std::rc::Rc;
+
+use crate::error::SqliteError;
+use crate::output::{csv_field, json_string};
+use crate::parser::parse_select;
+use crate::planner::KeyRange;
+use crate::query::{execute_select, ResultSet};
+use crate::schema::{
+    is_schema_table, parse_create_table, parse_foreign_keys, parse_index_info, schema_table, IndexInfo, TableSchema,
+};
+use crate::{decode_varint, Cell, Record, Records};
+use crate::page::{btree_header_offset, cell_pointers, right_most_pointer, PageKind, PageType};
+use crate::value::{
+    compare_values, decode_columns, decode_record_with_encoding, decode_value, render, serial_size, TextEncoding, Value,
+};
+
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+// Hint about how pages are about to be read, passed on to the OS for
+// memory-mapped databases
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPattern {
+    Normal,
+    // Full table scans
+    Sequential,
+    // Index lookups and keyed descents
+    Random,
+}
+
+// Why a page was read, as recorded by --trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadReason {
+    Schema,
+    // Following child pointers towards a key, or down to the next leaf
+    Descent,
+    LeafScan,
+    Index,
+    Overflow,
+    Freelist,
+    Other,
+}
+
+impl ReadReason {
+    pub fn label(self) -> &'static str {
+        match self {
+            ReadReason::Schema => "schema",
+            ReadReason::Descent => "descent",
+            ReadReason::LeafScan => "leaf-scan",
+            ReadReason::Index => "index",
+            ReadReason::Overflow => "overflow",
+            ReadReason::Freelist => "freelist",
+            ReadReason::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRead {
+    pub page: u32,
+    pub kind: PageKind,
+    pub reason: ReadReason,
+}
+
+// Shares the mapping between the reader and the Database, which needs it
+// to give access pattern hints
+#[cfg(all(unix, target_pointer_width = "64"))]
+struct SharedMmap(Rc<crate::mmap::Mmap>);
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+impl AsRef<[u8]> for SharedMmap {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref().as_ref()
+    }
+}
+
+// Pages read so far, and how many of them belonged to table and to index
+// b-trees. Page 1 and overflow pages count only towards the total.
+// Subtract a snapshot taken before a query to get its own reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageStats {
+    pub total: u64,
+    pub table: u64,
+    pub index: u64,
+}
+
+impl PageStats {
+    pub fn since(&self, earlier: PageStats) -> PageStats {
+        PageStats {
+            total: self.total - earlier.total,
+            table: self.table - earlier.table,
+            index: self.index - earlier.index,
+        }
+    }
+}
+
+// Receives debug messages about page reads and b-tree decisions. A plain
+// function pointer, so the executor can copy it out and keep logging while
+// the database is borrowed by a scan.
+pub type Log = fn(&str);
+
+pub struct Database {
+    reader: Box<dyn ReadSeek>,
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    mmap: Option<Rc<crate::mmap::Mmap>>,
+    page_size: usize,
+    usable_size: usize,
+    page_count: u32,
+    // Set at open when the header's page count (offset 28) disagrees with
+    // the file's length
+    size_mismatch: Option<String>,
+    // Bumped by every transaction that changes the file (offset 24)
+    change_counter: u32,
+    freelist_trunk: u32,
+    // Total free pages according to the header (offset 36)
+    freelist_count: u32,
+    schema_format: u32,
+    // Set when the file is in auto-vacuum or incremental-vacuum mode, and so
+    // has pointer-map pages
+    auto_vacuum: bool,
+    text_encoding: TextEncoding,
+    stats: PageStats,
+    max_pages: Option<u64>,
+    log: Option<Log>,
+    // Every page read, once tracing is switched on
+    trace: Option<Vec<PageRead>>,
+    // Set by callers that know better than the page's type why it is read
+    reason: Option<ReadReason>,
+}
+
+#[derive(Debug, Default)]
+pub struct Freelist {
+    pub trunks: Vec<u32>,
+    pub leaves: Vec<u32>,
+}
+
+impl Freelist {
+    // Trunk pages are free too, so they count alongside the leaves
+    pub fn page_count(&self) -> usize {
+        self.trunks.len() + self.leaves.len()
+    }
+}
+
+// Machine-readable overview of a database, for `.dbinfo --json`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbSummary {
+    pub page_size: usize,
+    pub page_count: u32,
+    pub encoding: TextEncoding,
+    // (name, root page) of every table, in schema order
+    pub tables: Vec<(String, u32)>,
+}
+
+impl DbSummary {
+    pub fn to_json(&self) -> String {
+        let tables: Vec<String> = self
+            .tables
+            .iter()
+            .map(|(name, root_page)| format!("{{\"name\":{},\"root_page\":{}}}", json_string(name), root_page))
+            .collect();
+        format!(
+            "{{\"page_size\":{},\"page_count\":{},\"encoding\":{},\"tables\":[{}]}}",
+            self.page_size,
+            self.page_count,
+            json_string(self.encoding.name()),
+            tables.join(",")
+        )
+    }
+}
+
+// Parsing needs random access, so a gzip file is inflated into memory whole
+#[cfg(feature = "gzip")]
+fn inflate(mut file: File) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut compressed)?;
+    crate::gzip::decompress(&compressed)
+}
+
+// Rows export_csv writes between flushes
+const CSV_FLUSH_ROWS: u64 = 1024;
+
+// The 16 bytes every database file starts with
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+impl Database {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_with_page_size(path, None)
+    }
+
+    // Reads the file as if its header gave `page_size`, for databases whose
+    // header has it wrong
+    pub fn open_with_page_size(path: &str, page_size: Option<usize>) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+        #[cfg(feature = "gzip")]
+        if path.ends_with(".gz") {
+            let data = inflate(file).with_context(|| format!("failed to decompress {}", path))?;
+            return Self::open_from_with_page_size(Cursor::new(data), page_size);
+        }
+        Self::from_file_with_page_size(file, page_size)
+    }
+
+    // Takes over a file the caller already has open, reading it from the
+    // start whatever its current position
+    pub fn from_file(file: File) -> Result<Self> {
+        Self::from_file_with_page_size(file, None)
+    }
+
+    #[allow(unused_mut)]
+    pub fn from_file_with_page_size(mut file: File, page_size: Option<usize>) -> Result<Self> {
+        #[cfg(feature = "gzip")]
+        {
+            let mut magic = [0; 2];
+            file.seek(SeekFrom::Start(0))?;
+            let read = file.read(&mut magic)?;
+            if crate::gzip::is_gzip(&magic[..read]) {
+                let data = inflate(file).context("failed to decompress the database")?;
+                return Self::open_from_with_page_size(Cursor::new(data), page_size);
+            }
+        }
+        Self::open_from_with_page_size(file, page_size)
+    }
+
+    /// A database held entirely in memory, such as a FixtureBuilder image or
+    /// a file fetched over the network. The simplest way to get a database
+    /// in tests and tools.
+    ///
+    /// Listing its tables, as `.tables` does:
+    ///
+    /// ```
+    /// use codecrafters_sqlite::database::Database;
+    /// use codecrafters_sqlite::fixture::FixtureBuilder;
+    ///
+    /// let image = FixtureBuilder::default()
+    ///     .add_table("fruit", "CREATE TABLE fruit(name TEXT)", &[])
+    ///     .add_table("veg", "CREATE TABLE veg(name TEXT)", &[])
+    ///     .build();
+    /// let mut db = Database::from_bytes(image).unwrap();
+    /// assert_eq!(db.schema().unwrap().table_names(), ["fruit", "veg"]);
+    /// ```
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::open_from(Cursor::new(bytes))
+    }
+
+    pub fn open_from<R: Read + Seek + 'static>(reader: R) -> Result<Self> {
+        Self::open_from_with_page_size(reader, None)
+    }
+
+    pub fn open_from_with_page_size<R: Read + Seek + 'static>(mut reader: R, page_size: Option<usize>) -> Result<Self> {
+        let mut header = [0; 100];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header).context("file is too short to be a database")?;
+        if header[..16] != MAGIC[..] {
+            if let Some(size) = looks_encrypted(&mut reader)? {
+                return Err(SqliteError::PossiblyEncrypted { size }.into());
+            }
+            bail!("file is not a database: missing the SQLite header");
+        }
+        // The page size is stored at the 16th byte offset, a value of 1 meaning 65536.
+        // Checked before any page buffer is sized from it.
+        let page_size = match (page_size, u16::from_be_bytes([header[16], header[17]])) {
+            (Some(page_size), _) => page_size,
+            (None, 1) => 65536,
+            (None, n) => n as usize,
+        };
+        if !page_size.is_power_of_two() || !(512..=65536).contains(&page_size) {
+            bail!("invalid page size {}", page_size);
+        }
+        check_first_page(&mut reader, page_size)?;
+        let reserved = header[20] as usize;
+        // The in-header database size is only trusted alongside a matching
+        // version-valid-for number; otherwise fall back to the file length
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        // Page 1 holds the schema, so without all of it there's nothing to read
+        if file_size < page_size as u64 {
+            bail!("file is truncated: {} bytes is less than one {}-byte page", file_size, page_size);
+        }
+        let header_page_count = u32::from_be_bytes(header[28..32].try_into()?);
+        let file_pages = file_size / page_size as u64;
+        let size_mismatch = if file_size % page_size as u64 != 0 {
+            Some(format!("file length {} is not a whole number of {}-byte pages", file_size, page_size))
+        } else if header_page_count as u64 != file_pages {
+            Some(format!("header says {} pages but the file holds {}", header_page_count, file_pages))
+        } else {
+            None
+        };
+        let mut page_count = header_page_count;
+        if page_count == 0 || header[24..28] != header[92..96] {
+            page_count = file_pages as u32;
+        }
+        let change_counter = u32::from_be_bytes(header[24..28].try_into()?);
+        let freelist_trunk = u32::from_be_bytes(header[32..36].try_into()?);
+        let freelist_count = u32::from_be_bytes(header[36..40].try_into()?);
+        // Formats 1 to 4 are defined, and a database with an empty schema
+        // may still have 0
+        let schema_format = u32::from_be_bytes(header[44..48].try_into()?);
+        if schema_format > 4 {
+            bail!("unsupported schema format number {} (expected 1 to 4)", schema_format);
+        }
+        Ok(Database {
+            reader: Box::new(reader),
+            #[cfg(all(unix, target_pointer_width = "64"))]
+            mmap: None,
+            page_size,
+            usable_size: page_size - reserved,
+            page_count,
+            size_mismatch,
+            change_counter,
+            freelist_trunk,
+            freelist_count,
+            schema_format,
+            // The largest root page number, only kept by auto-vacuum databases
+            auto_vacuum: header[52..56] != [0; 4],
+            text_encoding: TextEncoding::from_header(&header),
+            stats: PageStats::default(),
+            max_pages: None,
+            log: None,
+            trace: None,
+            reason: None,
+        })
+    }
+
+    // Memory-maps the file instead of reading it. Nothing else may truncate
+    // or write to it while the database is open.
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub fn open_mmap(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+        // SAFETY: as for Mmap::map; the database is opened read-only and the
+        // caller is told above not to let the file change while it's open
+        let mmap = unsafe { crate::mmap::Mmap::map(&file) }.with_context(|| format!("failed to map {}", path))?;
+        let mmap = Rc::new(mmap);
+        let mut db = Self::open_from(Cursor::new(SharedMmap(mmap.clone())))?;
+        db.mmap = Some(mmap);
+        Ok(db)
+    }
+
+    // Only has an effect on memory-mapped databases
+    pub fn set_access_pattern(&self, pattern: AccessPattern) -> Result<()> {
+        #[cfg(all(unix, target_pointer_width = "64"))]
+        if let Some(mmap) = &self.mmap {
+            mmap.advise(pattern)?;
+        }
+        #[cfg(not(all(unix, target_pointer_width = "64")))]
+        let _ = pattern;
+        Ok(())
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.page_count
+    }
+
+    pub fn freelist_count(&self) -> u32 {
+        self.freelist_count
+    }
+
+    // As read at open. Comparing it with the value from an earlier open
+    // tells whether the file was written in between; a database in WAL mode
+    // only updates it at checkpoints.
+    pub fn change_counter(&self) -> u32 {
+        self.change_counter
+    }
+
+    pub fn schema_format(&self) -> u32 {
+        self.schema_format
+    }
+
+    // Why the stored page count can't be trusted, meaning it's stale or the
+    // file was truncated or appended to, if it can't
+    pub fn size_mismatch(&self) -> Option<&str> {
+        self.size_mismatch.as_deref()
+    }
+
+    // Pages are numbered from 1; page 1 includes the 100-byte file header
+    pub fn read_page(&mut self, page_number: u32) -> Result<Vec<u8>> {
+        if page_number == 0 {
+            bail!("invalid page number 0");
+        }
+        if let Some(limit) = self.max_pages {
+            if self.stats.total >= limit {
+                return Err(SqliteError::PageBudgetExceeded { limit }.into());
+            }
+        }
+        let mut page = vec![0; self.page_size];
+        self.reader.seek(SeekFrom::Start((page_number as u64 - 1) * self.page_size as u64))?;
+        self.reader
+            .read_exact(&mut page)
+            .with_context(|| format!("failed to read page {}", page_number))?;
+        self.stats.total += 1;
+        if page_number != 1 {
+            match PageType::from_byte(page[0]) {
+                Some(PageType::InteriorTable | PageType::LeafTable) => self.stats.table += 1,
+                Some(PageType::InteriorIndex | PageType::LeafIndex) => self.stats.index += 1,
+                None => {}
+            }
+        }
+        if self.log.is_none() && self.trace.is_none() {
+            return Ok(page);
+        }
+        let kind = match (self.reason, PageType::from_byte(page[btree_header_offset(page_number)])) {
+            (Some(ReadReason::Overflow), _) => PageKind::Overflow,
+            (Some(ReadReason::Freelist), _) => PageKind::Freelist,
+            _ if self.is_pointer_map_page(page_number) => PageKind::PointerMap,
+            (_, Some(page_type)) => PageKind::Btree(page_type),
+            (_, None) => PageKind::Unknown,
+        };
+        if let Some(log) = self.log {
+            let label = if kind == PageKind::Unknown { "not a b-tree page" } else { kind.label() };
+            log(&format!("read page {} ({})", page_number, label));
+        }
+        let reason = self.reason.unwrap_or(match kind {
+            _ if page_number == 1 => ReadReason::Schema,
+            PageKind::Btree(PageType::InteriorTable) => ReadReason::Descent,
+            PageKind::Btree(PageType::LeafTable) => ReadReason::LeafScan,
+            PageKind::Btree(_) => ReadReason::Index,
+            _ => ReadReason::Other,
+        });
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(PageRead { page: page_number, kind, reason });
+        }
+        Ok(page)
+    }
+
+    fn read_page_for(&mut self, page_number: u32, reason: ReadReason) -> Result<Vec<u8>> {
+        let previous = self.reason.replace(reason);
+        let page = self.read_page(page_number);
+        self.reason = previous;
+        page
+    }
+
+    // Starts recording every page read, dropping any earlier record
+    pub fn set_trace(&mut self, on: bool) {
+        self.trace = on.then(Vec::new);
+    }
+
+    pub fn trace(&self) -> &[PageRead] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    // The trace as a JSON array, one page read per line
+    pub fn trace_json(&self) -> String {
+        let reads: Vec<String> = self
+            .trace()
+            .iter()
+            .map(|read| {
+                format!(
+                    "  {{\"page\":{},\"type\":{},\"reason\":{}}}",
+                    read.page,
+                    json_string(read.kind.label()),
+                    json_string(read.reason.label())
+                )
+            })
+            .collect();
+        if reads.is_empty() {
+            return "[]".to_string();
+        }
+        format!("[\n{}\n]", reads.join(",\n"))
+    }
+
+    // Off until a log function is set
+    pub fn set_log(&mut self, log: Option<Log>) {
+        self.log = log;
+    }
+
+    pub fn log(&self) -> Option<Log> {
+        self.log
+    }
+
+    fn debug(&self, message: impl FnOnce() -> String) {
+        if let Some(log) = self.log {
+            log(&message());
+        }
+    }
+
+    // Reads a page on the way down a b-tree, failing if this walk has
+    // already been through it
+    fn visit_page(&mut self, page_number: u32, visited: &mut HashSet<u32>) -> Result<Vec<u8>> {
+        if !visited.insert(page_number) {
+            return Err(SqliteError::CycleDetected { page: page_number }.into());
+        }
+        self.read_page(page_number)
+    }
+
+    // Number of pages read since the database was opened
+    pub fn pages_read(&self) -> u64 {
+        self.stats.total
+    }
+
+    pub fn page_stats(&self) -> PageStats {
+        self.stats
+    }
+
+    // Caps the total number of pages read, counting those already read;
+    // any read past the budget fails
+    pub fn set_max_pages(&mut self, max_pages: Option<u64>) {
+        self.max_pages = max_pages;
+    }
+
+    // The rows of the schema table, walked like any other table b-tree:
+    // page 1 is a leaf while the schema fits on it, and the interior page
+    // above its leaves once it doesn't
+    pub fn schema(&mut self) -> Result<Records> {
+        let mut records = Records::new();
+        self.read_schema_page(1, &mut records)?;
+        Ok(records)
+    }
+
+    fn read_schema_page(&mut self, page_number: u32, records: &mut Records) -> Result<()> {
+        let page = self.read_page(page_number)?;
+        let header_offset = btree_header_offset(page_number);
+        let cells = cell_pointers(&page, page_number)?;
+        match page[header_offset] {
+            5 => {
+                for offset in cells {
+                    let child = u32::from_be_bytes(page[offset..offset + 4].try_into()?);
+                    self.read_schema_page(child, records)?;
+                }
+                match right_most_pointer(&page, page_number) {
+                    Some(right_most) => self.read_schema_page(right_most, records),
+                    None => Ok(()),
+                }
+            }
+            13 => {
+                for offset in cells {
+                    let (rowid, payload) = self.leaf_payload(&page, offset)?;
+                    records.add_record(Record::from_payload(rowid, &payload, self.text_encoding)?);
+                }
+                Ok(())
+            }
+            other => bail!("schema page {} is not a table b-tree page (type {})", page_number, other),
+        }
+    }
+
+    // Every index created with CREATE INDEX, in schema order. The automatic
+    // indexes behind UNIQUE and PRIMARY KEY constraints have no SQL to take
+    // columns from and are left out, as are indexes on expressions.
+    pub fn indexes(&mut self) -> Result<Vec<IndexInfo>> {
+        let records = self.schema()?;
+        let mut indexes = Vec::new();
+        for record in records.iter().filter(|record| record.s_type == "index" && !record.sql.is_empty()) {
+            match parse_index_info(&record.sql, record.root_page as u32) {
+                Ok(index) => indexes.push(index),
+                Err(err) => self.debug(|| format!("skipping index {}: {}", record.name, err)),
+            }
+        }
+        Ok(indexes)
+    }
+
+    pub fn summary(&mut self) -> Result<DbSummary> {
+        let tables = self
+            .schema()?
+            .iter()
+            .filter(|record| record.s_type == "table")
+            .map(|record| (record.name.clone(), record.root_page as u32))
+            .collect();
+        Ok(DbSummary { page_size: self.page_size, page_count: self.page_count, encoding: self.text_encoding, tables })
+    }
+
+    // Freelist trunk pages each hold the next trunk's page number, a leaf
+    // count and that many leaf page numbers
+    pub fn freelist(&mut self) -> Result<Freelist> {
+        let mut freelist = Freelist::default();
+        let mut trunk = self.freelist_trunk;
+        while trunk != 0 {
+            if freelist.trunks.contains(&trunk) {
+                bail!("freelist loops back to trunk page {}", trunk);
+            }
+            let page = self.read_page_for(trunk, ReadReason::Freelist)?;
+            freelist.trunks.push(trunk);
+            let leaf_count = u32::from_be_bytes(page[4..8].try_into()?) as usize;
+            for i in 0..leaf_count.min((self.usable_size - 8) / 4) {
+                freelist.leaves.push(u32::from_be_bytes(page[8 + 4 * i..12 + 4 * i].try_into()?));
+            }
+            trunk = u32::from_be_bytes(page[0..4].try_into()?);
+        }
+        Ok(freelist)
+    }
+
+    // In auto-vacuum mode, page 2 is a pointer map with a 5-byte entry for
+    // each of the pages after it that it has room for, followed by another
+    // pointer map, and so on
+    pub fn is_pointer_map_page(&self, page_number: u32) -> bool {
+        let span = (self.usable_size / 5 + 1) as u32;
+        self.auto_vacuum && page_number >= 2 && (page_number - 2) % span == 0
+    }
+
+    // Classifies every page in the file. B-tree pages are recognised by their
+    // type byte; overflow pages are found by walking every b-tree in the schema.
+    pub fn page_kinds(&mut self) -> Result<Vec<PageKind>> {
+        let freelist = self.freelist()?;
+        let free: HashSet<u32> = freelist.trunks.iter().chain(freelist.leaves.iter()).copied().collect();
+        let mut overflow = HashSet::new();
+        let mut roots = vec![1];
+        roots.extend(self.schema()?.iter().filter(|rec| rec.root_page > 0).map(|rec| rec.root_page as u32));
+        for root in roots {
+            self.collect_overflow_pages(root, &mut overflow, &mut HashSet::new())?;
+        }
+
+        let mut kinds = Vec::with_capacity(self.page_count as usize);
+        for page_number in 1..=self.page_count {
+            let kind = if free.contains(&page_number) {
+                PageKind::Freelist
+            } else if overflow.contains(&page_number) {
+                PageKind::Overflow
+            } else if self.is_pointer_map_page(page_number) {
+                // Checked before the type byte, which a pointer map entry
+                // can happen to match
+                PageKind::PointerMap
+            } else {
+                let page = self.read_page(page_number)?;
+                match PageType::from_byte(page[btree_header_offset(page_number)]) {
+                    Some(page_type) => PageKind::Btree(page_type),
+                    None => PageKind::Unknown,
+                }
+            };
+            kinds.push(kind);
+        }
+        Ok(kinds)
+    }
+
+    // Space taken by a table's b-tree and the overflow pages of its rows,
+    // as whole pages. The table's indexes are left out; see index_size_bytes.
+    pub fn table_size_bytes(&mut self, table: &str) -> Result<u64> {
+        let (root, _) = self.table_root(table)?;
+        self.btree_size_bytes(root)
+    }
+
+    // Likewise for an index, including the automatic ones behind UNIQUE and
+    // PRIMARY KEY constraints
+    pub fn index_size_bytes(&mut self, index: &str) -> Result<u64> {
+        let records = self.schema()?;
+        let root = match records.iter().find(|rec| rec.s_type == "index" && rec.name.eq_ignore_ascii_case(index)) {
+            Some(record) => record.root_page as u32,
+            None => bail!("no such index: {}", index),
+        };
+        self.btree_size_bytes(root)
+    }
+
+    fn btree_size_bytes(&mut self, root: u32) -> Result<u64> {
+        let mut overflow = HashSet::new();
+        let mut visited = HashSet::new();
+        self.collect_overflow_pages(root, &mut overflow, &mut visited)?;
+        Ok((visited.len() + overflow.len()) as u64 * self.page_size as u64)
+    }
+
+    fn collect_overflow_pages(
+        &mut self,
+        page_number: u32,
+        overflow: &mut HashSet<u32>,
+        visited: &mut HashSet<u32>,
+    ) -> Result<()> {
+        let page = self.visit_page(page_number, visited)?;
+        let header_offset = btree_header_offset(page_number);
+        let page_type = match PageType::from_byte(page[header_offset]) {
+            Some(page_type) => page_type,
+            None => bail!("page {} is not a b-tree page", page_number),
+        };
+        for offset in cell_pointers(&page, page_number)? {
+            let payload_start = match page_type {
+                PageType::InteriorTable => {
+                    let child = u32::from_be_bytes(page[offset..offset + 4].try_into()?);
+                    self.collect_overflow_pages(child, overflow, visited)?;
+                    continue;
+                }
+                PageType::InteriorIndex => {
+                    let child = u32::from_be_bytes(page[offset..offset + 4].try_into()?);
+                    self.collect_overflow_pages(child, overflow, visited)?;
+                    offset + 4
+                }
+                _ => offset,
+            };
+            let (payload_size, mut len) = decode_varint(&page[payload_start..])?;
+            if page_type == PageType::LeafTable {
+                len += decode_varint(&page[payload_start + len..])?.1;
+            }
+            let payload_size = payload_size as usize;
+            let (local, mut next) = self.local_payload(&page, page_type, payload_start + len, payload_size)?;
+            if local.len() < payload_size {
+                let mut remaining = payload_size - local.len();
+                while next != 0 && remaining > 0 && overflow.insert(next) {
+                    let overflow_page = self.read_page_for(next, ReadReason::Overflow)?;
+                    remaining = remaining.saturating_sub(self.usable_size - 4);
+                    next = u32::from_be_bytes(overflow_page[0..4].try_into()?);
+                }
+            }
+        }
+        if let Some(right_most) = right_most_pointer(&page, page_number) {
+            self.collect_overflow_pages(right_most, overflow, visited)?;
+        }
+        Ok(())
+    }
+
+    // The part of a cell's payload stored on the page, and the first overflow
+    // page holding the rest (0 when it all fits). A payload running past the
+    // end of the page means the cell is corrupt.
+    fn local_payload<'p>(
+        &self,
+        page: &'p [u8],
+        page_type: PageType,
+        start: usize,
+        payload_size: usize,
+    ) -> Result<(&'p [u8], u32)> {
+        let local = self.local_payload_size(page_type, payload_size);
+        let end = start + local + if local < payload_size { 4 } else { 0 };
+        if end > page.len() {
+            bail!("cell payload at offset {} runs {} bytes past the end of the page", start, end - page.len());
+        }
+        let next = if local < payload_size { u32::from_be_bytes(page[start + local..end].try_into()?) } else { 0 };
+        Ok((&page[start..start + local], next))
+    }
+
+    // How much of a cell's payload is stored on the b-tree page itself, the
+    // rest spilling onto overflow pages
+    fn local_payload_size(&self, page_type: PageType, payload_size: usize) -> usize {
+        let usable = self.usable_size;
+        let max_local = match page_type {
+            PageType::LeafTable => usable - 35,
+            _ => (usable - 12) * 64 / 255 - 23,
+        };
+        if payload_size <= max_local {
+            return payload_size;
+        }
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let local = min_local + (payload_size - min_local) % (usable - 4);
+        if local > max_local {
+            min_local
+        } else {
+            local
+        }
+    }
+
+    // Runs a SELECT whose `?` placeholders are bound to `params` in order,
+    // so values never have to be spliced into the SQL text
+    pub fn query(&mut self, sql: &str, params: &[Value]) -> Result<ResultSet> {
+        let mut select = parse_select(sql)?;
+        select.bind(params)?;
+        let records = self.schema()?;
+        execute_select(self, &records, &select)
+    }
+
+    // Iterates over the rows of the named table in rowid order. They can be
+    // read by column name.
+    pub fn rows(&mut self, table: &str) -> Result<Rows<'_>> {
+        let (root_page, schema) = self.table_root(table)?;
+        let mut rows = Rows { db: self, stack: Vec::new(), visited: HashSet::new(), table: Some(schema) };
+        rows.descend(root_page)?;
+        Ok(rows)
+    }
+
+    fn table_root(&mut self, table: &str) -> Result<(u32, Rc<TableSchema>)> {
+        // The schema table is rooted at page 1, behind the file header
+        if is_schema_table(table) {
+            return Ok((1, Rc::new(schema_table(table))));
+        }
+        let records = self.schema()?;
+        match records.find(table) {
+            Some(record) => Ok((record.root_page as u32, Rc::new(parse_create_table(&record.sql)?))),
+            None => Err(records.not_found(table).into()),
+        }
+    }
+
+    // The row of a table with the given rowid, read with no more pages than
+    // the depth of its b-tree
+    pub fn get_row(&mut self, table: &str, rowid: i64) -> Result<Option<Row>> {
+        let (root_page, schema) = self.table_root(table)?;
+        let row = self.find_row(root_page, rowid)?;
+        Ok(row.map(|row| Row { table: Some(schema), ..row }))
+    }
+
+    // Streams one blob a page at a time instead of decoding the whole row, so
+    // the first bytes of a large blob can be read without its full overflow
+    // chain. None if the row is missing or the value is NULL.
+    pub fn blob_reader(&mut self, table: &str, rowid: i64, column: &str) -> Result<Option<BlobReader<'_>>> {
+        let (root_page, schema) = self.table_root(table)?;
+        let index = match schema.column_index(column) {
+            Some(index) if schema.rowid_alias == Some(index) => {
+                return Err(mismatch(column, &Value::Integer(rowid), "a blob"));
+            }
+            Some(index) => index,
+            None => bail!("no such column: {}", column),
+        };
+        let (page, offset) = match self.find_cell(root_page, rowid)? {
+            Some(cell) => cell,
+            None => return Ok(None),
+        };
+        let (payload_size, payload_size_len) = decode_varint(&page[offset..])?;
+        let (_, rowid_len) = decode_varint(&page[offset + payload_size_len..])?;
+        let start = offset + payload_size_len + rowid_len;
+        let payload_size = payload_size as usize;
+        let (local, next) = self.local_payload(&page, PageType::LeafTable, start, payload_size)?;
+        let (chunk, left) = (local.to_vec(), payload_size - local.len());
+        let encoding = self.text_encoding;
+        let mut payload = Payload { db: self, chunk, pos: 0, next, left };
+
+        // Walk the record header up to the column, totalling the sizes of the
+        // values stored before it
+        let header_size = payload.varint()?;
+        let mut header_read = varint_len(header_size);
+        let mut skip = 0;
+        let mut serial = None;
+        for i in 0..=index {
+            if header_read >= header_size as usize {
+                break;
+            }
+            let value_serial = payload.varint()?;
+            header_read += varint_len(value_serial);
+            if i < index {
+                skip += serial_size(value_serial);
+            } else {
+                serial = Some(value_serial);
+            }
+        }
+        let serial = match serial {
+            // Records written before an ALTER TABLE stop short of later columns
+            None => match &schema.columns[index].default {
+                Value::Null => return Ok(None),
+                other => return Err(mismatch(column, other, "a blob")),
+            },
+            Some(serial) => serial,
+        };
+        payload.skip(header_size as usize - header_read + skip)?;
+        let size = serial_size(serial);
+        match serial {
+            0 => Ok(None),
+            n if n >= 12 && n % 2 == 0 => Ok(Some(BlobReader { payload, remaining: size })),
+            _ => {
+                let mut bytes = vec![0; size];
+                payload.read_exact(&mut bytes)?;
+                Err(mismatch(column, &decode_value(serial, &bytes, encoding)?, "a blob"))
+            }
+        }
+    }
+
+    // Writes the table as CSV, a header line of column names and then one
+    // line per row as the scan reaches it, flushing every CSV_FLUSH_ROWS
+    // rows so memory use stays flat however big the table. Returns the
+    // number of rows written.
+    pub fn export_csv(&mut self, table: &str, writer: impl Write) -> Result<u64> {
+        let mut out = BufWriter::new(writer);
+        let (root_page, schema) = self.table_root(table)?;
+        let names: Vec<String> =
+            schema.columns.iter().map(|column| csv_field(&Value::Text(column.name.clone()))).collect();
+        writeln!(out, "{}", names.join(","))?;
+        let mut count = 0;
+        for row in self.table_rows(root_page)? {
+            let row = row?;
+            let mut fields = Vec::with_capacity(schema.columns.len());
+            for (index, column) in schema.columns.iter().enumerate() {
+                let value = match row.values.get(index) {
+                    _ if schema.rowid_alias == Some(index) => Value::Integer(row.rowid),
+                    Some(Value::Integer(i)) if column.has_real_affinity() => Value::Real(*i as f64),
+                    Some(value) => value.clone(),
+                    None => column.default.clone(),
+                };
+                fields.push(csv_field(&value));
+            }
+            writeln!(out, "{}", fields.join(","))?;
+            count += 1;
+            if count % CSV_FLUSH_ROWS == 0 {
+                out.flush()?;
+            }
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    // Like rows, but starting at the first row whose rowid is at least
+    // `start_rowid`, so a batch job can pick up where it left off
+    pub fn rows_from(&mut self, table: &str, start_rowid: i64) -> Result<Rows<'_>> {
+        let (root_page, schema) = self.table_root(table)?;
+        let mut rows = Rows { db: self, stack: Vec::new(), visited: HashSet::new(), table: Some(schema) };
+        rows.seek(root_page, start_rowid)?;
+        Ok(rows)
+    }
+
+    pub fn table_rows(&mut self, root_page: u32) -> Result<Rows<'_>> {
+        let mut rows = Rows { db: self, stack: Vec::new(), visited: HashSet::new(), table: None };
+        rows.descend(root_page)?;
+        Ok(rows)
+    }
+
+    // Number of rows in the table b-tree at `root_page`, read off the leaf
+    // cell counts without decoding any records
+    pub fn count_rows(&mut self, root_page: u32) -> Result<u64> {
+        let mut count = 0;
+        let mut pending = vec![root_page];
+        let mut visited = HashSet::new();
+        while let Some(page_number) = pending.pop() {
+            let page = self.visit_page(page_number, &mut visited)?;
+            let header_offset = btree_header_offset(page_number);
+            let cells = cell_pointers(&page, page_number)?;
+            match PageType::from_byte(page[header_offset]) {
+                Some(PageType::LeafTable) => count += cells.len() as u64,
+                Some(PageType::InteriorTable) => {
+                    for offset in cells {
+                        pending.push(u32::from_be_bytes(page[offset..offset + 4].try_into()?));
+                    }
+                    pending.extend(right_most_pointer(&page, page_number));
+                }
+                _ => bail!("page {} is not a table b-tree page (type {})", page_number, page[header_offset]),
+            }
+        }
+        Ok(count)
+    }
+
+    // Row counts of the user tables, by name; sqlite_* internal tables are
+    // left out
+    pub fn table_counts(&mut self) -> Result<Vec<(String, u64)>> {
+        let records = self.schema()?;
+        let mut counts = Vec::new();
+        for name in records.table_names() {
+            if name.to_ascii_lowercase().starts_with("sqlite_") {
+                continue;
+            }
+            let root_page = match records.find(&name) {
+                Some(record) if record.root_page > 0 => record.root_page as u32,
+                _ => continue,
+            };
+            counts.push((name, self.count_rows(root_page)?));
+        }
+        Ok(counts)
+    }
+
+    // Foreign keys that refer to a table or column that doesn't exist, one
+    // message each. Only the schema is looked at, not the rows. Tables whose
+    // CREATE statement can't be parsed are skipped.
+    pub fn foreign_key_problems(&mut self) -> Result<Vec<String>> {
+        let records = self.schema()?;
+        let mut problems = Vec::new();
+        for record in records.iter().filter(|record| record.s_type == "table") {
+            let (table, foreign_keys) = match (parse_create_table(&record.sql), parse_foreign_keys(&record.sql)) {
+                (Ok(table), Ok(foreign_keys)) => (table, foreign_keys),
+                _ => continue,
+            };
+            for foreign_key in foreign_keys.iter() {
+                let label = format!("{}({})", table.name, foreign_key.columns.join(", "));
+                let mut problem = |message: String| problems.push(format!("{}: {}", label, message));
+                for column in foreign_key.columns.iter().filter(|column| table.column_index(column).is_none()) {
+                    problem(format!("no such column {}", column));
+                }
+                let parent = match records.find(&foreign_key.parent).map(|parent| parse_create_table(&parent.sql)) {
+                    Some(Ok(parent)) => parent,
+                    Some(Err(_)) => continue,
+                    None => {
+                        problem(format!("references missing table {}", foreign_key.parent));
+                        continue;
+                    }
+                };
+                let mut parent_columns = foreign_key.parent_columns.clone();
+                if parent_columns.is_empty() {
+                    // The rowid can't be referenced, so the key must be declared
+                    parent_columns = parent.columns.iter().filter(|c| c.primary_key).map(|c| c.name.clone()).collect();
+                    if parent_columns.is_empty() {
+                        problem(format!("references the primary key of {}, which has none", parent.name));
+                        continue;
+                    }
+                }
+                for column in parent_columns.iter().filter(|column| parent.column_index(column).is_none()) {
+                    problem(format!("references missing column {}.{}", parent.name, column));
+                }
+                if parent_columns.len() != foreign_key.columns.len() {
+                    problem(format!(
+                        "names {} columns but references {} in {}",
+                        foreign_key.columns.len(),
+                        parent_columns.len(),
+                        parent.name
+                    ));
+                }
+            }
+        }
+        Ok(problems)
+    }
+
+    // Every row of every user table, tagged with its table's name. Tables
+    // come in the order table_counts lists them, and each is read only as
+    // the iterator reaches it.
+    pub fn all_rows(&mut self) -> Result<AllRows<'_>> {
+        let records = self.schema()?;
+        let mut tables = Vec::new();
+        for name in records.table_names().into_iter().rev() {
+            if name.to_ascii_lowercase().starts_with("sqlite_") {
+                continue;
+            }
+            match records.find(&name) {
+                Some(record) if record.root_page > 0 => {
+                    let schema = Rc::new(parse_create_table(&record.sql)?);
+                    tables.push((name, record.root_page as u32, schema));
+                }
+                _ => continue,
+            }
+        }
+        let rows = Rows { db: self, stack: Vec::new(), visited: HashSet::new(), table: None };
+        Ok(AllRows { rows, tables, current: String::new() })
+    }
+
+    pub fn total_rows(&mut self) -> Result<u64> {
+        Ok(self.table_counts()?.iter().map(|(_, count)| count).sum())
+    }
+
+    // Walks the table b-tree rooted at `root_page` in rowid order, calling `f`
+    // with the rowid and decoded columns of every row.
+    pub fn scan_table(&mut self, root_page: u32, f: &mut dyn FnMut(i64, Vec<Value>) -> Result<()>) -> Result<()> {
+        for row in self.table_rows(root_page)? {
+            let row = row?;
+            f(row.rowid, row.values)?;
+        }
+        Ok(())
+    }
+
+    // The values of the named columns in every row of a table, in rowid
+    // order, as one vector per column in the order named. Only those columns
+    // are decoded; the values before them in each record are skipped over.
+    pub fn scan_columns(&mut self, table: &str, columns: &[&str]) -> Result<Vec<Vec<Value>>> {
+        let (root_page, schema) = self.table_root(table)?;
+        // None stands for the rowid, which isn't stored in the record
+        let mut positions = Vec::with_capacity(columns.len());
+        for column in columns.iter() {
+            positions.push(match schema.column_index(column) {
+                Some(index) if schema.rowid_alias == Some(index) => None,
+                Some(index) => Some(index),
+                None if column.eq_ignore_ascii_case("rowid") => None,
+                None => bail!("no such column: {}", column),
+            });
+        }
+        let stored: Vec<usize> = positions.iter().flatten().copied().collect();
+        let mut vectors = vec![Vec::new(); columns.len()];
+        let mut path = Vec::new();
+        let mut leaf = Some(self.first_leaf(root_page, &mut path)?);
+        while let Some(page_number) = leaf {
+            let page = self.read_page(page_number)?;
+            let page_type = page[btree_header_offset(page_number)];
+            if page_type != 13 {
+                bail!("page {} is not a table b-tree page (type {})", page_number, page_type);
+            }
+            for offset in cell_pointers(&page, page_number)? {
+                let (rowid, payload) = self.leaf_payload(&page, offset)?;
+                let mut values = decode_columns(&payload, &stored, self.text_encoding)?.into_iter();
+                for (vector, position) in vectors.iter_mut().zip(positions.iter()) {
+                    vector.push(match position {
+                        None => Value::Integer(rowid),
+                        Some(index) => {
+                            values.next().flatten().unwrap_or_else(|| schema.columns[*index].default.clone())
+                        }
+                    });
+                }
+            }
+            leaf = self.next_leaf(&mut path)?;
+        }
+        Ok(vectors)
+    }
+
+    fn read_leaf_cell(&mut self, page: &[u8], offset: usize) -> Result<Row> {
+        let (rowid, payload) = self.leaf_payload(page, offset)?;
+        Ok(Row { rowid, values: decode_record_with_encoding(&payload, self.text_encoding)?, table: None })
+    }
+
+    // Leaf table cell: payload size, rowid, payload. The payload is read in
+    // full, through any overflow pages.
+    fn leaf_payload(&mut self, page: &[u8], offset: usize) -> Result<(i64, Vec<u8>)> {
+        let (payload_size, payload_size_len) = decode_varint(&page[offset..])?;
+        let (rowid, rowid_len) = decode_varint(&page[offset + payload_size_len..])?;
+        let payload_start = offset + payload_size_len + rowid_len;
+        let payload = self.read_payload(page, PageType::LeafTable, payload_start, payload_size as usize)?;
+        Ok((rowid as i64, payload))
+    }
+
+    // The undecoded record of the row with the given rowid: its header of
+    // serial types, then the values. None if there's no such row.
+    pub fn record_payload(&mut self, table: &str, rowid: i64) -> Result<Option<Vec<u8>>> {
+        let (root_page, _) = self.table_root(table)?;
+        match self.find_cell(root_page, rowid)? {
+            Some((page, offset)) => Ok(Some(self.leaf_payload(&page, offset)?.1)),
+            None => Ok(None),
+        }
+    }
+
+    // The cells of a b-tree page, in cell pointer order
+    pub fn cells_of_page(&mut self, page_number: u32) -> Result<Vec<Cell>> {
+        let page = self.read_page(page_number)?;
+        let page_type = match PageType::from_byte(page[btree_header_offset(page_number)]) {
+            Some(page_type) => page_type,
+            None => bail!("page {} is not a b-tree page", page_number),
+        };
+        cell_pointers(&page, page_number)?.into_iter().map(|offset| Cell::parse(&page, page_type, offset)).collect()
+    }
+
+    // Looks a single row up by rowid, descending the table b-tree by key
+    // rather than scanning it
+    pub fn find_row(&mut self, root_page: u32, rowid: i64) -> Result<Option<Row>> {
+        match self.find_cell(root_page, rowid)? {
+            Some((page, offset)) => self.read_leaf_cell(&page, offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // The rows with the given rowids, such as an index search found, in
+    // rowid order. Each is its own descent; rowids with no row are skipped
+    // and repeats give one row.
+    pub fn fetch_rows_by_rowids(&mut self, root_page: u32, rowids: &[i64]) -> Result<Vec<Row>> {
+        let mut rowids = rowids.to_vec();
+        rowids.sort_unstable();
+        rowids.dedup();
+        let mut rows = Vec::with_capacity(rowids.len());
+        for rowid in rowids {
+            if let Some(row) = self.find_row(root_page, rowid)? {
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+
+    // The leaf page holding a rowid's cell, and the cell's offset within it
+    fn find_cell(&mut self, root_page: u32, rowid: i64) -> Result<Option<(Vec<u8>, usize)>> {
+        // The leaf is reached by key, so it counts as part of the descent
+        let previous = self.reason.replace(ReadReason::Descent);
+        let cell = self.descend_to_cell(root_page, rowid);
+        self.reason = previous;
+        cell
+    }
+
+    fn descend_to_cell(&mut self, root_page: u32, rowid: i64) -> Result<Option<(Vec<u8>, usize)>> {
+        let mut page_number = root_page;
+        let mut visited = HashSet::new();
+        loop {
+            let page = self.visit_page(page_number, &mut visited)?;
+            let header_offset = btree_header_offset(page_number);
+            let cells = cell_pointers(&page, page_number)?;
+            match PageType::from_byte(page[header_offset]) {
+                Some(PageType::LeafTable) => {
+                    for offset in cells {
+                        let (_, payload_size_len) = decode_varint(&page[offset..])?;
+                        if decode_varint(&page[offset + payload_size_len..])?.0 as i64 == rowid {
+                            return Ok(Some((page, offset)));
+                        }
+                    }
+                    return Ok(None);
+                }
+                Some(PageType::InteriorTable) => {
+                    // Each left child holds rowids up to and including its key
+                    let mut child = right_most_pointer(&page, page_number).unwrap_or(0);
+                    for offset in cells {
+                        if rowid <= decode_varint(&page[offset + 4..])?.0 as i64 {
+                            child = u32::from_be_bytes(page[offset..offset + 4].try_into()?);
+                            break;
+                        }
+                    }
+                    self.debug(|| format!("page {}: rowid {} is under child page {}", page_number, rowid, child));
+                    page_number = child;
+                }
+                _ => bail!("page {} is not a table b-tree page (type {})", page_number, page[header_offset]),
+            }
+        }
+    }
+
+    // Returns the rowids of the index entries whose leading key columns equal
+    // `key`, in index order. Entries compare column by column, so a key of
+    // (a, b) narrows the search to a single run of the index.
+    pub fn search_index(&mut self, root_page: u32, key: &[Value]) -> Result<Vec<i64>> {
+        self.search_index_range(root_page, key, &KeyRange::default())
+    }
+
+    // Like search_index, but only entries whose column after the key falls
+    // within `range`. The tree is descended to the first such entry, then
+    // walked in order until an entry past the range.
+    pub fn search_index_range(&mut self, root_page: u32, key: &[Value], range: &KeyRange) -> Result<Vec<i64>> {
+        let mut rowids = Vec::new();
+        for entry in self.index_entries_in_range(root_page, key, range)? {
+            match entry.last() {
+                Some(Value::Integer(rowid)) => rowids.push(*rowid),
+                _ => bail!("index entry in b-tree {} doesn't end in a rowid", root_page),
+            }
+        }
+        Ok(rowids)
+    }
+
+    // Like search_index, but returns whole entries: the key columns followed
+    // by the rowid. An empty key matches every entry.
+    pub fn index_entries(&mut self, root_page: u32, key: &[Value]) -> Result<Vec<Vec<Value>>> {
+        self.index_entries_in_range(root_page, key, &KeyRange::default())
+    }
+
+    pub fn index_entries_in_range(
+        &mut self,
+        root_page: u32,
+        key: &[Value],
+        range: &KeyRange,
+    ) -> Result<Vec<Vec<Value>>> {
+        let mut entries = Vec::new();
+        self.search_index_page(root_page, key, range, &mut entries, &mut HashSet::new())?;
+        Ok(entries)
+    }
+
+    // Descends the left-most edge of a b-tree to its first leaf, recording
+    // in `path` each interior page passed with the child taken: a cell index,
+    // or the cell count for the right-most child
+    pub fn first_leaf(&mut self, root_page: u32, path: &mut Vec<(u32, usize)>) -> Result<u32> {
+        path.clear();
+        self.leftmost_leaf(root_page, path)
+    }
+
+    // The next leaf in key order after the one `path` leads to, or None after
+    // the last: ascends to the nearest page with a child left to take, then
+    // down that child's left-most edge. In an index b-tree, interior cells
+    // hold entries too; the one sorting between the two leaves is the cell
+    // before the child taken at the deepest page on the new path not at its
+    // first child.
+    pub fn next_leaf(&mut self, path: &mut Vec<(u32, usize)>) -> Result<Option<u32>> {
+        while let Some((page_number, child)) = path.pop() {
+            let page = self.read_page(page_number)?;
+            let cells = cell_pointers(&page, page_number)?;
+            if child >= cells.len() {
+                continue;
+            }
+            let next = match cells.get(child + 1) {
+                Some(&offset) => u32::from_be_bytes(page[offset..offset + 4].try_into()?),
+                None => right_most_pointer(&page, page_number).unwrap_or(0),
+            };
+            path.push((page_number, child + 1));
+            self.debug(|| format!("page {}: moving on to child page {}", page_number, next));
+            return self.leftmost_leaf(next, path).map(Some);
+        }
+        Ok(None)
+    }
+
+    fn leftmost_leaf(&mut self, mut page_number: u32, path: &mut Vec<(u32, usize)>) -> Result<u32> {
+        loop {
+            // A page already on the path means the tree loops back on itself
+            if path.iter().any(|(ancestor, _)| *ancestor == page_number) {
+                return Err(SqliteError::CycleDetected { page: page_number }.into());
+            }
+            let page = self.read_page(page_number)?;
+            let header_offset = btree_header_offset(page_number);
+            match PageType::from_byte(page[header_offset]) {
+                Some(PageType::LeafTable | PageType::LeafIndex) => return Ok(page_number),
+                Some(PageType::InteriorTable | PageType::InteriorIndex) => {
+                    let child = match cell_pointers(&page, page_number)?.first() {
+                        Some(&offset) => u32::from_be_bytes(page[offset..offset + 4].try_into()?),
+                        None => right_most_pointer(&page, page_number).unwrap_or(0),
+                    };
+                    path.push((page_number, 0));
+                    page_number = child;
+                }
+                None => bail!("page {} is not a b-tree page (type {})", page_number, page[header_offset]),
+            }
+        }
+    }
+
+    // The entries held by an index b-tree page's cells, in cell order
+    pub fn index_page_entries(&mut self, page_number: u32) -> Result<Vec<Vec<Value>>> {
+        let page = self.read_page(page_number)?;
+        let header_offset = btree_header_offset(page_number);
+        let page_type = match PageType::from_byte(page[header_offset]) {
+            Some(page_type @ (PageType::LeafIndex | PageType::InteriorIndex)) => page_type,
+            _ => bail!("page {} is not an index b-tree page (type {})", page_number, page[header_offset]),
+        };
+        let mut entries = Vec::new();
+        for offset in cell_pointers(&page, page_number)? {
+            let payload_start = if page_type == PageType::InteriorIndex { offset + 4 } else { offset };
+            let (payload_size, len) = decode_varint(&page[payload_start..])?;
+            let payload = self.read_payload(&page, page_type, payload_start + len, payload_size as usize)?;
+            entries.push(decode_record_with_encoding(&payload, self.text_encoding)?);
+        }
+        Ok(entries)
+    }
+
+    // Returns false once an entry past the key has been seen, so callers
+    // can stop without visiting the rest of the tree
+    fn search_index_page(
+        &mut self,
+        page_number: u32,
+        key: &[Value],
+        range: &KeyRange,
+        entries: &mut Vec<Vec<Value>>,
+        visited: &mut HashSet<u32>,
+    ) -> Result<bool> {
+        let page = self.visit_page(page_number, visited)?;
+        let header_offset = btree_header_offset(page_number);
+        let page_type = match PageType::from_byte(page[header_offset]) {
+            Some(page_type @ (PageType::LeafIndex | PageType::InteriorIndex)) => page_type,
+            _ => bail!("page {} is not an index b-tree page (type {})", page_number, page[header_offset]),
+        };
+        for offset in cell_pointers(&page, page_number)? {
+            // Interior index cells: 4-byte left child, then an ordinary entry
+            let payload_start = if page_type == PageType::InteriorIndex { offset + 4 } else { offset };
+            let (payload_size, len) = decode_varint(&page[payload_start..])?;
+            let payload = self.read_payload(&page, page_type, payload_start + len, payload_size as usize)?;
+            let entry = decode_record_with_encoding(&payload, self.text_encoding)?;
+            let ordering = compare_key(&entry, key).then_with(|| match entry.get(key.len()) {
+                Some(value) => range.position(value),
+                None => Ordering::Equal,
+            });
+            if ordering != Ordering::Less && page_type == PageType::InteriorIndex {
+                let child = u32::from_be_bytes(page[offset..offset + 4].try_into()?);
+                self.debug(|| format!("page {}: key may be under child page {}", page_number, child));
+                if !self.search_index_page(child, key, range, entries, visited)? {
+                    return Ok(false);
+                }
+            }
+            match ordering {
+                Ordering::Less => {}
+                Ordering::Equal => entries.push(entry),
+                Ordering::Greater => {
+                    self.debug(|| format!("page {}: past the key, stopping", page_number));
+                    return Ok(false);
+                }
+            }
+        }
+        if let Some(right_most) = right_most_pointer(&page, page_number) {
+            self.debug(|| format!("page {}: key may be under right-most child page {}", page_number, right_most));
+            return self.search_index_page(right_most, key, range, entries, visited);
+        }
+        Ok(true)
+    }
+
+    // Collects a cell's payload, following the overflow chain when it doesn't
+    // fit on the page.
+    fn read_payload(&mut self, page: &[u8], page_type: PageType, start: usize, payload_size: usize) -> Result<Vec<u8>> {
+        let usable = self.usable_size;
+        let (local, mut next) = self.local_payload(page, page_type, start, payload_size)?;
+        if local.len() == payload_size {
+            return Ok(local.to_vec());
+        }
+
+        // The size comes off the disk, so check it against what the file
+        // could hold before allocating for it
+        let limit = self.reader.seek(SeekFrom::End(0))?;
+        if payload_size as u64 > limit {
+            bail!("cell claims a {}-byte payload but the database holds only {} bytes", payload_size, limit);
+        }
+        let mut payload = Vec::with_capacity(payload_size);
+        payload.extend_from_slice(local);
+        while payload.len() < payload_size {
+            if next == 0 {
+                bail!("overflow chain ended early");
+            }
+            let overflow = self.read_page_for(next, ReadReason::Overflow)?;
+            let take = (payload_size - payload.len()).min(usable - 4);
+            payload.extend_from_slice(&overflow[4..4 + take]);
+            next = u32::from_be_bytes(overflow[0..4].try_into()?);
+        }
+        Ok(payload)
+    }
+}
+
+// Page 1 holds the schema table's b-tree, whose header follows the file
+// header. A type byte that isn't a table page's, or a cell content area
+// starting past the end of the page, means the page isn't where or as big
+// as the header says. Files too short to hold the b-tree header are left
+// to fail on first read.
+fn check_first_page(reader: &mut impl ReadSeek, page_size: usize) -> Result<()> {
+    let mut btree_header = [0; 8];
+    reader.seek(SeekFrom::Start(100))?;
+    if reader.read_exact(&mut btree_header).is_err() {
+        return Ok(());
+    }
+    let page_type = PageType::from_byte(btree_header[0]);
+    let content_start = match u16::from_be_bytes([btree_header[5], btree_header[6]]) {
+        0 => 65536,
+        n => n as usize,
+    };
+    let is_table = matches!(page_type, Some(PageType::LeafTable | PageType::InteriorTable));
+    if !is_table || content_start > page_size {
+        bail!(
+            "page 1 doesn't look like a {}-byte table b-tree page (type byte {:#04x}, cell content at {}); \
+             the header's page size may be wrong, try --page-size <bytes>",
+            page_size,
+            btree_header[0],
+            content_start
+        );
+    }
+    Ok(())
+}
+
+// Encrypted databases are page-aligned, with every byte (the header
+// included) indistinguishable from random. Compressed files are random-looking
+// too, so gzip is ruled out by its magic; anything else that dense with an
+// odd size is more likely some other file. Returns the file size on a match.
+fn looks_encrypted(reader: &mut impl ReadSeek) -> Result<Option<u64>> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    if size < 1024 || size % 512 != 0 {
+        return Ok(None);
+    }
+    let mut sample = vec![0; 1024];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut sample)?;
+    if sample[..2] == [0x1f, 0x8b] {
+        return Ok(None);
+    }
+    let mut counts = [0usize; 256];
+    for &byte in sample.iter() {
+        counts[byte as usize] += 1;
+    }
+    // Shannon entropy in bits per byte; random data comes out near 7.8 on a
+    // 1024-byte sample, and text or structured binary well under 6
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / sample.len() as f64;
+            -p * p.log2()
+        })
+        .sum();
+    Ok((entropy > 7.5).then_some(size))
+}
+
+// Compares an index entry's leading columns against a key prefix
+fn compare_key(entry: &[Value], key: &[Value]) -> Ordering {
+    for (value, wanted) in entry.iter().zip(key.iter()) {
+        let ordering = compare_values(value, wanted);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub rowid: i64,
+    pub values: Vec<Value>,
+    // Schema of the table the row was read from by name, for the getters
+    pub table: Option<Rc<TableSchema>>,
+}
+
+impl Row {
+    // A column's value by name, which for the INTEGER PRIMARY KEY column is
+    // the rowid and for columns added by ALTER TABLE may be the default
+    pub fn get(&self, column: &str) -> Result<Value> {
+        let table = match &self.table {
+            Some(table) => table,
+            None => bail!("row {} was not read by table name, so its columns have no names", self.rowid),
+        };
+        match table.column_index(column) {
+            Some(index) if table.rowid_alias == Some(index) => Ok(Value::Integer(self.rowid)),
+            Some(index) => Ok(self.values.get(index).cloned().unwrap_or_else(|| table.columns[index].default.clone())),
+            None if column.eq_ignore_ascii_case("rowid") => Ok(Value::Integer(self.rowid)),
+            None => bail!("no such column: {}", column),
+        }
+    }
+
+    // The typed getters return None for NULL and an error for a value of
+    // another type
+
+    pub fn get_i64(&self, column: &str) -> Result<Option<i64>> {
+        match self.get(column)? {
+            Value::Null => Ok(None),
+            Value::Integer(i) => Ok(Some(i)),
+            other => Err(mismatch(column, &other, "an integer")),
+        }
+    }
+
+    // Integers count too, since REAL columns store whole numbers that way
+    pub fn get_f64(&self, column: &str) -> Result<Option<f64>> {
+        match self.get(column)? {
+            Value::Null => Ok(None),
+            Value::Real(r) => Ok(Some(r)),
+            Value::Integer(i) => Ok(Some(i as f64)),
+            other => Err(mismatch(column, &other, "a real")),
+        }
+    }
+
+    pub fn get_text(&self, column: &str) -> Result<Option<String>> {
+        match self.get(column)? {
+            Value::Null => Ok(None),
+            Value::Text(text) => Ok(Some(text)),
+            other => Err(mismatch(column, &other, "text")),
+        }
+    }
+
+    // A streaming reader over a blob column; see Database::blob_reader
+    pub fn blob_reader<'a>(&self, db: &'a mut Database, column: &str) -> Result<Option<BlobReader<'a>>> {
+        match &self.table {
+            Some(table) => db.blob_reader(&table.name, self.rowid, column),
+            None => bail!("row {} was not read by table name, so its columns have no names", self.rowid),
+        }
+    }
+
+    pub fn get_blob(&self, column: &str) -> Result<Option<Vec<u8>>> {
+        match self.get(column)? {
+            Value::Null => Ok(None),
+            Value::Blob(bytes) => Ok(Some(bytes)),
+            other => Err(mismatch(column, &other, "a blob")),
+        }
+    }
+}
+
+fn mismatch(column: &str, value: &Value, wanted: &str) -> anyhow::Error {
+    anyhow::anyhow!("column {} holds {} {}, not {}", column, value.type_name(), render(value), wanted)
+}
+
+// A page on the path from the root to the current leaf, with the index of
+// the next cell (or, past the last cell, the right-most child) to visit
+struct Frame {
+    page_number: u32,
+    page: Vec<u8>,
+    cells: Vec<usize>,
+    next: usize,
+}
+
+pub struct Rows<'a> {
+    db: &'a mut Database,
+    stack: Vec<Frame>,
+    // Every page descended into so far, to catch corrupt trees that loop
+    visited: HashSet<u32>,
+    table: Option<Rc<TableSchema>>,
+}
+
+impl Rows<'_> {
+    fn descend(&mut self, page_number: u32) -> Result<()> {
+        let page = self.db.visit_page(page_number, &mut self.visited)?;
+        let page_type = page[btree_header_offset(page_number)];
+        if page_type != 5 && page_type != 13 {
+            bail!("page {} is not a table b-tree page (type {})", page_number, page_type);
+        }
+        let cells = cell_pointers(&page, page_number)?;
+        self.stack.push(Frame { page_number, page, cells, next: 0 });
+        Ok(())
+    }
+
+    // Descends by key to the first cell with a rowid of at least `start`,
+    // leaving each frame on the path positioned just past the child taken so
+    // advance carries on from there
+    fn seek(&mut self, root_page: u32, start: i64) -> Result<()> {
+        let mut page_number = root_page;
+        loop {
+            self.descend(page_number)?;
+            let frame = match self.stack.last_mut() {
+                Some(frame) => frame,
+                None => return Ok(()),
+            };
+            let page = &frame.page;
+            let leaf = page[btree_header_offset(frame.page_number)] == 13;
+            let mut rowids = Vec::with_capacity(frame.cells.len());
+            for &offset in frame.cells.iter() {
+                // Leaf cells start with the payload size, interior cells with
+                // the left child pointer
+                let key_start = if leaf { offset + decode_varint(&page[offset..])?.1 } else { offset + 4 };
+                rowids.push(decode_varint(&page[key_start..])?.0 as i64);
+            }
+            if leaf {
+                frame.next = rowids.iter().position(|&rowid| rowid >= start).unwrap_or(frame.cells.len());
+                return Ok(());
+            }
+            // Each left child holds rowids up to and including its key
+            let index = rowids.iter().position(|&rowid| rowid >= start);
+            let child = match index {
+                Some(index) => {
+                    let offset = frame.cells[index];
+                    u32::from_be_bytes(page[offset..offset + 4].try_into()?)
+                }
+                None => right_most_pointer(page, frame.page_number).unwrap_or(0),
+            };
+            frame.next = index.unwrap_or(frame.cells.len()) + 1;
+            page_number = child;
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<Row>> {
+        while let Some(frame) = self.stack.last_mut() {
+            let header_offset = btree_header_offset(frame.page_number);
+            let index = frame.next;
+            frame.next += 1;
+            if frame.page[header_offset] == 13 {
+                if index < frame.cells.len() {
+                    let offset = frame.cells[index];
+                    let mut row = self.db.read_leaf_cell(&frame.page, offset)?;
+                    row.table = self.table.clone();
+                    return Ok(Some(row));
+                }
+            } else if index <= frame.cells.len() {
+                // Interior table page: 4-byte left child pointer followed by the
+                // rowid key, then the right-most pointer in the page header
+                let child = if index < frame.cells.len() {
+                    let offset = frame.cells[index];
+                    u32::from_be_bytes(frame.page[offset..offset + 4].try_into()?)
+                } else {
+                    right_most_pointer(&frame.page, frame.page_number).unwrap_or(0)
+                };
+                let parent = frame.page_number;
+                self.db.debug(|| format!("page {}: scanning child page {}", parent, child));
+                self.descend(child)?;
+                continue;
+            }
+            self.stack.pop();
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.advance() {
+            Ok(row) => row.map(Ok),
+            Err(err) => {
+                self.stack.clear();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+pub struct AllRows<'a> {
+    rows: Rows<'a>,
+    // Tables not yet started, the next one last
+    tables: Vec<(String, u32, Rc<TableSchema>)>,
+    current: String,
+}
+
+impl Iterator for AllRows<'_> {
+    type Item = Result<(String, Row)>;
+
+    // Like Rows, stops after the first error
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.rows.next() {
+                Some(Ok(row)) => return Some(Ok((self.current.clone(), row))),
+                Some(Err(err)) => {
+                    self.tables.clear();
+                    return Some(Err(err));
+                }
+                None => {}
+            }
+            let (name, root_page, schema) = self.tables.pop()?;
+            self.current = name;
+            self.rows.table = Some(schema);
+            self.rows.visited.clear();
+            if let Err(err) = self.rows.descend(root_page) {
+                self.tables.clear();
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+// A cell's payload read front to back: the bytes on the leaf page first,
+// then each overflow page as the reader reaches it
+struct Payload<'a> {
+    db: &'a mut Database,
+    chunk: Vec<u8>,
+    pos: usize,
+    // Next overflow page, and the payload bytes not yet loaded
+    next: u32,
+    left: usize,
+}
+
+impl Payload<'_> {
+    // Makes sure the current chunk has unread bytes; false at the end of the
+    // payload
+    fn fill(&mut self) -> Result<bool> {
+        if self.pos < self.chunk.len() {
+            return Ok(true);
+        }
+        if self.left == 0 {
+            return Ok(false);
+        }
+        if self.next == 0 {
+            bail!("overflow chain ended early");
+        }
+        let page = self.db.read_page_for(self.next, ReadReason::Overflow)?;
+        let take = self.left.min(self.db.usable_size - 4);
+        self.chunk = page[4..4 + take].to_vec();
+        self.pos = 0;
+        self.next = u32::from_be_bytes(page[0..4].try_into()?);
+        self.left -= take;
+        Ok(true)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        if !self.fill()? {
+            bail!("record header runs past the end of the payload");
+        }
+        self.pos += 1;
+        Ok(self.chunk[self.pos - 1])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut bytes = Vec::with_capacity(9);
+        while bytes.len() < 9 {
+            let byte = self.byte()?;
+            bytes.push(byte);
+            if byte < 0x80 {
+                break;
+            }
+        }
+        Ok(decode_varint(&bytes)?.0)
+    }
+
+    // Overflow pages skipped over still have to be read for their next
+    // pointers, but nothing is copied out of them
+    fn skip(&mut self, mut count: usize) -> Result<()> {
+        while count > 0 {
+            if !self.fill()? {
+                bail!("record runs past the end of the payload");
+            }
+            let step = count.min(self.chunk.len() - self.pos);
+            self.pos += step;
+            count -= step;
+        }
+        Ok(())
+    }
+
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() || !self.fill()? {
+            return Ok(0);
+        }
+        let count = buf.len().min(self.chunk.len() - self.pos);
+        buf[..count].copy_from_slice(&self.chunk[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            let count = self.read_into(buf)?;
+            if count == 0 {
+                bail!("record runs past the end of the payload");
+            }
+            buf = &mut buf[count..];
+        }
+        Ok(())
+    }
+}
+
+fn varint_len(value: u64) -> usize {
+    let mut len = 1;
+    while len < 9 && value >> (7 * len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+// Reads a blob value straight out of its cell and overflow chain, loading
+// overflow pages only as the read reaches them
+pub struct BlobReader<'a> {
+    payload: Payload<'a>,
+    remaining: usize,
+}
+
+impl BlobReader<'_> {
+    // Bytes of the blob not yet read
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let want = buf.len().min(self.remaining);
+        let count = self.payload.read_into(&mut buf[..want]).map_err(std::io::Error::other)?;
+        if count == 0 && want > 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "blob runs past the end of the payload"));
+        }
+        self.remaining -= count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{index_fixture, FixtureBuilder, PAGE_SIZE};
+
+    // Every row of sample.db's apples table, rooted at page 2, as .rows
+    // prints it. The id column aliases the rowid, so it's stored as NULL.
+    fn dump(db: &mut Database) -> Result<Vec<String>> {
+        let mut rows = Vec::new();
+        db.scan_table(2, &mut |_, values| {
+            let row: Vec<String> = values.iter().map(Value::to_string).collect();
+            rows.push(row.join("|"));
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
+    #[test]
+    fn scan_table_dumps_every_row() {
+        let mut db = Database::open("sample.db").unwrap();
+        let expected =
+            ["|Granny Smith|Light Green", "|Fuji|Red", "|Honeycrisp|Blush Red", "|Golden Delicious|Yellow"];
+        assert_eq!(dump(&mut db).unwrap(), expected);
+    }
+
+    #[test]
+    fn payload_larger_than_the_database_is_rejected() {
+        let rows = vec![(1, vec![Value::Blob(vec![7; 20_000])])];
+        let mut image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(b)", &rows).build();
+        let mut db = Database::from_bytes(image.clone()).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page;
+        let page = (root - 1) * PAGE_SIZE;
+        let cells = cell_pointers(&image[page..page + PAGE_SIZE], root as u32).unwrap();
+
+        // The ~20000-byte payload's size takes a three-byte varint; make it
+        // claim 2^21 - 1 bytes instead, far more than the file's pages hold
+        let size = page + cells[0];
+        assert_eq!(decode_varint(&image[size..]).unwrap().1, 3);
+        image[size..size + 3].copy_from_slice(&[0xff, 0xff, 0x7f]);
+        let len = image.len();
+        let mut db = Database::from_bytes(image).unwrap();
+        let err = db.rows("t").unwrap().find_map(Result::err).unwrap();
+        let expected =
+            format!("cell claims a {}-byte payload but the database holds only {} bytes", (1 << 21) - 1, len);
+        assert_eq!(err.to_string(), expected);
+    }
+
+    // A gzip stream of stored (uncompressed) DEFLATE blocks
+    #[cfg(feature = "gzip")]
+    fn gzip_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        let chunks: Vec<&[u8]> = data.chunks(0xffff).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            out.push((i + 1 == chunks.len()) as u8);
+            out.extend((chunk.len() as u16).to_le_bytes());
+            out.extend((!(chunk.len() as u16)).to_le_bytes());
+            out.extend_from_slice(chunk);
+        }
+        out.extend(crate::gzip::crc32(data).to_le_bytes());
+        out.extend((data.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzipped_database_opens_by_path() {
+        let path = std::env::temp_dir().join(format!("gzip-test-{}.db.gz", std::process::id()));
+        std::fs::write(&path, gzip_stored(&std::fs::read("sample.db").unwrap())).unwrap();
+        let db = Database::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let mut db = db.unwrap();
+
+        // What .tables lists, and the rows behind one of them
+        let tables = db.schema().unwrap().table_names();
+        assert_eq!(tables, ["apples", "oranges", "sqlite_sequence"]);
+        assert_eq!(dump(&mut db).unwrap().len(), 4);
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    // A schema of `count` empty tables t000, t001, ...
+    fn many_tables(count: usize) -> Vec<u8> {
+        let mut builder = FixtureBuilder::default();
+        for i in 0..count {
+            let name = format!("t{:03}", i);
+            builder.add_table(&name, &format!("CREATE TABLE {}(id INTEGER PRIMARY KEY, name TEXT)", name), &[]);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn schema_spilling_off_page_1_is_read_in_full() {
+        let image = many_tables(120);
+        assert_eq!(image[btree_header_offset(1)], 5, "page 1 should be an interior page");
+        let mut db = Database::from_bytes(image).unwrap();
+        let names = db.schema().unwrap().table_names();
+        assert_eq!(names.len(), 120);
+        assert_eq!(names.last().map(String::as_str), Some("t119"));
+        assert_eq!(db.rows("t119").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn fixture_rows_can_overflow() {
+        let long = "x".repeat(10_000);
+        let rows = [(1, vec![text(&long)]), (2, vec![text("short")])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let values: Vec<Value> = db.rows("t").unwrap().map(|row| row.unwrap().values[0].clone()).collect();
+        assert_eq!(values, [text(&long), text("short")]);
+    }
+
+    #[test]
+    fn page_kinds_count_index_pages() {
+        let image = index_fixture(5000);
+        let pages = image.len() / PAGE_SIZE;
+        let mut db = Database::from_bytes(image).unwrap();
+        let kinds = db.page_kinds().unwrap();
+        let count = |kind| kinds.iter().filter(|k| **k == kind).count();
+        assert!(count(PageKind::Btree(PageType::LeafIndex)) > 1);
+        assert_eq!(count(PageKind::Btree(PageType::InteriorIndex)), 1);
+        assert!(count(PageKind::Btree(PageType::LeafTable)) > 1);
+        assert_eq!(count(PageKind::Btree(PageType::InteriorTable)), 1);
+        assert_eq!(count(PageKind::Unknown), 0);
+        assert_eq!(kinds.len(), pages);
+    }
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    #[test]
+    fn access_pattern_hints_on_a_mapped_database() {
+        let path = std::env::temp_dir().join(format!("mmap-test-{}.db", std::process::id()));
+        std::fs::write(&path, crate::fixture::table_fixture(2000)).unwrap();
+        let mut db = Database::open_mmap(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        db.set_access_pattern(AccessPattern::Sequential).unwrap();
+        assert_eq!(db.rows("t").unwrap().count(), 2000);
+        db.set_access_pattern(AccessPattern::Random).unwrap();
+        let select = crate::parser::parse_select("SELECT name FROM t WHERE id = 1234").unwrap();
+        let records = db.schema().unwrap();
+        let result = crate::query::execute_select(&mut db, &records, &select).unwrap();
+        assert_eq!(result.rows, [vec![text("name 1234")]]);
+        db.set_access_pattern(AccessPattern::Normal).unwrap();
+    }
+
+    #[test]
+    fn a_scan_past_the_page_budget_fails() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        db.set_max_pages(Some(db.pages_read() + 5));
+        let err = db.rows("t").unwrap().collect::<Result<Vec<Row>>>().unwrap_err();
+        assert_eq!(err.to_string(), format!("page budget exceeded: more than {} pages read", db.pages_read()));
+        assert!(matches!(err.downcast_ref::<SqliteError>(), Some(SqliteError::PageBudgetExceeded { .. })));
+        // A lookup by rowid stays well within the same budget
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        let root_page = db.schema().unwrap().find("t").unwrap().root_page as u32;
+        db.set_max_pages(Some(db.pages_read() + 5));
+        let row = db.find_row(root_page, 1500).unwrap().unwrap();
+        assert_eq!(row.values[1], text("name 1500"));
+    }
+
+    #[test]
+    fn rows_under_the_right_most_pointer_are_read() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page as u32;
+        let page = db.read_page(root).unwrap();
+        let last_leaf = right_most_pointer(&page, root).unwrap();
+        let leaf = db.read_page(last_leaf).unwrap();
+        let last_rowids: Vec<i64> = cell_pointers(&leaf, last_leaf)
+            .unwrap()
+            .into_iter()
+            .map(|offset| {
+                let (_, size_len) = decode_varint(&leaf[offset..]).unwrap();
+                decode_varint(&leaf[offset + size_len..]).unwrap().0 as i64
+            })
+            .collect();
+        assert_eq!(last_rowids.last(), Some(&2000));
+        assert!(last_rowids.len() > 1);
+        let rowids: Vec<i64> = db.rows("t").unwrap().map(|row| row.unwrap().rowid).collect();
+        assert_eq!(rowids, (1..=2000).collect::<Vec<i64>>());
+        let row = db.find_row(root, last_rowids[0]).unwrap().unwrap();
+        assert_eq!(row.values[1], text(&format!("name {}", last_rowids[0])));
+    }
+
+    #[test]
+    fn cells_of_a_leaf_and_an_interior_page() {
+        let rows = [(3, vec![text("apple")]), (7, vec![text("banana")]), (9, vec![Value::Null])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(name TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let cells = db.cells_of_page(2).unwrap();
+        let rowids: Vec<Option<i64>> = cells.iter().map(|cell| cell.rowid).collect();
+        assert_eq!(rowids, [Some(3), Some(7), Some(9)]);
+        let page = db.read_page(2).unwrap();
+        for (cell, (_, values)) in cells.iter().zip(rows.iter()) {
+            let record = crate::fixture::encode_record(values);
+            assert_eq!((cell.left_child, cell.payload_size), (None, record.len() as u64));
+            assert_eq!(page[cell.content_offset..cell.content_offset + record.len()], record[..]);
+        }
+
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page as u32;
+        let cells = db.cells_of_page(root).unwrap();
+        assert!(cells.len() > 1);
+        assert!(cells.iter().all(|cell| cell.left_child.is_some() && cell.payload_size == 0));
+        let keys: Vec<i64> = cells.iter().filter_map(|cell| cell.rowid).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", keys);
+    }
+
+    fn select(db: &mut Database, sql: &str) -> Vec<Vec<Value>> {
+        let records = db.schema().unwrap();
+        crate::query::execute_select(db, &records, &crate::parser::parse_select(sql).unwrap()).unwrap().rows
+    }
+
+    thread_local! {
+        static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    // A Log is a plain function, so it records into a thread local
+    fn record_log(message: &str) {
+        LOGGED.with(|logged| logged.borrow_mut().push(message.to_string()));
+    }
+
+    #[test]
+    fn log_reports_page_reads_descents_and_where_results() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page;
+        db.set_log(Some(record_log));
+        let rows = select(&mut db, "SELECT name FROM t WHERE score > 499.5");
+        assert_eq!(rows, [vec![text("name 1999")], vec![text("name 2000")]]);
+        let logged = LOGGED.with(|logged| logged.take());
+        assert!(logged.contains(&format!("read page {} (interior-table)", root)), "{:?}", logged);
+        let leaves = logged.iter().filter(|line| line.starts_with("read page ") && line.ends_with(" (leaf-table)"));
+        assert!(leaves.count() > 1, "{:?}", logged);
+        assert!(logged.iter().any(|line| line.starts_with(&format!("page {}: scanning child page ", root))));
+        assert!(logged.contains(&"row 2000: WHERE score > 499.5 is 1".to_string()), "{:?}", logged);
+        assert!(logged.contains(&"row 1: WHERE score > 499.5 is 0".to_string()), "{:?}", logged);
+        db.set_log(None);
+        select(&mut db, "SELECT name FROM t WHERE id = 5");
+        assert!(LOGGED.with(|logged| logged.borrow().is_empty()));
+    }
+
+    // Page 1 holds the schema table's b-tree behind the file header: as its
+    // only page while the schema fits, or as the interior page above leaves
+    // from page 2 on once it doesn't. Either way the schema rows, and the
+    // rows of a table rooted at page 2, read back the same.
+    #[test]
+    fn schema_on_page_1_alone_or_spilled() {
+        let rows = [(1, vec![Value::Null, text("one")]), (2, vec![Value::Null, text("two")])];
+        for extra in [0, 119] {
+            let mut builder = FixtureBuilder::default();
+            builder.add_table("t", "CREATE TABLE t(id INTEGER PRIMARY KEY, name TEXT)", &rows);
+            for i in 0..extra {
+                builder.add_table(&format!("filler{}", i), &format!("CREATE TABLE filler{}(x)", i), &[]);
+            }
+            let image = builder.build();
+            let page_type = PageType::from_byte(image[100]);
+            assert_eq!(page_type == Some(PageType::LeafTable), extra == 0);
+
+            let mut db = Database::from_bytes(image).unwrap();
+            let schema: Vec<(Value, Value)> = db
+                .rows("sqlite_schema")
+                .unwrap()
+                .map(|row| {
+                    let row = row.unwrap();
+                    (row.values[1].clone(), row.values[3].clone())
+                })
+                .collect();
+            assert_eq!(schema.len(), extra + 1);
+            assert_eq!(schema[0], (text("t"), Value::Integer(2)));
+            let names: Vec<Value> = db.schema().unwrap().iter().map(|record| text(&record.name)).collect();
+            assert_eq!(names, schema.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>());
+            let rows = select(&mut db, "SELECT id, name FROM t");
+            assert_eq!(rows, [vec![Value::Integer(1), text("one")], vec![Value::Integer(2), text("two")]]);
+        }
+    }
+
+    #[test]
+    fn query_binds_placeholders_in_order() {
+        let rows = [
+            (1, vec![text("apple"), text("red")]),
+            (2, vec![text("lime"), text("green")]),
+            (3, vec![text("cherry"), text("red")]),
+        ];
+        let image =
+            FixtureBuilder::default().add_table("fruit", "CREATE TABLE fruit(name TEXT, color TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let result = db.query("SELECT name FROM fruit WHERE color = ?", &[text("red")]).unwrap();
+        assert_eq!(result.rows, [vec![text("apple")], vec![text("cherry")]]);
+        // A bound value is only ever a value, never SQL
+        let result = db.query("SELECT name FROM fruit WHERE color = ?", &[text("red' OR '1' = '1")]).unwrap();
+        assert!(result.rows.is_empty());
+        let params = [text("red"), text("apple")];
+        let result = db.query("SELECT name FROM fruit WHERE color = ? AND name != ?", &params).unwrap();
+        assert_eq!(result.rows, [vec![text("cherry")]]);
+        let err = db.query("SELECT name FROM fruit WHERE color = ?", &[]).unwrap_err();
+        assert_eq!(err.to_string(), "statement has 1 parameters but 0 values were given");
+    }
+
+    #[test]
+    fn a_page_pointing_to_itself_is_a_cycle() {
+        let mut image = crate::fixture::table_fixture(2000);
+        let mut db = Database::from_bytes(image.clone()).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page as u32;
+        // Point the root's right-most child back at the root
+        let header = (root as usize - 1) * crate::fixture::PAGE_SIZE;
+        image[header + 8..header + 12].copy_from_slice(&root.to_be_bytes());
+        let mut db = Database::from_bytes(image).unwrap();
+        let err = db.query("SELECT name FROM t", &[]).unwrap_err();
+        assert_eq!(err.to_string(), format!("cycle detected at page {}", root));
+        let err = db.rows("t").unwrap().find_map(Result::err).unwrap();
+        let cycle = err.downcast_ref::<SqliteError>();
+        assert!(matches!(cycle, Some(SqliteError::CycleDetected { page }) if *page == root), "{}", err);
+        // A lookup that never takes the right-most pointer still succeeds
+        let row = db.find_row(root, 1).unwrap().unwrap();
+        assert_eq!(row.values[1], text("name 1"));
+    }
+
+    // Two user tables, one of them spread over many pages, and an internal
+    // table that doesn't count
+    fn two_tables() -> Vec<u8> {
+        let small = [(1, vec![text("a")]), (2, vec![text("b")]), (3, vec![text("c")])];
+        let sequence = [(1, vec![text("small"), Value::Integer(3)])];
+        let large: Vec<(i64, Vec<Value>)> = (1..=1500).map(|id| (id, vec![text(&format!("row {}", id))])).collect();
+        FixtureBuilder::default()
+            .add_table("small", "CREATE TABLE small(v TEXT)", &small)
+            .add_table("sqlite_sequence", "CREATE TABLE sqlite_sequence(name,seq)", &sequence)
+            .add_table("large", "CREATE TABLE large(v TEXT)", &large)
+            .build()
+    }
+
+    #[test]
+    fn total_rows_sums_the_user_tables() {
+        let mut db = Database::from_bytes(two_tables()).unwrap();
+        assert_eq!(db.table_counts().unwrap(), [("large".to_string(), 1500), ("small".to_string(), 3)]);
+        assert_eq!(db.total_rows().unwrap(), 1503);
+    }
+
+    #[test]
+    fn rows_from_starts_at_the_first_rowid_not_below_the_start() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        let rows: Vec<Row> = db.rows_from("t", 1234).unwrap().map(Result::unwrap).collect();
+        assert_eq!((rows[0].rowid, &rows[0].values[1]), (1234, &text("name 1234")));
+        assert_eq!(rows.len(), 767);
+        assert_eq!(rows.last().unwrap().rowid, 2000);
+        // Earlier leaves are skipped: the root, then straight to the leaf
+        let before = db.page_stats();
+        assert_eq!(db.rows_from("t", 1990).unwrap().next().unwrap().unwrap().rowid, 1990);
+        assert_eq!(db.page_stats().since(before).table, 2);
+        assert_eq!(db.rows_from("t", 2001).unwrap().count(), 0);
+
+        let rows: Vec<(i64, Vec<Value>)> = (1..=500).map(|i| (i * 2, vec![Value::Integer(i)])).collect();
+        let image = FixtureBuilder::default().add_table("even", "CREATE TABLE even(n)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(db.rows_from("even", 301).unwrap().next().unwrap().unwrap().rowid, 302);
+        assert_eq!(db.rows_from("even", -5).unwrap().next().unwrap().unwrap().rowid, 2);
+    }
+
+    #[test]
+    fn a_zero_page_size_is_an_error() {
+        let mut image = crate::fixture::table_fixture(10);
+        image[16..18].copy_from_slice(&[0, 0]);
+        let err = Database::from_bytes(image.clone()).err().unwrap();
+        assert_eq!(err.to_string(), "invalid page size 0");
+        image[16..18].copy_from_slice(&1000u16.to_be_bytes());
+        assert_eq!(Database::from_bytes(image).err().unwrap().to_string(), "invalid page size 1000");
+        // Only the magic string and size field need to be there to get as far
+        let mut image = vec![0; 100];
+        image[..16].copy_from_slice(b"SQLite format 3\0");
+        assert_eq!(Database::from_bytes(image).err().unwrap().to_string(), "invalid page size 0");
+    }
+
+    #[test]
+    fn typed_getters_on_rows() {
+        let rows = [
+            (1, vec![Value::Null, text("apple"), Value::Real(1.5), Value::Blob(vec![0xca, 0xfe])]),
+            (2, vec![Value::Null, Value::Null, Value::Integer(3), Value::Null]),
+        ];
+        let sql = "CREATE TABLE items(id INTEGER PRIMARY KEY, name TEXT, price REAL, tag BLOB)";
+        let image = FixtureBuilder::default().add_table("items", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let rows: Vec<Row> = db.rows("items").unwrap().map(Result::unwrap).collect();
+        let (first, second) = (&rows[0], &rows[1]);
+        assert_eq!(first.get_i64("id").unwrap(), Some(1));
+        assert_eq!(first.get_text("name").unwrap().as_deref(), Some("apple"));
+        assert_eq!(first.get_f64("price").unwrap(), Some(1.5));
+        assert_eq!(first.get_blob("tag").unwrap(), Some(vec![0xca, 0xfe]));
+        assert_eq!(second.get_text("name").unwrap(), None);
+        assert_eq!(second.get_blob("tag").unwrap(), None);
+        assert_eq!(second.get_f64("price").unwrap(), Some(3.0));
+        let err = first.get_i64("name").unwrap_err();
+        assert_eq!(err.to_string(), "column name holds text apple, not an integer");
+        let err = first.get_text("price").unwrap_err();
+        assert_eq!(err.to_string(), "column price holds real 1.5, not text");
+        assert_eq!(first.get_i64("nope").unwrap_err().to_string(), "no such column: nope");
+    }
+
+    // Pages from the root of a table b-tree down to its leaves
+    fn tree_height(db: &mut Database, root_page: u32) -> u64 {
+        let mut height = 1;
+        let mut page = root_page;
+        while let Some(child) = db.cells_of_page(page).unwrap().first().and_then(|cell| cell.left_child) {
+            height += 1;
+            page = child;
+        }
+        height
+    }
+
+    #[test]
+    fn a_rowid_lookup_reads_one_page_per_level() {
+        // Small pages, so the tree is three levels deep
+        let rows: Vec<(i64, Vec<Value>)> = (1..=5000).map(|id| (id, vec![text(&format!("row {}", id))])).collect();
+        let image = FixtureBuilder::new(512).add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page as u32;
+        let height = tree_height(&mut db, root);
+        assert_eq!(height, 3);
+        for rowid in [1, 2500, 4999, 5000, 0, 9999] {
+            let expected = (1..=5000).contains(&rowid).then(|| vec![text(&format!("row {}", rowid))]);
+            let before = db.page_stats();
+            let row = db.find_row(root, rowid).unwrap();
+            assert!(db.page_stats().since(before).total <= height, "rowid {}", rowid);
+            assert_eq!(row.map(|row| row.values), expected);
+            // By name, the schema is read too, but the table no deeper
+            let before = db.page_stats();
+            let row = db.get_row("t", rowid).unwrap();
+            assert!(db.page_stats().since(before).table <= height, "rowid {}", rowid);
+            assert_eq!(row.map(|row| row.values), expected);
+        }
+    }
+
+    // Just enough JSON to check the structure of what `to_json` writes,
+    // rather than its exact spelling
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Null,
+        Bool(bool),
+        Number(f64),
+        Str(String),
+        Array(Vec<Json>),
+        Object(Vec<(String, Json)>),
+    }
+
+    fn parse_json(text: &str) -> Json {
+        let mut chars = text.chars().peekable();
+        let value = json_value(&mut chars);
+        assert!(chars.all(char::is_whitespace), "trailing data after the JSON value in {}", text);
+        value
+    }
+
+    fn json_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Json {
+        let skip_space = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        };
+        skip_space(chars);
+        let value = match chars.next().expect("unexpected end of JSON") {
+            '{' => {
+                let mut fields = Vec::new();
+                skip_space(chars);
+                if chars.next_if_eq(&'}').is_none() {
+                    loop {
+                        let Json::Str(key) = json_value(chars) else { panic!("object key is not a string") };
+                        skip_space(chars);
+                        assert_eq!(chars.next(), Some(':'));
+                        fields.push((key, json_value(chars)));
+                        match chars.next() {
+                            Some(',') => continue,
+                            Some('}') => break,
+                            other => panic!("expected , or }} in object, found {:?}", other),
+                        }
+                    }
+                }
+                Json::Object(fields)
+            }
+            '[' => {
+                let mut items = Vec::new();
+                skip_space(chars);
+                if chars.next_if_eq(&']').is_none() {
+                    loop {
+                        items.push(json_value(chars));
+                        match chars.next() {
+                            Some(',') => continue,
+                            Some(']') => break,
+                            other => panic!("expected , or ] in array, found {:?}", other),
+                        }
+                    }
+                }
+                Json::Array(items)
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next().expect("unterminated JSON string") {
+                        '"' => break,
+                        '\\' => match chars.next().expect("unterminated JSON escape") {
+                            'n' => string.push('\n'),
+                            't' => string.push('\t'),
+                            'r' => string.push('\r'),
+                            'u' => {
+                                let hex: String = chars.by_ref().take(4).collect();
+                                let code = u32::from_str_radix(&hex, 16).unwrap();
+                                string.push(char::from_u32(code).unwrap());
+                            }
+                            other => string.push(other),
+                        },
+                        c => string.push(c),
+                    }
+                }
+                Json::Str(string)
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || "+-.".contains(*c)) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => Json::Null,
+                    "true" => Json::Bool(true),
+                    "false" => Json::Bool(false),
+                    number => Json::Number(number.parse().unwrap_or_else(|_| panic!("bad JSON token {}", number))),
+                }
+            }
+        };
+        skip_space(chars);
+        value
+    }
+
+    #[test]
+    fn summary_json_lists_the_tables() {
+        let image = FixtureBuilder::default()
+            .add_table("apples", "CREATE TABLE apples(name TEXT)", &[])
+            .add_index("idx_name", "apples", "CREATE INDEX idx_name ON apples(name)", &[])
+            .add_table("say \"hi\"", "CREATE TABLE \"say \"\"hi\"\"\"(x)", &[])
+            .build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let summary = db.summary().unwrap();
+        assert_eq!(summary.tables, [("apples".to_string(), 2), ("say \"hi\"".to_string(), 4)]);
+        let table = |name: &str, root_page| {
+            Json::Object(vec![("name".into(), Json::Str(name.into())), ("root_page".into(), Json::Number(root_page))])
+        };
+        let expected = Json::Object(vec![
+            ("page_size".into(), Json::Number(4096.0)),
+            ("page_count".into(), Json::Number(4.0)),
+            ("encoding".into(), Json::Str("UTF-8".into())),
+            ("tables".into(), Json::Array(vec![table("apples", 2.0), table("say \"hi\"", 4.0)])),
+        ]);
+        assert_eq!(parse_json(&summary.to_json()), expected);
+    }
+
+    #[test]
+    fn a_stored_page_count_that_disagrees_with_the_file() {
+        let image = crate::fixture::table_fixture(2000);
+        let pages = image.len() / crate::fixture::PAGE_SIZE;
+        assert_eq!(Database::from_bytes(image.clone()).unwrap().size_mismatch(), None);
+        let mut stale = image.clone();
+        stale[28..32].copy_from_slice(&(pages as u32 + 3).to_be_bytes());
+        let db = Database::from_bytes(stale).unwrap();
+        let expected = format!("header says {} pages but the file holds {}", pages + 3, pages);
+        assert_eq!(db.size_mismatch(), Some(expected.as_str()));
+        let mut appended = image;
+        appended.extend_from_slice(&[0; 10]);
+        let db = Database::from_bytes(appended.clone()).unwrap();
+        let expected = format!("file length {} is not a whole number of 4096-byte pages", appended.len());
+        assert_eq!(db.size_mismatch(), Some(expected.as_str()));
+    }
+
+    #[test]
+    fn a_blob_prefix_is_read_without_the_rest_of_its_chain() {
+        let blob: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let rows = [(1, vec![text("big"), Value::Blob(blob.clone())]), (2, vec![text("none"), Value::Null])];
+        let sql = "CREATE TABLE files(name TEXT, data BLOB)";
+        let image = FixtureBuilder::default().add_table("files", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        db.schema().unwrap();
+        let before = db.page_stats();
+        let mut prefix = vec![0; 6000];
+        db.blob_reader("files", 1, "data").unwrap().unwrap().read_exact(&mut prefix).unwrap();
+        assert_eq!(prefix, blob[..6000]);
+        // The schema and the leaf, then only the start of the chain of
+        // thirteen overflow pages
+        let read = db.page_stats().since(before);
+        assert!(read.total <= 5, "{:?}", read);
+        let mut whole = Vec::new();
+        db.blob_reader("files", 1, "data").unwrap().unwrap().read_to_end(&mut whole).unwrap();
+        assert_eq!(whole, blob);
+        assert!(db.blob_reader("files", 2, "data").unwrap().is_none());
+        assert!(db.blob_reader("files", 3, "data").unwrap().is_none());
+    }
+
+    #[test]
+    fn indexes_list_their_parsed_columns() {
+        let image = FixtureBuilder::default()
+            .add_table("t", "CREATE TABLE t(id INTEGER PRIMARY KEY, a TEXT, b INTEGER)", &[])
+            .add_index("idx_ab", "t", "CREATE INDEX idx_ab ON t(a, b)", &[])
+            .add_index("idx_ba", "t", "CREATE UNIQUE INDEX idx_ba ON t (b DESC, a COLLATE NOCASE ASC)", &[])
+            .add_index("idx_some", "t", "CREATE INDEX \"idx_some\" ON t(\"a\") WHERE b > 0", &[])
+            .build();
+        let indexes = Database::from_bytes(image).unwrap().indexes().unwrap();
+        let summary: Vec<(&str, &str, Vec<&str>, u32)> = indexes
+            .iter()
+            .map(|index| {
+                let columns = index.columns.iter().map(String::as_str).collect();
+                (index.name.as_str(), index.table.as_str(), columns, index.root_page)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [("idx_ab", "t", vec!["a", "b"], 3), ("idx_ba", "t", vec!["b", "a"], 4), ("idx_some", "t", vec!["a"], 5)]
+        );
+        let flags: Vec<(bool, bool)> = indexes.iter().map(|index| (index.unique, index.partial)).collect();
+        assert_eq!(flags, [(false, false), (true, false), (false, true)]);
+    }
+
+    #[test]
+    fn trace_json_records_each_page_read_and_why() {
+        let rows = [(1, vec![text("a")]), (2, vec![text("b")])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(db.trace_json(), "[]");
+        db.set_trace(true);
+        db.query("SELECT v FROM t", &[]).unwrap();
+        let expected = "[
+  {\"page\":1,\"type\":\"leaf-table\",\"reason\":\"schema\"},
+  {\"page\":2,\"type\":\"leaf-table\",\"reason\":\"leaf-scan\"}
+]";
+        assert_eq!(db.trace_json(), expected);
+
+        let mut db = Database::from_bytes(crate::fixture::index_fixture(2000)).unwrap();
+        db.set_trace(true);
+        db.query("SELECT id FROM t WHERE a = 'apple' AND b = 5", &[]).unwrap();
+        let json = db.trace_json();
+        assert!(json.contains("\"type\":\"interior-index\",\"reason\":\"index\""), "{}", json);
+        assert!(json.contains("\"type\":\"leaf-index\",\"reason\":\"index\""), "{}", json);
+        // The index covers the query, so no table page is read
+        assert!(!json.contains("leaf-scan") && !json.contains("descent"), "{}", json);
+    }
+
+    // Splits a CSV line written by csv_field back into fields, undoing the
+    // quoting; the test data has no embedded newlines
+    fn parse_csv_line(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(String::new()),
+                c => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn export_csv_of_a_multi_page_table_parses_back() {
+        let rows: Vec<(i64, Vec<Value>)> = (1..=500)
+            .map(|id| (id, vec![Value::Null, text(&format!("item \"{}\", boxed", id)), Value::Integer(id % 7)]))
+            .collect();
+        let sql = "CREATE TABLE items(id INTEGER PRIMARY KEY, name TEXT, price REAL)";
+        let image = FixtureBuilder::default().add_table("items", sql, &rows).build();
+        assert!(image.len() / crate::fixture::PAGE_SIZE > 4);
+        let mut db = Database::from_bytes(image).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(db.export_csv("items", &mut out).unwrap(), 500);
+
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("id,name,price"));
+        let parsed: Vec<Vec<String>> = lines.map(parse_csv_line).collect();
+        assert_eq!(parsed.len(), 500);
+        for (i, fields) in parsed.iter().enumerate() {
+            let id = i as i64 + 1;
+            let expected = [id.to_string(), format!("item \"{}\", boxed", id), format!("{:.1}", (id % 7) as f64)];
+            assert_eq!(fields, &expected);
+        }
+    }
+
+    // Deterministic high-entropy bytes from an xorshift generator
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn random_page_aligned_bytes_are_reported_as_possibly_encrypted() {
+        let err = Database::from_bytes(random_bytes(4096)).err().unwrap();
+        assert!(matches!(err.downcast_ref(), Some(SqliteError::PossiblyEncrypted { size: 4096 })), "{}", err);
+        assert!(err.to_string().contains("SQLCipher"), "{}", err);
+
+        // Without the page alignment, or with bytes that don't look random,
+        // it is just not a database
+        for bytes in [random_bytes(4000), vec![0; 4096], "not a database\n".repeat(256).into_bytes()] {
+            let err = Database::from_bytes(bytes).err().unwrap();
+            assert_eq!(err.to_string(), "file is not a database: missing the SQLite header");
+        }
+    }
+
+    // `t` on page 2, then trunk 3 with leaves 4 to 6 and trunk 7 with leaves
+    // 8 and 9, at the head of the freelist
+    fn with_free_pages() -> Vec<u8> {
+        FixtureBuilder::default()
+            .add_table("t", "CREATE TABLE t(v TEXT)", &[(1, vec![text("kept")])])
+            .add_free_pages(3)
+            .add_free_pages(2)
+            .build()
+    }
+
+    #[test]
+    fn freelist_lists_trunks_and_their_leaves() {
+        let mut db = Database::from_bytes(with_free_pages()).unwrap();
+        let freelist = db.freelist().unwrap();
+        assert_eq!(freelist.trunks, [7, 3]);
+        assert_eq!(freelist.leaves, [8, 9, 4, 5, 6]);
+        assert_eq!((freelist.page_count(), db.freelist_count()), (7, 7));
+        let kinds = db.page_kinds().unwrap();
+        assert_eq!(kinds.iter().filter(|&&kind| kind == PageKind::Freelist).count(), 7);
+    }
+
+    #[test]
+    fn freelist_that_loops_back_is_an_error() {
+        let mut image = with_free_pages();
+        // Trunk 3, the last, points back at trunk 7
+        let trunk_3 = 2 * crate::fixture::PAGE_SIZE;
+        image[trunk_3..trunk_3 + 4].copy_from_slice(&7u32.to_be_bytes());
+        let err = Database::from_bytes(image).unwrap().freelist().unwrap_err();
+        assert_eq!(err.to_string(), "freelist loops back to trunk page 7");
+    }
+
+    #[test]
+    fn schema_format_outside_1_to_4_is_rejected() {
+        let rows = [(1, vec![text("a")])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        for format in [0, 1, 4] {
+            let mut image = image.clone();
+            image[44..48].copy_from_slice(&u32::to_be_bytes(format));
+            assert_eq!(Database::from_bytes(image).unwrap().schema_format(), format);
+        }
+        for format in [5, 0x0100_0000] {
+            let mut image = image.clone();
+            image[44..48].copy_from_slice(&u32::to_be_bytes(format));
+            let err = Database::from_bytes(image).err().unwrap();
+            assert_eq!(err.to_string(), format!("unsupported schema format number {} (expected 1 to 4)", format));
+        }
+    }
+
+    #[test]
+    fn table_size_counts_its_pages_and_overflow_pages() {
+        let page_size = crate::fixture::PAGE_SIZE as u64;
+        let mut rows: Vec<(i64, Vec<Value>)> = (1..=400).map(|id| (id, vec![text(&format!("row {}", id))])).collect();
+        rows.push((401, vec![text(&"x".repeat(3 * page_size as usize))]));
+        let image = FixtureBuilder::default()
+            .add_table("t", "CREATE TABLE t(v TEXT)", &rows)
+            .add_table("empty", "CREATE TABLE empty(v TEXT)", &[])
+            .build();
+        let file_size = image.len() as u64;
+        let mut db = Database::from_bytes(image).unwrap();
+        let size = db.table_size_bytes("t").unwrap();
+        assert!(size > 0 && size % page_size == 0, "{}", size);
+        // Every page but the schema's and the empty table's single leaf
+        assert_eq!(size, file_size - 2 * page_size);
+        assert_eq!(db.table_size_bytes("empty").unwrap(), page_size);
+        assert!(db.index_size_bytes("t").is_err());
+    }
+
+    #[test]
+    fn wrong_page_size_in_the_header_suggests_an_override() {
+        let rows = [(1, vec![text("a")]), (2, vec![text("b")])];
+        let mut image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        image[16..18].copy_from_slice(&1024u16.to_be_bytes());
+        let err = Database::from_bytes(image.clone()).err().unwrap().to_string();
+        assert!(err.starts_with("page 1 doesn't look like a 1024-byte table b-tree page"), "{}", err);
+        assert!(err.ends_with("try --page-size <bytes>"), "{}", err);
+
+        let page_size = crate::fixture::PAGE_SIZE;
+        let mut db = Database::open_from_with_page_size(Cursor::new(image), Some(page_size)).unwrap();
+        assert_eq!(db.page_size(), page_size);
+        assert_eq!(db.query("SELECT v FROM t", &[]).unwrap().rows, [vec![text("a")], vec![text("b")]]);
+    }
+
+    #[test]
+    fn rows_fetched_by_rowid_come_back_in_rowid_order() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(2000)).unwrap();
+        let root = db.schema().unwrap().find("t").unwrap().root_page as u32;
+        let rows = db.fetch_rows_by_rowids(root, &[1500, 3, 999, 3, 2001, 2000]).unwrap();
+        let rowids: Vec<i64> = rows.iter().map(|row| row.rowid).collect();
+        assert_eq!(rowids, [3, 999, 1500, 2000]);
+        for row in rows {
+            assert_eq!(row.values[1], text(&format!("name {}", row.rowid)));
+        }
+        assert!(db.fetch_rows_by_rowids(root, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rows_outlive_the_pages_they_were_read_from() {
+        let rows = [(1, vec![text("one"), Value::Blob(vec![1])]), (2, vec![text(&"y".repeat(6000)), Value::Null])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT, b BLOB)", &rows).build();
+        let (collected, record) = {
+            let mut db = Database::from_bytes(image).unwrap();
+            let collected: Vec<Row> = db.rows("t").unwrap().collect::<Result<_>>().unwrap();
+            let record = db.schema().unwrap().find("t").unwrap().clone();
+            (collected, record)
+        };
+        // The database and every page buffer are gone by now
+        assert_eq!(collected[0].values, [text("one"), Value::Blob(vec![1])]);
+        assert_eq!(collected[1].get("v").unwrap(), text(&"y".repeat(6000)));
+        assert_eq!(record.sql, "CREATE TABLE t(v TEXT, b BLOB)");
+    }
+
+    #[test]
+    fn file_shorter_than_a_page_is_a_clean_error() {
+        let rows = [(1, vec![text("a")])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        let err = Database::from_bytes(image[..300].to_vec()).err().unwrap();
+        assert_eq!(err.to_string(), "file is truncated: 300 bytes is less than one 4096-byte page");
+
+        // A tightly packed 512-byte page 1 is still whole
+        let image = FixtureBuilder::new(512).add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(db.query("SELECT v FROM t", &[]).unwrap().rows, [vec![text("a")]]);
+    }
+
+    #[test]
+    fn next_leaf_walks_an_index_in_key_order() {
+        let rows: Vec<(i64, Vec<Value>)> = (1..=3000).map(|id| (id, vec![Value::Integer(id * 7919 % 1000)])).collect();
+        let entries: Vec<Vec<Value>> =
+            rows.iter().map(|(id, values)| vec![values[0].clone(), Value::Integer(*id)]).collect();
+        let image = FixtureBuilder::default()
+            .add_table("t", "CREATE TABLE t(a INTEGER)", &rows)
+            .add_index("idx_a", "t", "CREATE INDEX idx_a ON t(a)", &entries)
+            .build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let root = db.indexes().unwrap()[0].root_page;
+
+        let mut path = Vec::new();
+        let mut leaf = Some(db.first_leaf(root, &mut path).unwrap());
+        let (mut leaves, mut keys) = (0, Vec::new());
+        while let Some(page_number) = leaf {
+            let page = db.read_page(page_number).unwrap();
+            assert_eq!(PageType::from_byte(page[0]), Some(PageType::LeafIndex));
+            for offset in cell_pointers(&page, page_number).unwrap() {
+                let (size, size_len) = decode_varint(&page[offset..]).unwrap();
+                let payload = db.read_payload(&page, PageType::LeafIndex, offset + size_len, size as usize).unwrap();
+                keys.push(decode_record_with_encoding(&payload, TextEncoding::Utf8).unwrap());
+            }
+            leaves += 1;
+            leaf = db.next_leaf(&mut path).unwrap();
+        }
+        assert!(leaves > 2, "{} leaves", leaves);
+        // Interior cells hold the entries between leaves, which the leaves lack
+        assert!(keys.len() > 2900 && keys.len() < 3000, "{}", keys.len());
+        assert!(keys.windows(2).all(|pair| {
+            let ordering = compare_values(&pair[0][0], &pair[1][0]).then(compare_values(&pair[0][1], &pair[1][1]));
+            ordering == Ordering::Less
+        }));
+    }
+
+    #[test]
+    fn scan_columns_matches_the_same_columns_read_row_by_row() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(1500)).unwrap();
+        let columns = db.scan_columns("t", &["score", "id", "name"]).unwrap();
+        assert_eq!(columns.len(), 3);
+        let mut by_row = vec![Vec::new(); 3];
+        for row in db.rows("t").unwrap() {
+            let row = row.unwrap();
+            for (vector, column) in by_row.iter_mut().zip(["score", "id", "name"]) {
+                vector.push(row.get(column).unwrap());
+            }
+        }
+        assert_eq!(by_row[0].len(), 1500);
+        assert_eq!(columns, by_row);
+        assert!(db.scan_columns("t", &["missing"]).is_err());
+    }
+
+    #[test]
+    fn from_file_reads_a_handle_opened_by_the_caller() {
+        let path = std::env::temp_dir().join(format!("from-file-test-{}.db", std::process::id()));
+        std::fs::write(&path, crate::fixture::table_fixture(300)).unwrap();
+        let mut file = File::open(&path).unwrap();
+        // Wherever the caller left the file, it's read from the start
+        file.seek(SeekFrom::Start(5000)).unwrap();
+        let mut db = Database::from_file(file).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(db.schema().unwrap().table_names(), ["t"]);
+        let result = db.query("SELECT COUNT(*) FROM t", &[]).unwrap();
+        assert_eq!(result.rows, [vec![Value::Integer(300)]]);
+    }
+
+    #[test]
+    fn all_rows_yields_the_sum_of_the_table_counts() {
+        let mut db = Database::from_bytes(two_tables()).unwrap();
+        let counts = db.table_counts().unwrap();
+        let mut yielded: Vec<(String, u64)> = Vec::new();
+        for item in db.all_rows().unwrap() {
+            let (table, _) = item.unwrap();
+            match yielded.last_mut() {
+                Some((last, count)) if *last == table => *count += 1,
+                _ => yielded.push((table, 1)),
+            }
+        }
+        assert_eq!(yielded, counts);
+        assert_eq!(yielded.iter().map(|(_, count)| count).sum::<u64>(), 1503);
+
+        // Streaming: the first row needs only the first leaf of its table
+        let before = db.page_stats();
+        let (table, row) = db.all_rows().unwrap().next().unwrap().unwrap();
+        assert_eq!((table.as_str(), row.rowid), ("large", 1));
+        assert!(db.page_stats().since(before).table <= 2);
+    }
+
+    fn foreign_key_problems_of(tables: &[(&str, &str)]) -> Vec<String> {
+        let mut builder = FixtureBuilder::default();
+        for (name, sql) in tables {
+            builder.add_table(name, sql, &[]);
+        }
+        Database::from_bytes(builder.build()).unwrap().foreign_key_problems().unwrap()
+    }
+
+    #[test]
+    fn foreign_keys_are_parsed_from_columns_and_constraints() {
+        let sql = "CREATE TABLE orders(id INTEGER PRIMARY KEY, customer REFERENCES customers(id), sku TEXT, \
+                   FOREIGN KEY (sku) REFERENCES products ON DELETE CASCADE)";
+        let keys: Vec<(Vec<String>, String, Vec<String>)> = parse_foreign_keys(sql)
+            .unwrap()
+            .into_iter()
+            .map(|key| (key.columns, key.parent, key.parent_columns))
+            .collect();
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        let expected = [
+            (names(&["customer"]), "customers".to_string(), names(&["id"])),
+            (names(&["sku"]), "products".to_string(), Vec::new()),
+        ];
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn dangling_foreign_key_to_a_missing_table_is_reported() {
+        let problems = foreign_key_problems_of(&[("t", "CREATE TABLE t(a INTEGER REFERENCES missing(x))")]);
+        assert_eq!(problems, ["t(a): references missing table missing"]);
+    }
+
+    #[test]
+    fn foreign_key_to_a_missing_parent_column_is_reported() {
+        let problems = foreign_key_problems_of(&[
+            ("parent", "CREATE TABLE parent(id INTEGER PRIMARY KEY, name TEXT)"),
+            ("child", "CREATE TABLE child(p INTEGER, FOREIGN KEY (p) REFERENCES parent(code))"),
+            ("ok", "CREATE TABLE ok(p INTEGER REFERENCES parent(id), q REFERENCES parent)"),
+        ]);
+        assert_eq!(problems, ["child(p): references missing column parent.code"]);
+    }
+
+    // CREATE statements longer than page 1 can hold continue on overflow
+    // pages, like any other long payload
+    #[test]
+    fn schema_sql_overflowing_page_1() {
+        let columns: Vec<String> = (0..1500).map(|i| format!("column_{}", i)).collect();
+        let sql = format!("CREATE TABLE wide({})", columns.join(", "));
+        assert!(sql.len() > 3 * crate::fixture::PAGE_SIZE);
+        let mut values = vec![Value::Null; 1500];
+        values[1499] = text("last");
+        let image = FixtureBuilder::default().add_table("wide", &sql, &[(1, values)]).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(db.schema().unwrap().find("wide").map(|record| record.sql.clone()), Some(sql));
+        let result = db.query("SELECT column_1499 FROM wide", &[]).unwrap();
+        assert_eq!(result.rows, [vec![text("last")]]);
+    }
+}
@@ -0,0 +1,196 @@
+// Dates as SQLite stores them: ISO-8601 text or unix-epoch integers. There
+// is no time zone; text with an offset is converted to UTC, as SQLite's date
+// functions do.
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+impl DateTime {
+    pub fn from_timestamp(seconds: i64) -> Self {
+        let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+            nanosecond: 0,
+        }
+    }
+
+    // Seconds since 1970-01-01 00:00:00, dropping any fraction
+    pub fn timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    // `YYYY-MM-DD`, optionally followed by a space or `T` and `HH:MM`,
+    // `HH:MM:SS` or `HH:MM:SS.fraction`, then optionally `Z` or `±HH:MM`
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let bytes = text.as_bytes();
+        let (year, month, day) = (number(bytes, 0, 4)?, number(bytes, 5, 2)?, number(bytes, 8, 2)?);
+        if bytes.get(4) != Some(&b'-') || bytes.get(7) != Some(&b'-') {
+            return None;
+        }
+        let (month, day) = (month as u8, day as u8);
+        let mut datetime = DateTime { year, month, day, hour: 0, minute: 0, second: 0, nanosecond: 0 };
+        let mut pos = 10;
+        if matches!(bytes.get(pos), Some(b' ' | b'T')) {
+            datetime.hour = number(bytes, pos + 1, 2)? as u8;
+            datetime.minute = number(bytes, pos + 4, 2)? as u8;
+            if bytes.get(pos + 3) != Some(&b':') {
+                return None;
+            }
+            pos += 6;
+            if bytes.get(pos) == Some(&b':') {
+                datetime.second = number(bytes, pos + 1, 2)? as u8;
+                pos += 3;
+                if bytes.get(pos) == Some(&b'.') {
+                    let digits = bytes[pos + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+                    if digits == 0 {
+                        return None;
+                    }
+                    // Nanosecond precision at most; further digits are dropped
+                    let fraction = &text[pos + 1..pos + 1 + digits.min(9)];
+                    datetime.nanosecond = fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32);
+                    pos += 1 + digits;
+                }
+            }
+        }
+        let offset = match &bytes[pos..] {
+            [] => 0,
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let minutes = number(bytes, pos + 1, 2)? * 60 + number(bytes, pos + 4, 2)?;
+                if *sign == b'+' {
+                    minutes
+                } else {
+                    -minutes
+                }
+            }
+            _ => return None,
+        };
+        if !datetime.is_valid() {
+            return None;
+        }
+        if offset != 0 {
+            let nanosecond = datetime.nanosecond;
+            datetime = DateTime::from_timestamp(datetime.timestamp() - offset * 60);
+            datetime.nanosecond = nanosecond;
+        }
+        Some(datetime)
+    }
+
+    fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+// The form SQLite's datetime() gives, with milliseconds when there are any
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        if self.nanosecond >= 1_000_000 {
+            write!(f, ".{:03}", self.nanosecond / 1_000_000)?;
+        }
+        Ok(())
+    }
+}
+
+// `len` ASCII digits at `start`
+fn number(bytes: &[u8], start: usize, len: usize) -> Option<i64> {
+    let digits = bytes.get(start..start + len)?;
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(digits.iter().fold(0, |n, digit| n * 10 + (digit - b'0') as i64))
+}
+
+fn days_in_month(year: i64, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar, and back,
+// counting in 400-year eras of 146097 days that start on March 1st so the
+// leap day falls at the end of each year
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u8;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    // Checked against Python's calendar.timegm
+    const KNOWN: [(&str, i64); 3] =
+        [("2024-02-29 12:34:56", 1_709_210_096), ("1969-12-31 23:59:59", -1), ("2000-01-01 00:00:00", 946_684_800)];
+
+    #[test]
+    fn iso_text_reads_as_the_known_timestamp() {
+        for (text, seconds) in KNOWN {
+            let datetime = Value::Text(text.to_string()).as_datetime().unwrap();
+            assert_eq!(datetime.timestamp(), seconds, "{}", text);
+            assert_eq!(datetime.to_string(), text);
+        }
+        let datetime = Value::Text("2024-02-29T14:34:56.250+02:00".to_string()).as_datetime().unwrap();
+        assert_eq!((datetime.timestamp(), datetime.nanosecond), (1_709_210_096, 250_000_000));
+        assert_eq!(datetime.to_string(), "2024-02-29 12:34:56.250");
+        assert_eq!(Value::Text("2023-02-29".to_string()).as_datetime(), None);
+    }
+
+    #[test]
+    fn epoch_integer_reads_as_the_known_date() {
+        for (text, seconds) in KNOWN {
+            let datetime = Value::Integer(seconds).as_datetime().unwrap();
+            assert_eq!(datetime.to_string(), text);
+            assert_eq!(DateTime::parse(&datetime.to_string()), Some(datetime));
+        }
+        assert_eq!(Value::Real(1.5).as_datetime(), None);
+    }
+}
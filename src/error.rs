@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SqliteError {
+    #[error("no such table: {name} (available tables: {})", available.join(", "))]
+    TableNotFound { name: String, available: Vec<String> },
+    #[error("page budget exceeded: more than {limit} pages read")]
+    PageBudgetExceeded { limit: u64 },
+    // A corrupt b-tree whose child pointers lead back to a page already on
+    // the walk, which would otherwise never finish
+    #[error("cycle detected at page {page}")]
+    CycleDetected { page: u32 },
+    #[error("page {page} is corrupt: {detail}")]
+    CorruptPage { page: u32, detail: String },
+    // No SQLite header, but the file is a whole number of pages of what
+    // looks like random bytes, as an encrypted (e.g. SQLCipher) database is
+    #[error("file is not a database: missing the SQLite header, and its {size} bytes look encrypted (SQLCipher?)")]
+    PossiblyEncrypted { size: u64 },
+}
@@ -0,0 +1,441 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+use crate::parser::{BinaryOp, Expr, ScalarFunction};
+use crate::value::{render, Collation, Value};
+
+// Evaluates an expression against a row, looking columns up through
+// `column`. Conditions follow SQLite: true and false are the integers 1 and 0,
+// and a comparison involving NULL is unknown (NULL). So is arithmetic on
+// NULL, and division by zero.
+pub fn eval(expr: &Expr, column: &dyn Fn(&str) -> Result<Value>) -> Result<Value> {
+    eval_collated(expr, column, &|_| Collation::Binary)
+}
+
+// Like eval, with text compared under the columns' collations: a comparison
+// takes the left operand's when it is a column, and otherwise the right's
+pub fn eval_collated(
+    expr: &Expr,
+    column: &dyn Fn(&str) -> Result<Value>,
+    collation: &dyn Fn(&str) -> Collation,
+) -> Result<Value> {
+    let eval = |expr: &Expr| eval_collated(expr, column, collation);
+    let value = match expr {
+        Expr::Column(name) => column(name)?,
+        Expr::Literal(value) => value.clone(),
+        Expr::Parameter(index) => bail!("parameter {} is not bound", index + 1),
+        Expr::Not(inner) => match truth(&eval(inner)?) {
+            Some(b) => boolean(!b),
+            None => Value::Null,
+        },
+        Expr::Binary { op: BinaryOp::And, left, right } => {
+            match (truth(&eval(left)?), truth(&eval(right)?)) {
+                (Some(false), _) | (_, Some(false)) => boolean(false),
+                (Some(true), Some(true)) => boolean(true),
+                _ => Value::Null,
+            }
+        }
+        Expr::Binary { op: BinaryOp::Or, left, right } => {
+            match (truth(&eval(left)?), truth(&eval(right)?)) {
+                (Some(true), _) | (_, Some(true)) => boolean(true),
+                (Some(false), Some(false)) => boolean(false),
+                _ => Value::Null,
+            }
+        }
+        Expr::Negate(inner) => match numeric(&eval(inner)?) {
+            Value::Integer(i) => i.checked_neg().map_or(Value::Real(-(i as f64)), Value::Integer),
+            Value::Real(r) => Value::Real(-r),
+            _ => Value::Null,
+        },
+        Expr::Binary { op: op @ (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div), left, right } => {
+            arithmetic(*op, &eval(left)?, &eval(right)?)
+        }
+        // True on a match; otherwise unknown if either side held a NULL
+        Expr::In { expr, list } => {
+            let value = eval(expr)?;
+            let order = match expr.as_ref() {
+                Expr::Column(name) => collation(name),
+                _ => Collation::Binary,
+            };
+            let mut result = if list.is_empty() { boolean(false) } else { Value::Null };
+            if value != Value::Null {
+                result = boolean(false);
+                for item in list.iter() {
+                    match compare(&value, &eval(item)?, order) {
+                        Some(Ordering::Equal) => return Ok(boolean(true)),
+                        Some(_) => {}
+                        None => result = Value::Null,
+                    }
+                }
+            }
+            result
+        }
+        Expr::InSelect { .. } => bail!("subquery was not run before evaluating the condition"),
+        Expr::Function { function, args } => {
+            let args = args.iter().map(eval).collect::<Result<Vec<_>>>()?;
+            call(*function, &args)?
+        }
+        // Both sides as text, as a number would be written
+        Expr::Binary { op: BinaryOp::Concat, left, right } => match (as_text(&eval(left)?), as_text(&eval(right)?)) {
+            (Some(left), Some(right)) => Value::Text(left + &right),
+            _ => Value::Null,
+        },
+        Expr::Binary { op: op @ (BinaryOp::Is | BinaryOp::IsNot), left, right } => {
+            let order = comparison_collation(left, right, collation);
+            let same = match (eval(left)?, eval(right)?) {
+                (Value::Null, Value::Null) => true,
+                (Value::Null, _) | (_, Value::Null) => false,
+                (left, right) => order.compare(&left, &right) == Ordering::Equal,
+            };
+            boolean(same == (*op == BinaryOp::Is))
+        }
+        Expr::Binary { op: BinaryOp::Glob, left, right } => {
+            match (as_text(&eval(left)?), as_text(&eval(right)?)) {
+                (Some(value), Some(pattern)) => boolean(glob_match(&pattern, &value)),
+                _ => Value::Null,
+            }
+        }
+        Expr::Binary { op, left, right } => {
+            let order = comparison_collation(left, right, collation);
+            let left = eval(left)?;
+            let right = eval(right)?;
+            match compare(&left, &right, order) {
+                None => Value::Null,
+                Some(ordering) => boolean(match op {
+                    BinaryOp::Eq => ordering == Ordering::Equal,
+                    BinaryOp::Ne => ordering != Ordering::Equal,
+                    BinaryOp::Lt => ordering == Ordering::Less,
+                    BinaryOp::Le => ordering != Ordering::Greater,
+                    BinaryOp::Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                }),
+            }
+        }
+    };
+    Ok(value)
+}
+
+// The parser has already checked the argument count
+fn call(function: ScalarFunction, args: &[Value]) -> Result<Value> {
+    let value = match (function, &args[0]) {
+        (ScalarFunction::Typeof, arg) => Value::Text(arg.type_name().to_string()),
+        // The value as an SQL literal, as it would be written in a statement
+        (ScalarFunction::Quote, arg) => Value::Text(Expr::Literal(arg.clone()).to_string()),
+        // Uppercase hex of a blob's bytes, or of anything else's text
+        (ScalarFunction::Hex, Value::Blob(_)) => Value::Text(render(&args[0])),
+        (ScalarFunction::Hex, arg) => {
+            Value::Text(as_text(arg).unwrap_or_default().bytes().map(|byte| format!("{:02X}", byte)).collect())
+        }
+        _ if args.iter().any(|arg| matches!(arg, Value::Null)) => Value::Null,
+        // Characters of text, bytes of a blob, and numbers as rendered
+        (ScalarFunction::Length, Value::Text(text)) => Value::Integer(text.chars().count() as i64),
+        (ScalarFunction::Length, Value::Blob(bytes)) => Value::Integer(bytes.len() as i64),
+        (ScalarFunction::Length, arg) => Value::Integer(render(arg).chars().count() as i64),
+        (ScalarFunction::Abs, Value::Integer(i)) => match i.checked_abs() {
+            Some(i) => Value::Integer(i),
+            None => bail!("integer overflow"),
+        },
+        // Text and blobs are converted to a real, as SQLite does
+        (ScalarFunction::Abs, arg) => Value::Real(real(&numeric(arg)).abs()),
+        // ASCII only, like SQLite built without ICU
+        (ScalarFunction::Upper, arg) => Value::Text(as_text(arg).unwrap_or_default().to_ascii_uppercase()),
+        (ScalarFunction::Lower, arg) => Value::Text(as_text(arg).unwrap_or_default().to_ascii_lowercase()),
+        // Counted in bytes for a blob, and in characters for anything else
+        (ScalarFunction::Substr, Value::Blob(bytes)) => {
+            let (start, end) = substr_range(bytes.len(), integer(&args[1]), args.get(2).map(integer));
+            Value::Blob(bytes[start..end].to_vec())
+        }
+        (ScalarFunction::Substr, arg) => {
+            let chars: Vec<char> = as_text(arg).unwrap_or_default().chars().collect();
+            let (start, end) = substr_range(chars.len(), integer(&args[1]), args.get(2).map(integer));
+            Value::Text(chars[start..end].iter().collect())
+        }
+        // 1-based position of the first match, or 0 when there is none;
+        // bytes when both are blobs, and characters otherwise
+        (ScalarFunction::Instr, arg) => {
+            let found = match (arg, &args[1]) {
+                (Value::Blob(_), Value::Blob(needle)) if needle.is_empty() => Some(0),
+                (Value::Blob(haystack), Value::Blob(needle)) => {
+                    haystack.windows(needle.len()).position(|window| window == needle.as_slice())
+                }
+                _ => {
+                    let haystack = as_text(arg).unwrap_or_default();
+                    let needle = as_text(&args[1]).unwrap_or_default();
+                    haystack.find(&needle).map(|i| haystack[..i].chars().count())
+                }
+            };
+            Value::Integer(found.map_or(0, |i| i as i64 + 1))
+        }
+    };
+    Ok(value)
+}
+
+// SQLite's substr(x, start, length) bounds as a range of `len` characters
+// or bytes. Positions count from 1, or back from the end when negative; a
+// start of 0 sits just before the first character, and a negative length
+// takes the characters before the start instead of after it.
+fn substr_range(len: usize, start: i64, length: Option<i64>) -> (usize, usize) {
+    let len = len as i64;
+    let mut start = start;
+    let mut length = length.unwrap_or(i64::MAX);
+    let backwards = length < 0;
+    if backwards {
+        length = length.saturating_neg();
+    }
+    if start < 0 {
+        start += len;
+        if start < 0 {
+            length = length.saturating_add(start).max(0);
+            start = 0;
+        }
+    } else if start > 0 {
+        start -= 1;
+    } else if length > 0 {
+        length -= 1;
+    }
+    if backwards {
+        start -= length;
+        if start < 0 {
+            length += start;
+            start = 0;
+        }
+    }
+    let start = start.min(len);
+    (start as usize, start.saturating_add(length).min(len) as usize)
+}
+
+// Integer arguments, read the way arithmetic reads numbers but truncated
+fn integer(value: &Value) -> i64 {
+    match numeric(value) {
+        Value::Integer(i) => i,
+        Value::Real(r) => r as i64,
+        _ => 0,
+    }
+}
+
+// GLOB and the case functions work on values as text; blobs by their bytes
+// rather than as hex
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Blob(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        value => Some(render(value)),
+    }
+}
+
+// Case-sensitive Unix-style matching as in SQLite's GLOB: `*` matches any
+// run of characters, `?` exactly one, and `[...]` one character from a set
+// such as `[a-c_]`, or outside it when the set starts with `^`. A `]`
+// straight after the opening bracket is part of the set.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    // Where to resume after the last `*`: its pattern position, and the
+    // value position it is currently standing in for
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, v));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match match_class(&pattern, p + 1, value[v]) {
+                Some((true, end)) => Some(end),
+                _ => None,
+            },
+            Some(&c) if c == value[v] => Some(p + 1),
+            _ => None,
+        };
+        match (step, star) {
+            (Some(next), _) => {
+                p = next;
+                v += 1;
+            }
+            // Let the last `*` swallow one more character and retry
+            (None, Some((after_star, start))) => {
+                star = Some((after_star, start + 1));
+                p = after_star;
+                v = start + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Matches `c` against the set starting at `start`, just past the `[`.
+// Returns whether it matched and the pattern position after the `]`, or
+// None when the set is never closed.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start;
+    let negated = pattern.get(i) == Some(&'^');
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let member = *pattern.get(i)?;
+        if member == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&end| end != ']') {
+            matched |= member <= c && c <= pattern[i + 2];
+            i += 3;
+        } else {
+            matched |= member == c;
+            i += 1;
+        }
+    }
+}
+
+// A WHERE clause keeps a row only when its condition is definitely true
+pub fn is_true(value: &Value) -> bool {
+    truth(value) == Some(true)
+}
+
+fn truth(value: &Value) -> Option<bool> {
+    match value {
+        Value::Null => None,
+        Value::Integer(i) => Some(*i != 0),
+        Value::Real(r) => Some(*r != 0.0),
+        Value::Text(s) => Some(s.trim().parse::<f64>().is_ok_and(|n| n != 0.0)),
+        Value::Blob(_) => Some(false),
+    }
+}
+
+// Integers stay integers unless the result overflows, which like any
+// operation involving a real gives a real
+fn arithmetic(op: BinaryOp, left: &Value, right: &Value) -> Value {
+    if matches!(left, Value::Null) || matches!(right, Value::Null) {
+        return Value::Null;
+    }
+    let (left, right) = (numeric(left), numeric(right));
+    if let (Value::Integer(a), Value::Integer(b)) = (&left, &right) {
+        let exact = match op {
+            BinaryOp::Add => a.checked_add(*b),
+            BinaryOp::Sub => a.checked_sub(*b),
+            BinaryOp::Mul => a.checked_mul(*b),
+            _ if *b == 0 => return Value::Null,
+            _ => a.checked_div(*b),
+        };
+        if let Some(result) = exact {
+            return Value::Integer(result);
+        }
+    }
+    let (a, b) = (real(&left), real(&right));
+    let result = match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Sub => a - b,
+        BinaryOp::Mul => a * b,
+        _ if b == 0.0 => return Value::Null,
+        _ => a / b,
+    };
+    // Such as Inf - Inf, which SQLite makes NULL as it does NaN on disk
+    if result.is_nan() {
+        Value::Null
+    } else {
+        Value::Real(result)
+    }
+}
+
+// Text used as a number reads as its longest numeric prefix, or 0 when it
+// has none; blobs count as 0
+fn numeric(value: &Value) -> Value {
+    match value {
+        Value::Text(text) => {
+            let text = text.trim_start();
+            let bytes = text.as_bytes();
+            let digits = |mut i: usize| {
+                while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+                    i += 1;
+                }
+                i
+            };
+            // [sign] digits [. digits] [e [sign] digits]
+            let mut end = digits(if matches!(bytes.first(), Some(b'+' | b'-')) { 1 } else { 0 });
+            let integer_end = end;
+            if bytes.get(end) == Some(&b'.') {
+                end = digits(end + 1);
+            }
+            if matches!(bytes.get(end), Some(b'e' | b'E')) {
+                let exponent = if matches!(bytes.get(end + 1), Some(b'+' | b'-')) { end + 2 } else { end + 1 };
+                if digits(exponent) > exponent {
+                    end = digits(exponent);
+                }
+            }
+            let prefix = &text[..end];
+            match (end == integer_end).then(|| prefix.parse::<i64>().ok()).flatten() {
+                Some(i) => Value::Integer(i),
+                None => prefix.parse::<f64>().map_or(Value::Integer(0), Value::Real),
+            }
+        }
+        Value::Blob(_) => Value::Integer(0),
+        other => other.clone(),
+    }
+}
+
+fn real(value: &Value) -> f64 {
+    match value {
+        Value::Integer(i) => *i as f64,
+        Value::Real(r) => *r,
+        _ => 0.0,
+    }
+}
+
+fn boolean(b: bool) -> Value {
+    Value::Integer(b as i64)
+}
+
+// Comparison operators: unknown if either side is NULL, otherwise SQLite's
+// storage-class ordering
+fn compare(left: &Value, right: &Value, collation: Collation) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        _ => Some(collation.compare(left, right)),
+    }
+}
+
+fn comparison_collation(left: &Expr, right: &Expr, collation: &dyn Fn(&str) -> Collation) -> Collation {
+    match (left, right) {
+        (Expr::Column(name), _) | (_, Expr::Column(name)) => collation(name),
+        _ => Collation::Binary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_run_of_characters() {
+        assert!(glob_match("A*", "Apple"));
+        assert!(glob_match("A*", "A"));
+        assert!(glob_match("*e", "Apple"));
+        assert!(glob_match("*pp*", "Apple"));
+        assert!(!glob_match("A*", "apple"));
+        assert!(!glob_match("*x*", "Apple"));
+    }
+
+    #[test]
+    fn question_mark_matches_exactly_one_character() {
+        assert!(glob_match("?pple", "Apple"));
+        assert!(glob_match("h?llo", "héllo"));
+        assert!(!glob_match("?pple", "pple"));
+        assert!(!glob_match("A???", "Apple"));
+    }
+
+    #[test]
+    fn classes_match_one_character_of_a_set() {
+        assert!(glob_match("[a-c]at", "bat"));
+        assert!(!glob_match("[a-c]at", "rat"));
+        assert!(!glob_match("[a-c]at", "Bat"));
+        assert!(glob_match("[^a-c]at", "rat"));
+        assert!(glob_match("[]x]", "]"));
+        assert!(glob_match("file[0-9].[ch]", "file7.h"));
+    }
+}
@@ -0,0 +1,447 @@
+// Builds structurally complete database images in memory, so tests and
+// benchmarks can get multi-page tables without a sqlite3 binary around.
+use crate::value::{compare_values, Value};
+
+pub const PAGE_SIZE: usize = 4096;
+
+pub struct FixtureBuilder {
+    page_size: usize,
+    pages: Vec<Vec<u8>>,
+    // type, name, tbl_name, rootpage, sql
+    schema: Vec<(String, String, String, u32, String)>,
+    auto_vacuum: bool,
+    // First freelist trunk page, 0 for none, and the free pages in all
+    freelist_trunk: u32,
+    free_pages: u32,
+}
+
+impl Default for FixtureBuilder {
+    fn default() -> Self {
+        FixtureBuilder::new(PAGE_SIZE)
+    }
+}
+
+impl FixtureBuilder {
+    pub fn new(page_size: usize) -> Self {
+        // Page 1 is filled in by build() once the schema is known
+        FixtureBuilder {
+            page_size,
+            pages: vec![Vec::new()],
+            schema: Vec::new(),
+            auto_vacuum: false,
+            freelist_trunk: 0,
+            free_pages: 0,
+        }
+    }
+
+    // Marks the image as auto-vacuumed, which reserves page 2 and every
+    // page after the run it covers as pointer-map pages. Call it before
+    // adding anything. The pointer-map entries are left zeroed, which is
+    // enough for readers that only need to tell those pages apart.
+    pub fn auto_vacuum(&mut self) -> &mut Self {
+        self.auto_vacuum = true;
+        self
+    }
+
+    pub fn add_table(&mut self, name: &str, sql: &str, rows: &[(i64, Vec<Value>)]) -> &mut Self {
+        let root_page = self.write_table_btree(rows);
+        self.schema.push(("table".to_string(), name.to_string(), name.to_string(), root_page, sql.to_string()));
+        self
+    }
+
+    // Leaves the pages deleting rows would: a freelist trunk page listing
+    // `leaves` free leaf pages after it. Each call adds a trunk in front of
+    // the ones before.
+    pub fn add_free_pages(&mut self, leaves: usize) -> &mut Self {
+        let mut trunk = vec![0; self.page_size];
+        trunk[0..4].copy_from_slice(&self.freelist_trunk.to_be_bytes());
+        trunk[4..8].copy_from_slice(&(leaves as u32).to_be_bytes());
+        let trunk_page = self.push_page(trunk);
+        for i in 0..leaves {
+            let leaf = self.push_page(vec![0; self.page_size]);
+            self.pages[trunk_page as usize - 1][8 + 4 * i..12 + 4 * i].copy_from_slice(&leaf.to_be_bytes());
+        }
+        self.freelist_trunk = trunk_page;
+        self.free_pages += 1 + leaves as u32;
+        self
+    }
+
+    pub fn add_view(&mut self, name: &str, sql: &str) -> &mut Self {
+        self.schema.push(("view".to_string(), name.to_string(), name.to_string(), 0, sql.to_string()));
+        self
+    }
+
+    // Each entry is the indexed columns followed by the row's rowid; they
+    // are sorted into index order here.
+    pub fn add_index(&mut self, name: &str, table: &str, sql: &str, entries: &[Vec<Value>]) -> &mut Self {
+        let mut entries = entries.to_vec();
+        entries.sort_by(|a, b| {
+            a.iter().zip(b.iter()).map(|(x, y)| compare_values(x, y)).find(|o| o.is_ne()).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let root_page = self.write_index_btree(&entries);
+        self.schema.push(("index".to_string(), name.to_string(), table.to_string(), root_page, sql.to_string()));
+        self
+    }
+
+    // The schema table is rooted at page 1. When its rows don't all fit
+    // there, they go to leaf pages of their own and page 1 becomes the
+    // interior page above them, as sqlite does once a schema outgrows it.
+    pub fn build(&mut self) -> Vec<u8> {
+        let rows: Vec<(i64, Vec<Value>)> = self
+            .schema
+            .iter()
+            .enumerate()
+            .map(|(i, (s_type, name, tbl_name, root_page, sql))| {
+                let values = vec![
+                    Value::Text(s_type.clone()),
+                    Value::Text(name.clone()),
+                    Value::Text(tbl_name.clone()),
+                    Value::Integer(*root_page as i64),
+                    Value::Text(sql.clone()),
+                ];
+                (i as i64 + 1, values)
+            })
+            .collect();
+        let cells: Vec<(i64, Vec<u8>)> =
+            rows.iter().map(|(rowid, values)| (*rowid, self.leaf_cell(*rowid, values))).collect();
+        let page_cells: Vec<Vec<u8>> = cells.iter().map(|(_, cell)| cell.clone()).collect();
+        self.pages[0] = if self.fits(100, 8, &page_cells) {
+            self.make_page(100, 13, &page_cells, None)
+        } else {
+            let children = self.write_table_levels(cells, 100);
+            let (right_most, _) = children[children.len() - 1];
+            self.make_page(100, 5, &interior_cells(&children[..children.len() - 1]), Some(right_most))
+        };
+        self.write_header();
+        self.pages.concat()
+    }
+
+    // Writes the rows as leaf pages plus however many interior levels they
+    // need, returning the root page number.
+    fn write_table_btree(&mut self, rows: &[(i64, Vec<Value>)]) -> u32 {
+        let cells = rows.iter().map(|(rowid, values)| (*rowid, self.leaf_cell(*rowid, values))).collect();
+        let level = self.write_table_levels(cells, 0);
+        if level.len() == 1 {
+            return level[0].0;
+        }
+        self.write_interior(&level).0
+    }
+
+    // Leaf pages for the rows' cells, then interior levels above them until
+    // the children left fit on one interior page whose header starts at
+    // `header_offset`. Returns those children with their largest rowids.
+    fn write_table_levels(&mut self, rows: Vec<(i64, Vec<u8>)>, header_offset: usize) -> Vec<(u32, i64)> {
+        let mut level: Vec<(u32, i64)> = Vec::new();
+        let mut cells = Vec::new();
+        let mut max_rowid = 0;
+        for (rowid, cell) in rows {
+            cells.push(cell);
+            if !self.fits(0, 8, &cells) {
+                let cell = cells.pop();
+                let page = self.make_page(0, 13, &cells, None);
+                level.push((self.push_page(page), max_rowid));
+                cells = cell.into_iter().collect();
+            }
+            max_rowid = rowid;
+        }
+        let page = self.make_page(0, 13, &cells, None);
+        level.push((self.push_page(page), max_rowid));
+
+        while level.len() > 1 && !self.fits(header_offset, 12, &interior_cells(&level[..level.len() - 1])) {
+            let mut parents = Vec::new();
+            let mut group: Vec<(u32, i64)> = Vec::new();
+            for child in level {
+                group.push(child);
+                if !self.fits(0, 12, &interior_cells(&group)) {
+                    let child = group.pop();
+                    parents.push(self.write_interior(&group));
+                    group = child.into_iter().collect();
+                }
+            }
+            parents.push(self.write_interior(&group));
+            level = parents;
+        }
+        level
+    }
+
+    // A table leaf cell. A payload too big for the page keeps the part
+    // sqlite would keep locally and continues on a chain of overflow pages,
+    // written here.
+    fn leaf_cell(&mut self, rowid: i64, values: &[Value]) -> Vec<u8> {
+        let payload = encode_record(values);
+        let mut cell = encode_varint(payload.len() as u64);
+        cell.extend(encode_varint(rowid as u64));
+        let usable = self.page_size;
+        let max_local = usable - 35;
+        if payload.len() <= max_local {
+            cell.extend(payload);
+            return cell;
+        }
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let local = match min_local + (payload.len() - min_local) % (usable - 4) {
+            local if local > max_local => min_local,
+            local => local,
+        };
+        cell.extend_from_slice(&payload[..local]);
+        cell.extend(self.write_overflow(&payload[local..]).to_be_bytes());
+        cell
+    }
+
+    // Overflow pages each start with the next page's number, 0 on the last
+    fn write_overflow(&mut self, rest: &[u8]) -> u32 {
+        let chunks: Vec<&[u8]> = rest.chunks(self.page_size - 4).collect();
+        let first = self.page_after(self.pages.len() as u32);
+        let mut page_number = first;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let next = if i + 1 < chunks.len() { self.page_after(page_number) } else { 0 };
+            let mut page = vec![0; self.page_size];
+            page[..4].copy_from_slice(&next.to_be_bytes());
+            page[4..4 + chunk.len()].copy_from_slice(chunk);
+            self.push_page(page);
+            page_number = next;
+        }
+        first
+    }
+
+    // Unlike table b-trees, index entries on interior pages are not repeated
+    // in the leaves: the entry separating two pages moves up a level.
+    fn write_index_btree(&mut self, entries: &[Vec<Value>]) -> u32 {
+        let max_local = (self.page_size - 12) * 64 / 255 - 23;
+        let mut pages = Vec::new();
+        let mut separators: Vec<Vec<u8>> = Vec::new();
+        let mut cells = Vec::new();
+        for entry in entries.iter() {
+            let record = encode_record(entry);
+            assert!(record.len() <= max_local, "fixture index entries must not overflow");
+            cells.push(index_cell(None, &record));
+            if !self.fits(0, 8, &cells) {
+                cells.pop();
+                let page = self.make_page(0, 10, &cells, None);
+                pages.push(self.push_page(page));
+                separators.push(record);
+                cells = Vec::new();
+            }
+        }
+        if cells.is_empty() {
+            // The last entry became a separator with nothing after it
+            if let Some(record) = separators.pop() {
+                cells.push(index_cell(None, &record));
+            }
+        }
+        let page = self.make_page(0, 10, &cells, None);
+        pages.push(self.push_page(page));
+
+        while pages.len() > 1 {
+            let mut parents = Vec::new();
+            let mut parent_separators = Vec::new();
+            let mut cells = Vec::new();
+            for (i, separator) in separators.iter().enumerate() {
+                cells.push(index_cell(Some(pages[i]), separator));
+                if !self.fits(0, 12, &cells) {
+                    cells.pop();
+                    let page = self.make_page(0, 2, &cells, Some(pages[i]));
+                    parents.push(self.push_page(page));
+                    parent_separators.push(separator.clone());
+                    cells = Vec::new();
+                }
+            }
+            let page = self.make_page(0, 2, &cells, pages.last().copied());
+            parents.push(self.push_page(page));
+            pages = parents;
+            separators = parent_separators;
+        }
+        pages[0]
+    }
+
+    // Every child but the last becomes a (left child, key) cell; the last one
+    // is the right-most pointer.
+    fn write_interior(&mut self, children: &[(u32, i64)]) -> (u32, i64) {
+        let (right_most, max_key) = children[children.len() - 1];
+        let page = self.make_page(0, 5, &interior_cells(&children[..children.len() - 1]), Some(right_most));
+        (self.push_page(page), max_key)
+    }
+
+    fn push_page(&mut self, page: Vec<u8>) -> u32 {
+        if self.is_pointer_map(self.pages.len() as u32 + 1) {
+            self.pages.push(vec![0; self.page_size]);
+        }
+        self.pages.push(page);
+        self.pages.len() as u32
+    }
+
+    fn is_pointer_map(&self, page_number: u32) -> bool {
+        let span = (self.page_size / 5 + 1) as u32;
+        self.auto_vacuum && page_number >= 2 && (page_number - 2) % span == 0
+    }
+
+    // The number push_page gives the page after `page_number`
+    fn page_after(&self, page_number: u32) -> u32 {
+        if self.is_pointer_map(page_number + 1) {
+            page_number + 2
+        } else {
+            page_number + 1
+        }
+    }
+
+    fn fits(&self, header_offset: usize, header_size: usize, cells: &[Vec<u8>]) -> bool {
+        let used: usize = cells.iter().map(|cell| cell.len() + 2).sum();
+        header_offset + header_size + used <= self.page_size
+    }
+
+    fn make_page(&self, header_offset: usize, page_type: u8, cells: &[Vec<u8>], right_most: Option<u32>) -> Vec<u8> {
+        let mut page = vec![0; self.page_size];
+        let header_size = if right_most.is_some() { 12 } else { 8 };
+        let mut content = self.page_size;
+        let mut pointer = header_offset + header_size;
+        for cell in cells.iter() {
+            content -= cell.len();
+            page[content..content + cell.len()].copy_from_slice(cell);
+            page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+            pointer += 2;
+        }
+        page[header_offset] = page_type;
+        page[header_offset + 3..header_offset + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        page[header_offset + 5..header_offset + 7].copy_from_slice(&(content as u16).to_be_bytes());
+        if let Some(right_most) = right_most {
+            page[header_offset + 8..header_offset + 12].copy_from_slice(&right_most.to_be_bytes());
+        }
+        page
+    }
+
+    fn write_header(&mut self) {
+        let page_count = self.pages.len() as u32;
+        let page_size = if self.page_size == 65536 { 1 } else { self.page_size as u16 };
+        let header = &mut self.pages[0];
+        header[..16].copy_from_slice(b"SQLite format 3\0");
+        header[16..18].copy_from_slice(&page_size.to_be_bytes());
+        // File format versions, reserved bytes and payload fractions
+        header[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+        header[24..28].copy_from_slice(&1u32.to_be_bytes());
+        header[28..32].copy_from_slice(&page_count.to_be_bytes());
+        header[32..36].copy_from_slice(&self.freelist_trunk.to_be_bytes());
+        header[36..40].copy_from_slice(&self.free_pages.to_be_bytes());
+        header[40..44].copy_from_slice(&1u32.to_be_bytes());
+        header[44..48].copy_from_slice(&4u32.to_be_bytes());
+        // Auto-vacuum images record their largest root page
+        if self.auto_vacuum {
+            let largest_root = self.schema.iter().map(|(_, _, _, root_page, _)| *root_page).max().unwrap_or(1);
+            header[52..56].copy_from_slice(&largest_root.max(1).to_be_bytes());
+        }
+        header[56..60].copy_from_slice(&1u32.to_be_bytes());
+        header[92..96].copy_from_slice(&1u32.to_be_bytes());
+        header[96..100].copy_from_slice(&3_040_001u32.to_be_bytes());
+    }
+}
+
+// A single table `t(id INTEGER PRIMARY KEY, name TEXT, score REAL)` with
+// `rows` rows, spread over as many pages as it takes.
+pub fn table_fixture(rows: usize) -> Vec<u8> {
+    let rows: Vec<(i64, Vec<Value>)> = (1..=rows as i64)
+        .map(|id| (id, vec![Value::Null, Value::Text(format!("name {}", id)), Value::Real(id as f64 / 4.0)]))
+        .collect();
+    FixtureBuilder::default()
+        .add_table("t", "CREATE TABLE t(id INTEGER PRIMARY KEY, name TEXT, score REAL)", &rows)
+        .build()
+}
+
+// `t(id INTEGER PRIMARY KEY, a TEXT, b INTEGER)` with a two-column index
+// `idx_ab` on (a, b). Column a cycles through a handful of names and b
+// through ten values, so every (a, b) pair appears several times.
+pub fn index_fixture(rows: usize) -> Vec<u8> {
+    let names = ["apple", "banana", "cherry", "damson", "elder"];
+    let rows: Vec<(i64, Vec<Value>)> = (1..=rows as i64)
+        .map(|id| {
+            let name = names[id as usize % names.len()];
+            (id, vec![Value::Null, Value::Text(name.to_string()), Value::Integer(id % 10)])
+        })
+        .collect();
+    let entries: Vec<Vec<Value>> =
+        rows.iter().map(|(id, values)| vec![values[1].clone(), values[2].clone(), Value::Integer(*id)]).collect();
+    FixtureBuilder::default()
+        .add_table("t", "CREATE TABLE t(id INTEGER PRIMARY KEY, a TEXT, b INTEGER)", &rows)
+        .add_index("idx_ab", "t", "CREATE INDEX idx_ab ON t(a, b)", &entries)
+        .build()
+}
+
+fn interior_cells(children: &[(u32, i64)]) -> Vec<Vec<u8>> {
+    children
+        .iter()
+        .map(|(page, key)| {
+            let mut cell = page.to_be_bytes().to_vec();
+            cell.extend(encode_varint(*key as u64));
+            cell
+        })
+        .collect()
+}
+
+// Index cells are the same on both page types, interior ones just start
+// with their left child's page number
+fn index_cell(left_child: Option<u32>, record: &[u8]) -> Vec<u8> {
+    let mut cell = left_child.map(|page| page.to_be_bytes().to_vec()).unwrap_or_default();
+    cell.extend(encode_varint(record.len() as u64));
+    cell.extend_from_slice(record);
+    cell
+}
+
+pub fn encode_record(values: &[Value]) -> Vec<u8> {
+    let mut serials = Vec::new();
+    let mut body = Vec::new();
+    for value in values.iter() {
+        let serial = match value {
+            Value::Null => 0,
+            Value::Integer(0) => 8,
+            Value::Integer(1) => 9,
+            Value::Integer(i) => {
+                let (serial, size) = match *i {
+                    -0x80..=0x7f => (1, 1),
+                    -0x8000..=0x7fff => (2, 2),
+                    -0x80_0000..=0x7f_ffff => (3, 3),
+                    -0x8000_0000..=0x7fff_ffff => (4, 4),
+                    -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+                    _ => (6, 8),
+                };
+                body.extend_from_slice(&i.to_be_bytes()[8 - size..]);
+                serial
+            }
+            Value::Real(r) => {
+                body.extend_from_slice(&r.to_be_bytes());
+                7
+            }
+            Value::Text(s) => {
+                body.extend_from_slice(s.as_bytes());
+                s.len() as u64 * 2 + 13
+            }
+            Value::Blob(b) => {
+                body.extend_from_slice(b);
+                b.len() as u64 * 2 + 12
+            }
+        };
+        serials.extend(encode_varint(serial));
+    }
+    // The header size counts its own varint
+    let mut header_size = serials.len() + 1;
+    if encode_varint(header_size as u64).len() > 1 {
+        header_size += 1;
+    }
+    let mut record = encode_varint(header_size as u64);
+    record.extend(serials);
+    record.extend(body);
+    record
+}
+
+pub fn encode_varint(value: u64) -> Vec<u8> {
+    if value > 0x00ff_ffff_ffff_ffff {
+        // Nine bytes: eight 7-bit groups, then a full final byte
+        let mut bytes: Vec<u8> = (0..8).rev().map(|i| ((value >> (8 + 7 * i)) & 0x7f) as u8 | 0x80).collect();
+        bytes.push(value as u8);
+        return bytes;
+    }
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        bytes.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
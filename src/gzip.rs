@@ -0,0 +1,366 @@
+// Minimal gzip (RFC 1952) / DEFLATE (RFC 1951) decoder, enough to inflate
+// an archived database into memory before parsing it.
+use anyhow::{bail, Result};
+
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+// Largest output `decompress` inflates to. A few kilobytes of deflate can
+// expand to gigabytes, so a crafted file must not be able to exhaust memory.
+pub const MAX_OUTPUT: usize = 1 << 30;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] =
+    [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order in which code length code lengths are stored in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.len() >= 2 && data[..2] == MAGIC
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    decompress_with_limit(data, MAX_OUTPUT)
+}
+
+// Fails rather than producing more than `limit` bytes
+fn decompress_with_limit(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    if data.len() < 18 || !is_gzip(data) {
+        bail!("not a gzip file");
+    }
+    if data[2] != 8 {
+        bail!("unsupported gzip compression method {}", data[2]);
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    // FEXTRA
+    if flags & 0x04 != 0 {
+        let len = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+        pos += 2 + len;
+    }
+    // FNAME and FCOMMENT are NUL-terminated
+    for flag in [0x08, 0x10] {
+        if flags & flag != 0 {
+            while pos < data.len() && data[pos] != 0 {
+                pos += 1;
+            }
+            pos += 1;
+        }
+    }
+    // FHCRC
+    if flags & 0x02 != 0 {
+        pos += 2;
+    }
+    if pos >= data.len() {
+        bail!("truncated gzip header");
+    }
+
+    let mut reader = BitReader { data, pos, bit_buf: 0, bit_count: 0 };
+    let out = inflate(&mut reader, limit)?;
+
+    let trailer = reader.pos;
+    if trailer + 8 > data.len() {
+        bail!("truncated gzip trailer");
+    }
+    let crc = u32::from_le_bytes(data[trailer..trailer + 4].try_into()?);
+    let size = u32::from_le_bytes(data[trailer + 4..trailer + 8].try_into()?);
+    if size != out.len() as u32 || crc != crc32(&out) {
+        bail!("gzip checksum mismatch");
+    }
+    Ok(out)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        while self.bit_count < count {
+            if self.pos >= self.data.len() {
+                bail!("unexpected end of compressed data");
+            }
+            self.bit_buf |= (self.data[self.pos] as u32) << self.bit_count;
+            self.pos += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u64 << count) - 1) as u32;
+        self.bit_buf >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    // Drops any partial byte, as stored blocks start on a byte boundary
+    fn align(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+// Canonical Huffman code: number of codes of each bit length, and the symbols
+// ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths.iter() {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("invalid Huffman code")
+    }
+}
+
+fn inflate(reader: &mut BitReader, limit: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let pos = reader.pos;
+                if pos + 4 > reader.data.len() {
+                    bail!("truncated stored block");
+                }
+                // LEN is followed by NLEN, its one's complement
+                let len = u16::from_le_bytes([reader.data[pos], reader.data[pos + 1]]);
+                let nlen = u16::from_le_bytes([reader.data[pos + 2], reader.data[pos + 3]]);
+                if nlen != !len {
+                    bail!("stored block length {} doesn't match its complement {}", len, nlen);
+                }
+                let (len, start) = (len as usize, pos + 4);
+                if start + len > reader.data.len() {
+                    bail!("truncated stored block");
+                }
+                check_limit(out.len() + len, limit)?;
+                out.extend_from_slice(&reader.data[start..start + len]);
+                reader.pos = start + len;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(reader, &mut out, &literals, &distances, limit)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_tables(reader)?;
+                inflate_block(reader, &mut out, &literals, &distances, limit)?;
+            }
+            _ => bail!("invalid deflate block type"),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn read_dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_count) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + reader.bits(2)? as usize),
+                None => bail!("repeat code with no previous length"),
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        lengths.extend(std::iter::repeat(value).take(repeat));
+    }
+    if lengths.len() > literal_count + distance_count {
+        bail!("code lengths overflow the dynamic block header");
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: usize,
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => {
+                check_limit(out.len() + 1, limit)?;
+                out.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= 30 {
+                    bail!("invalid distance code {}", index);
+                }
+                let distance = DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    bail!("distance {} reaches before the start of the output", distance);
+                }
+                check_limit(out.len() + len, limit)?;
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => bail!("invalid literal/length code {}", symbol),
+        }
+    }
+}
+
+fn check_limit(size: usize, limit: usize) -> Result<()> {
+    if size > limit {
+        bail!("decompressed data is larger than the {}-byte limit", limit);
+    }
+    Ok(())
+}
+
+// The CRC-32 gzip's trailer carries, of the uncompressed data
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Made with Python's zlib, one per block type
+    const STORED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x13, 0x00, 0xec, 0xff, 0x68, 0x65, 0x6c,
+        0x6c, 0x6f, 0x2c, 0x20, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x20, 0x62, 0x6c, 0x6f, 0x63, 0x6b, 0xf4, 0xc7,
+        0x22, 0xc7, 0x13, 0x00, 0x00, 0x00,
+    ];
+    const FIXED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x85, 0xb4,
+        0xcc, 0x8a, 0xd4, 0x14, 0x00, 0xf2, 0xc4, 0xcd, 0x40, 0x12, 0x00, 0x00, 0x00,
+    ];
+    const DYNAMIC: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x95, 0xcb, 0xd9, 0x15, 0x40, 0x30, 0x14, 0x45,
+        0xd1, 0x7f, 0x55, 0xdc, 0x12, 0xcc, 0x2c, 0xdd, 0x20, 0x31, 0xf3, 0x08, 0x11, 0x54, 0xcf, 0xd2, 0xc1, 0xfd,
+        0x3e, 0x67, 0x1b, 0x71, 0xf0, 0x0b, 0x1c, 0x9d, 0xc6, 0x66, 0xfb, 0x7a, 0x44, 0x65, 0xc4, 0x2d, 0x68, 0xe4,
+        0xc2, 0x60, 0xe7, 0x75, 0x87, 0x9c, 0xda, 0xfc, 0x79, 0x2a, 0x9f, 0x1b, 0x4a, 0x5a, 0xef, 0x1b, 0x10, 0xf0,
+        0x24, 0xe4, 0x49, 0xc4, 0x93, 0x98, 0x27, 0x09, 0x4f, 0x52, 0x9e, 0x64, 0x3c, 0xc9, 0x39, 0xf2, 0x02, 0x0f,
+        0xdf, 0xde, 0x02, 0xcb, 0x01, 0x00, 0x00,
+    ];
+
+    fn block_type(gzip: &[u8]) -> u8 {
+        (gzip[10] >> 1) & 3
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(block_type(STORED), 0);
+        assert_eq!(decompress(STORED).unwrap(), b"hello, stored block");
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!(block_type(FIXED), 1);
+        assert_eq!(decompress(FIXED).unwrap(), b"abcabcabcabc fixed");
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        assert_eq!(block_type(DYNAMIC), 2);
+        let line = |i| format!("row {}: the quick brown fox jumps over the lazy dog\n", i);
+        let expected: String = (0..9).map(line).collect();
+        assert_eq!(decompress(DYNAMIC).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn corrupt_input_is_an_error() {
+        let mut flipped = FIXED.to_vec();
+        let crc = flipped.len() - 8;
+        flipped[crc] ^= 1;
+        assert_eq!(decompress(&flipped).unwrap_err().to_string(), "gzip checksum mismatch");
+        assert!(decompress(&STORED[..STORED.len() - 12]).is_err());
+        assert_eq!(decompress(b"SQLite format 3\0 and more").unwrap_err().to_string(), "not a gzip file");
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn stored_block_length_must_match_its_complement() {
+        let mut broken = STORED.to_vec();
+        broken[13] ^= 1;
+        let err = decompress(&broken).unwrap_err();
+        assert_eq!(err.to_string(), "stored block length 19 doesn't match its complement 65517");
+    }
+
+    #[test]
+    fn output_past_the_limit_is_an_error() {
+        assert_eq!(decompress_with_limit(FIXED, 18).unwrap(), b"abcabcabcabc fixed");
+        let err = decompress_with_limit(FIXED, 17).unwrap_err();
+        assert_eq!(err.to_string(), "decompressed data is larger than the 17-byte limit");
+        assert!(decompress_with_limit(STORED, 18).is_err());
+        assert!(decompress_with_limit(DYNAMIC, 100).is_err());
+    }
+}
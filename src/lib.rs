@@ -0,0 +1,341 @@
+pub mod database;
+#[cfg(feature = "datetime")]
+pub mod datetime;
+pub mod error;
+pub mod eval;
+pub mod fixture;
+#[cfg(feature = "gzip")]
+pub mod gzip;
+#[cfg(all(unix, target_pointer_width = "64"))]
+pub mod mmap;
+pub mod output;
+pub mod page;
+pub mod parser;
+pub mod planner;
+pub mod query;
+pub mod schema;
+pub mod tokenizer;
+pub mod value;
+
+pub use value::decode_record;
+
+use anyhow::{bail, Result};
+use error::SqliteError;
+use page::{btree_header_offset, PageType};
+use value::{decode_text, decode_value, record_header, serial_size, TextEncoding, Value};
+
+// A cell's framing, parsed without decoding its record
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub offset: u16,
+    // Interior cells only
+    pub left_child: Option<u32>,
+    // Zero for interior table cells, which carry only a key
+    pub payload_size: u64,
+    // The rowid of a table leaf cell, or the key of an interior table cell
+    pub rowid: Option<i64>,
+    // Where the payload starts on the page
+    pub content_offset: usize,
+}
+impl Cell {
+    pub fn parse(page: &[u8], page_type: PageType, offset: usize) -> Result<Self> {
+        let mut cursor = offset;
+        let mut left_child = None;
+        if matches!(page_type, PageType::InteriorTable | PageType::InteriorIndex) {
+            left_child = Some(u32::from_be_bytes([page[cursor], page[cursor + 1], page[cursor + 2], page[cursor + 3]]));
+            cursor += 4;
+        }
+        let mut payload_size = 0;
+        if page_type != PageType::InteriorTable {
+            let (size, len) = decode_varint(&page[cursor..])?;
+            payload_size = size;
+            cursor += len;
+        }
+        let mut rowid = None;
+        if matches!(page_type, PageType::InteriorTable | PageType::LeafTable) {
+            let (key, len) = decode_varint(&page[cursor..])?;
+            rowid = Some(key as i64);
+            cursor += len;
+        }
+        Ok(Cell { offset: offset as u16, left_child, payload_size, rowid, content_offset: cursor })
+    }
+}
+pub fn get_db_info(buffer: &[u8], page_size: usize) -> DbInfo {
+    let header_offset = btree_header_offset(1);
+    let page_header_byte = buffer[header_offset];
+    let page_header_size = match page_header_byte {
+        13 => 8,
+        _ => 12,
+    };
+    let page_header = &buffer[header_offset..header_offset + page_header_size];
+    let tbl_count = u16::from_be_bytes([page_header[3], page_header[ 4]]);
+    let user_version = u32::from_be_bytes([buffer[60], buffer[61], buffer[62], buffer[63]]);
+    let application_id = u32::from_be_bytes([buffer[68], buffer[69], buffer[70], buffer[71]]);
+    DbInfo {
+        no_tables: tbl_count as usize,
+        db_page_size: page_size,
+        user_version,
+        application_id,
+        read_version: buffer[18],
+        write_version: buffer[19],
+        schema_format: u32::from_be_bytes([buffer[44], buffer[45], buffer[46], buffer[47]]),
+        sqlite_version: u32::from_be_bytes([buffer[96], buffer[97], buffer[98], buffer[99]]),
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct RecordHeader {
+    size: usize,
+    rowid: usize,
+    header_size: usize,
+    type_size: usize,
+    name_size: usize,
+    tbl_name_size: usize,
+    root_page_serial: u64,
+    sql_size: usize,
+}
+
+impl RecordHeader {
+    // `header_size` counts the size varint itself, which can take more than
+    // one byte, as well as the serial types after it
+    fn new(serials: &[u64], payload_size: usize, rowid: usize, header_size: usize) -> Result<Self> {
+        if serials.len() < 5 {
+            bail!("schema record {} has {} columns, not 5", rowid, serials.len());
+        }
+        if header_size > payload_size {
+            bail!("schema record {} header runs past its {} byte payload", rowid, payload_size);
+        }
+        // serial_size rather than the text formula, as the automatic
+        // indexes behind UNIQUE and PRIMARY KEY constraints have a NULL sql
+        let type_size = serial_size(serials[0]);
+        let name_size = serial_size(serials[1]);
+        let tbl_name_size = serial_size(serials[2]);
+        let root_page_serial = serials[3];
+        let sql_size = serial_size(serials[4]);
+
+        Ok(RecordHeader {
+            size: payload_size,
+            rowid, 
+            header_size,
+            type_size,
+            name_size,
+            tbl_name_size,
+            root_page_serial,
+            sql_size,
+        })
+    }
+}
+#[deprecated(note = "use value::decode_text, which handles UTF-8 and UTF-16")]
+pub fn convert_from_ascii(arr: &[u8]) -> String {
+    let mut res = String::new();
+    for i in arr.iter() {
+        res.push(*i as char);
+    }
+    res
+}
+// Fails when the buffer ends before the varint does, including when it's
+// empty, rather than returning a value that advances by nothing
+pub fn decode_varint(buf: &[u8]) -> Result<(u64, usize)> {
+    let mut value: u64 = 0;
+    let mut consumed = 0;
+
+    for &b in buf.iter().take(9) {
+        consumed += 1;
+
+        if b < 0x80 {
+            // last byte: full 8 bits
+            value = (value << 7) | (b as u64);
+            return Ok((value, consumed));
+        } else {
+            // continuation byte: lower 7 bits only
+            value = (value << 7) | ((b & 0x7F) as u64);
+        }
+    }
+    if consumed < 9 {
+        bail!("varint runs past the end of the buffer ({} bytes)", buf.len());
+    }
+
+    Ok((value, consumed))
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub s_type: String,
+    pub name: String,
+    pub tbl_name: String,
+    pub root_page: usize,
+    pub sql: String,
+    pub header: RecordHeader,
+}
+
+impl Record {
+    fn new(record_payload: &[u8], record_header: RecordHeader, encoding: TextEncoding) -> Self {
+        let text = |bytes: &[u8]| decode_text(bytes, encoding).into_owned();
+        let mut i = 0;
+        let s_type = text(&record_payload[i..record_header.type_size + i]);
+        i += record_header.type_size;
+        let name = text(&record_payload[i..record_header.name_size + i]);
+        i += record_header.name_size;
+        let tbl_name = text(&record_payload[i..record_header.tbl_name_size + i]);
+        i += record_header.tbl_name_size;
+        let root_page = match decode_value(record_header.root_page_serial, &record_payload[i..], encoding) {
+            Ok(Value::Integer(page)) => page as usize,
+            _ => 0,
+        };
+        i += serial_size(record_header.root_page_serial);
+        let sql = text(&record_payload[i..record_header.sql_size + i]);
+        Record { s_type, name, tbl_name, root_page, sql, header: record_header }
+    }
+
+    // A whole schema table row, read through any overflow pages
+    pub fn from_payload(rowid: i64, payload: &[u8], encoding: TextEncoding) -> Result<Self> {
+        let (header_size, serials) = record_header(payload)?;
+        let header = RecordHeader::new(&serials, payload.len(), rowid as usize, header_size)?;
+        Ok(Record::new(&payload[header_size..], header, encoding))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Records {
+    records: Vec<Record>
+}
+
+impl Records {
+    pub fn new() -> Self {
+        Records { records: Vec::new() }
+    }
+    pub fn add_record(&mut self, record: Record) {
+        self.records.push(record);
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, Record> {
+        self.records.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+    pub fn contains(&self, tbl_name: String) -> bool {
+        for rec in self.records.iter() {
+            if rec.tbl_name.eq_ignore_ascii_case(&tbl_name) {
+                return true;
+            }
+        }
+        false
+    }
+    pub fn find(&self, tbl_name: &str) -> Option<&Record> {
+        self.records.iter().find(|rec| rec.s_type == "table" && rec.tbl_name.eq_ignore_ascii_case(tbl_name))
+    }
+    pub fn find_view(&self, name: &str) -> Option<&Record> {
+        self.records.iter().find(|rec| rec.s_type == "view" && rec.name.eq_ignore_ascii_case(name))
+    }
+    // Indexes on the table that have a CREATE INDEX statement, which leaves
+    // out the automatic ones behind UNIQUE and PRIMARY KEY constraints
+    pub fn indexes(&self, tbl_name: &str) -> Vec<&Record> {
+        self.records
+            .iter()
+            .filter(|rec| rec.s_type == "index" && rec.tbl_name.eq_ignore_ascii_case(tbl_name) && !rec.sql.is_empty())
+            .collect()
+    }
+    pub fn table_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.records.iter().filter(|rec| rec.s_type == "table").map(|rec| rec.tbl_name.clone()).collect();
+        names.sort();
+        names
+    }
+    pub fn not_found(&self, tbl_name: &str) -> SqliteError {
+        SqliteError::TableNotFound { name: tbl_name.to_string(), available: self.table_names() }
+    }
+}
+
+impl<'a> IntoIterator for &'a Records {
+    type Item = &'a Record;
+    type IntoIter = std::slice::Iter<'a, Record>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct DbInfo {
+    pub no_tables: usize,
+    pub db_page_size: usize,
+    // Stamps applications put in the header, at offsets 60 and 68
+    pub user_version: u32,
+    pub application_id: u32,
+    // File format versions at offsets 18 and 19: 1 for legacy rollback
+    // journalling, 2 for WAL
+    pub read_version: u8,
+    pub write_version: u8,
+    // Schema format number (offset 44), 1 to 4
+    pub schema_format: u32,
+    // SQLITE_VERSION_NUMBER of the library that last wrote the file (offset 96)
+    pub sqlite_version: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::encode_record;
+
+    fn schema_row(sql: &str) -> Vec<Value> {
+        let text = |s: &str| Value::Text(s.to_string());
+        vec![text("table"), text("t"), text("t"), Value::Integer(2), text(sql)]
+    }
+
+    #[test]
+    fn schema_record_with_a_two_byte_header_size() {
+        let payload = encode_record(&schema_row("CREATE TABLE t(x)"));
+        // The same record with its header size written as a 2-byte varint,
+        // which counts its own extra byte
+        let mut padded = vec![0x80, payload[0] + 1];
+        padded.extend_from_slice(&payload[1..]);
+        let record = Record::from_payload(1, &padded, TextEncoding::Utf8).unwrap();
+        assert_eq!((record.s_type.as_str(), record.name.as_str(), record.root_page), ("table", "t", 2));
+        assert_eq!(record.sql, "CREATE TABLE t(x)");
+        assert_eq!(record.header.header_size, payload[0] as usize + 1);
+        assert_eq!(record.header.sql_size, "CREATE TABLE t(x)".len());
+    }
+
+    #[test]
+    fn malformed_schema_records_are_errors() {
+        let payload = encode_record(&schema_row("CREATE TABLE t(x)"));
+        // A header size smaller than the varint holding it
+        let mut bad = vec![0x80, 0x01];
+        bad.extend_from_slice(&payload[1..]);
+        assert!(Record::from_payload(1, &bad, TextEncoding::Utf8).is_err());
+        let short = encode_record(&[Value::Text("table".to_string()), Value::Text("t".to_string())]);
+        let err = Record::from_payload(1, &short, TextEncoding::Utf8).unwrap_err();
+        assert_eq!(err.to_string(), "schema record 1 has 2 columns, not 5");
+    }
+
+    #[test]
+    fn records_iterate_in_schema_order() {
+        let image = crate::fixture::FixtureBuilder::default()
+            .add_table("apples", "CREATE TABLE apples(name TEXT)", &[])
+            .add_index("idx_name", "apples", "CREATE INDEX idx_name ON apples(name)", &[])
+            .add_table("oranges", "CREATE TABLE oranges(name TEXT)", &[])
+            .build();
+        let records = crate::database::Database::from_bytes(image).unwrap().schema().unwrap();
+        assert_eq!((records.len(), records.is_empty()), (3, false));
+        let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, ["apples", "idx_name", "oranges"]);
+        let mut types = Vec::new();
+        for record in &records {
+            types.push(record.s_type.as_str());
+        }
+        assert_eq!(types, ["table", "index", "table"]);
+        assert!(Records::new().is_empty());
+    }
+
+    #[test]
+    fn decode_varint_fails_on_empty_or_truncated_input() {
+        let err = decode_varint(&[]).unwrap_err();
+        assert_eq!(err.to_string(), "varint runs past the end of the buffer (0 bytes)");
+        assert!(decode_varint(&[0x81, 0x80]).is_err());
+        assert_eq!(decode_varint(&[0x81, 0x00]).unwrap(), (128, 2));
+        assert_eq!(decode_varint(&[0x7f, 0xff]).unwrap(), (127, 1));
+    }
+}
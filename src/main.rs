@@ -1,238 +1,1157 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::time::Instant;
 
-#[derive(Debug)]
-struct Cell {
-    offset: u16
+use codecrafters_sqlite::database::Database;
+use codecrafters_sqlite::output::{format_rows, Mode, Printer};
+use codecrafters_sqlite::page::PageKind;
+use codecrafters_sqlite::parser::{parse_statement, Statement};
+use codecrafters_sqlite::planner::plan;
+use codecrafters_sqlite::query::execute_plan;
+use codecrafters_sqlite::schema::IndexInfo;
+use codecrafters_sqlite::value::{record_header, render, Value};
+use codecrafters_sqlite::get_db_info;
+
+struct Options {
+    db_path: String,
+    // Without one, statements are read from stdin
+    command: Option<String>,
+    max_bytes: Option<usize>,
+    mmap: bool,
+    stats: bool,
+    max_pages: Option<u64>,
+    verbose: bool,
+    // File to write results to instead of stdout
+    output: Option<String>,
+    // (path, alias) pairs from --attach
+    attach: Vec<(String, String)>,
+    // Statement to parse and dump, without opening a database
+    parse_only: Option<String>,
+    // Compare the header's page count with the file length: warn under
+    // --validate-size, fail under --strict
+    validate_size: bool,
+    strict: bool,
+    // File to write a JSON record of the main database's page reads to
+    trace: Option<String>,
+    // Print the version, and the database's file format details if given one
+    version: bool,
+    // Script of statements and dot commands to run before anything else
+    init: Option<String>,
+    // Stop at the first failing statement instead of reporting and going on
+    bail: bool,
+    // Overrides the page size in the database header
+    page_size: Option<usize>,
+    // Show each value's storage class in list and JSON output
+    typed: bool,
+    // Print how long each statement took to stderr
+    time: bool,
 }
-impl Cell {
-    fn new(offset: u16) -> Self {
-        Cell { offset }
+
+impl Options {
+    // Flags may appear anywhere; the remaining arguments are the database
+    // path followed by the command
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options> {
+        let mut max_bytes = None;
+        let mut mmap = false;
+        let mut stats = false;
+        let mut max_pages = None;
+        let mut attach = Vec::new();
+        let mut verbose = false;
+        let mut output = None;
+        let mut parse_only = None;
+        let mut validate_size = false;
+        let mut strict = false;
+        let mut trace = None;
+        let mut version = false;
+        let mut init = None;
+        let mut bail = false;
+        let mut page_size = None;
+        let mut typed = false;
+        let mut time = false;
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--max-bytes" => max_bytes = Some(flag_value(&mut args, &arg)?.parse()?),
+                "--mmap" => mmap = true,
+                "--stats" => stats = true,
+                "--verbose" => verbose = true,
+                "--output" => output = Some(flag_value(&mut args, &arg)?),
+                "--parse-only" => parse_only = Some(flag_value(&mut args, &arg)?),
+                "--validate-size" => validate_size = true,
+                "--strict" => strict = true,
+                "--trace" => trace = Some(flag_value(&mut args, &arg)?),
+                "--version" => version = true,
+                "--init" => init = Some(flag_value(&mut args, &arg)?),
+                "--bail" => bail = true,
+                "--typed" => typed = true,
+                "--time" => time = true,
+                "--page-size" => page_size = Some(flag_value(&mut args, &arg)?.parse()?),
+                "--max-pages" => max_pages = Some(flag_value(&mut args, &arg)?.parse()?),
+                "--attach" => {
+                    let path = flag_value(&mut args, &arg)?;
+                    let alias = match (args.next(), args.next()) {
+                        (Some(keyword), Some(alias)) if keyword.eq_ignore_ascii_case("AS") => alias,
+                        _ => bail!("Usage: --attach <path> AS <alias>"),
+                    };
+                    attach.push((path, alias));
+                }
+                _ => positional.push(arg),
+            }
+        }
+        if positional.is_empty() && parse_only.is_none() && !version {
+            bail!("Missing <database path>");
+        }
+        let db_path = positional.first().cloned().unwrap_or_default();
+        let command = if version {
+            Some(".version".to_string())
+        } else if positional.len() > 1 {
+            Some(positional[1..].join(" "))
+        } else {
+            None
+        };
+        Ok(Options {
+            db_path,
+            command,
+            max_bytes,
+            mmap,
+            stats,
+            max_pages,
+            verbose,
+            output,
+            attach,
+            parse_only,
+            validate_size,
+            strict,
+            trace,
+            version,
+            init,
+            bail,
+            page_size,
+            typed,
+            time,
+        })
     }
 }
-fn get_db_info(buffer: &Vec<u8>, page_size: u16, print_result: bool) -> DbInfo {
-    let page_header_byte = buffer[100];
-    let page_header_size = match page_header_byte {
-        13 => 8,
-        _ => 12,
+
+fn flag_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    match args.next() {
+        Some(value) => Ok(value),
+        None => bail!("Missing value for {}", flag),
+    }
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+// Results go to stdout unless --output names a file
+fn printer(options: &Options) -> Result<Printer> {
+    Ok(match &options.output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("failed to create {}", path))?;
+            Printer::to_writer(Box::new(BufWriter::new(file)), options.max_bytes)
+        }
+        None => Printer::new(options.max_bytes),
+    })
+}
+
+fn open_database(options: &Options, path: &str) -> Result<Database> {
+    let mut db = match (options.mmap, options.page_size) {
+        (true, Some(_)) => bail!("--page-size can't be combined with --mmap"),
+        (true, None) => Database::open_mmap(path)?,
+        (false, page_size) => Database::open_with_page_size(path, page_size)?,
     };
-    let page_header = &buffer[100..100 + page_header_size];
-    let tbl_count = u16::from_be_bytes([page_header[3], page_header[ 4]]);
-    let db_info = DbInfo { no_tables: tbl_count as usize, db_page_size: page_size as usize, page_header_size, records: Records::new()};
-    
-    // You can use print statements as follows for debugging, they'll be visible when running tests.
-    eprintln!("Logs from your program will appear here!");
+    configure(&mut db, options, path)?;
+    Ok(db)
+}
 
-    if print_result {
-        println!("database page size: {}", db_info.db_page_size);
-        println!("number of tables: {}", db_info.no_tables);
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+fn open_database(options: &Options, path: &str) -> Result<Database> {
+    if options.mmap {
+        bail!("--mmap is only supported on 64-bit unix");
     }
-    return db_info
+    let mut db = Database::open_with_page_size(path, options.page_size)?;
+    configure(&mut db, options, path)?;
+    Ok(db)
 }
 
-fn get_db_tables<'a> (db_info: &'a mut DbInfo, buffer: &Vec<u8>, print_result: bool) -> &'a Records {
-    // Read master table 
-    let mut cells: Vec<Cell> = Vec::new();
-    let mut i = db_info.page_header_size + 100;
-    loop {
-        if buffer[i] == 0 && buffer[i+1] == 0 {
-            break
+fn configure(db: &mut Database, options: &Options, path: &str) -> Result<()> {
+    db.set_max_pages(options.max_pages);
+    if options.verbose {
+        db.set_log(Some(|message| eprintln!("{}", message)));
+    }
+    if let Some(mismatch) = db.size_mismatch() {
+        if options.strict {
+            bail!("{}: {}", path, mismatch);
+        }
+        if options.validate_size {
+            eprintln!("warning: {}: {}", path, mismatch);
         }
-        cells.push(Cell::new(u16::from_be_bytes([buffer[i], buffer[i+1]])));
-        i += 2
     }
-    // Parsing records
-    for cell in cells.iter() {
-        let offset = usize::from(cell.offset);
-        let (payload_size, payload_size_len) = decode_varint(&buffer[offset..]);
-        let (rowid, rowid_len) = decode_varint(&buffer[offset + payload_size_len..]);
-        let record_start = offset + payload_size_len + rowid_len;
-        let (header_size, header_len) = decode_varint(&buffer[record_start..]);
-        let payload_header = &buffer[record_start + header_len..record_start + header_size as usize];
-        let rec_header = RecordHeader::new(payload_header, payload_size as usize, rowid as usize, header_size as usize);
-        let rec_payload_start = record_start + rec_header.header_size;
-        let record_payload = &buffer[rec_payload_start..rec_payload_start + rec_header.size - rec_header.header_size];
-        let record = Record::new(record_payload, rec_header);
-        if print_result {
-        println!("{:?}\n", record);
-        print!("{} ", record.tbl_name);
+    // Descending keys came with format 4. Such indexes are never searched,
+    // so queries on their columns fall back to scanning the table. A schema
+    // that can't be read is left for the command that needs it to report.
+    if db.schema_format() == 4 {
+        for index in db.indexes().unwrap_or_default().iter().filter(|index| index.descending) {
+            eprintln!("warning: {}: index {} has descending keys and won't be used", path, index.name);
         }
-        db_info.records.add_record(record);
+    }
+    Ok(())
 }
-        &db_info.records
+
+// Settings changed by dot commands, kept between statements in the REPL
+#[derive(Default)]
+struct Session {
+    mode: Mode,
+    headers: bool,
+    // Table column widths from .width, 0 meaning sized to fit
+    widths: Vec<usize>,
+    typed: bool,
+    // Print each statement's elapsed time, as sqlite3's .timer does
+    timer: bool,
+    // Databases opened with --attach, by alias
+    attached: Vec<(String, Database)>,
+}
+
+impl Session {
+    fn attached(&mut self, alias: &str) -> Result<&mut Database> {
+        match self.attached.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(alias)) {
+            Some((_, db)) => Ok(db),
+            None => bail!("unknown database {}", alias),
+        }
+    }
 }
 
 fn main() -> Result<()> {
     // Parse arguments
-    let args = std::env::args().collect::<Vec<_>>();
-    match args.len() {
-        0 | 1 => bail!("Missing <database path> and <command>"),
-        2 => bail!("Missing <command>"),
-        _ => {}
-    }
-
-    // Parse command and act accordingly
-    let command = &args[2];
-    let mut file = File::open(&args[1])?;
-    let mut header = [0; 100];
-    file.read_exact(&mut header)?;
-    #[allow(unused_variables)]
-    let page_size = u16::from_be_bytes([header[16], header[17]]);
-    let mut buffer = Vec::new();
-    buffer.resize(page_size as usize, 0u8);
-    file.read_exact(&mut buffer[100..])?;
-    let mut db_info = get_db_info(&buffer, page_size, false);
-
-    match command.as_str() {
+    let options = Options::parse(std::env::args().skip(1))?;
+    let mut printer = printer(&options)?;
+    if let Some(sql) = &options.parse_only {
+        print_ast(&mut printer, sql)?;
+        return printer.flush();
+    }
+    if options.version && options.db_path.is_empty() {
+        printer.line(&crate_version())?;
+        return printer.flush();
+    }
+    let mut db = open_database(&options, &options.db_path)?;
+    db.set_trace(options.trace.is_some());
+    let mut session = Session { typed: options.typed, timer: options.time, ..Session::default() };
+    for (path, alias) in options.attach.iter() {
+        if alias.eq_ignore_ascii_case("main") || session.attached(alias).is_ok() {
+            bail!("database {} is already in use", alias);
+        }
+        session.attached.push((alias.clone(), open_database(&options, path)?));
+    }
+
+    let result = match &options.init {
+        Some(path) => read_script(&mut db, &mut session, &options, &mut printer, path),
+        None => Ok(()),
+    };
+    let result = result.and_then(|()| match &options.command {
+        Some(command) => run(&mut db, &mut session, &options, &mut printer, command),
+        None => repl(&mut db, &mut session, &options, &mut printer),
+    });
+    printer.flush()?;
+    // Written even when the command failed, as that is often when it's wanted
+    if let Some(path) = &options.trace {
+        std::fs::write(path, db.trace_json() + "\n").with_context(|| format!("failed to write {}", path))?;
+    }
+    result
+}
+
+fn crate_version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+// Reads statements, which may span lines until a terminating ';', and dot
+// commands from stdin. Errors are reported and the loop carries on.
+fn repl(db: &mut Database, session: &mut Session, options: &Options, printer: &mut Printer) -> Result<()> {
+    let interactive = io::stdin().is_terminal();
+    let mut pending = String::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("{}", if pending.is_empty() { "sqlite> " } else { "   ...> " });
+            io::stdout().flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if !feed_line(db, session, options, printer, &mut pending, &line)? {
+            return Ok(());
+        }
+    }
+    finish_input(db, session, options, printer, &pending)
+}
+
+// Runs a file of statements and dot commands as if typed into the REPL,
+// for .read and --init. A .quit in the file only ends the file.
+fn read_script(db: &mut Database, session: &mut Session, options: &Options, printer: &mut Printer, path: &str) -> Result<()> {
+    let script = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+    let mut pending = String::new();
+    for line in script.lines() {
+        if !feed_line(db, session, options, printer, &mut pending, line)? {
+            return Ok(());
+        }
+    }
+    finish_input(db, session, options, printer, &pending)
+}
+
+// Takes one more line of input: a dot command when no statement is under
+// way, or else more of a statement, which runs once terminated. Returns
+// false at .quit or .exit.
+fn feed_line(
+    db: &mut Database,
+    session: &mut Session,
+    options: &Options,
+    printer: &mut Printer,
+    pending: &mut String,
+    line: &str,
+) -> Result<bool> {
+    if pending.is_empty() && line.trim_start().starts_with('.') {
+        match line.trim() {
+            ".quit" | ".exit" => return Ok(false),
+            command => report(options, run(db, session, options, printer, command))?,
+        }
+        return Ok(true);
+    }
+    pending.push_str(line);
+    pending.push('\n');
+    let (statements, rest) = split_statements(pending);
+    for statement in statements {
+        report(options, run(db, session, options, printer, &statement))?;
+    }
+    *pending = rest;
+    Ok(true)
+}
+
+// Input may end with a statement that was never terminated
+fn finish_input(db: &mut Database, session: &mut Session, options: &Options, printer: &mut Printer, pending: &str) -> Result<()> {
+    if pending.trim().is_empty() {
+        return Ok(());
+    }
+    report(options, run(db, session, options, printer, pending))
+}
+
+// Errors are reported and input carries on, unless --bail asks to stop
+fn report(options: &Options, result: Result<()>) -> Result<()> {
+    match result {
+        Err(err) if !options.bail => {
+            eprintln!("Error: {}", err);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+// Splits off every complete ';'-terminated statement, leaving the
+// unterminated remainder. Semicolons inside quotes don't count.
+fn split_statements(text: &str) -> (Vec<String>, String) {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '[') => quote = Some(']'),
+            (None, ';') => {
+                if !text[start..i].trim().is_empty() {
+                    statements.push(text[start..i].trim().to_string());
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let rest = if text[start..].trim().is_empty() { String::new() } else { text[start..].to_string() };
+    (statements, rest)
+}
+
+fn run(db: &mut Database, session: &mut Session, options: &Options, printer: &mut Printer, command: &str) -> Result<()> {
+    let command = command.trim();
+    let page_size = db.page_size();
+    let buffer = db.read_page(1)?;
+    let db_info = get_db_info(&buffer, page_size);
+
+    match command {
+        ".dbinfo --json" => {
+            printer.line(&db.summary()?.to_json())?;
+        },
         ".dbinfo" => {
             // The page size is stored at the 16th byte offset, using 2 bytes in big-endian order
-           get_db_info(&buffer, page_size, true);
+            printer.line(&format!("database page size: {}", db_info.db_page_size))?;
+            // Every row of the schema table, which page 1's cell count only
+            // matches while the schema fits on it
+            printer.line(&format!("number of tables: {}", db.schema()?.len()))?;
+        },
+        ".version" => {
+            let journal = |version: u8| match version {
+                1 => "legacy",
+                2 => "WAL",
+                _ => "unknown",
+            };
+            let (read, write) = (db_info.read_version, db_info.write_version);
+            printer.line(&crate_version())?;
+            printer.line(&format!("read version: {} ({})", read, journal(read)))?;
+            printer.line(&format!("write version: {} ({})", write, journal(write)))?;
+            printer.line(&format!("schema format: {}", db_info.schema_format))?;
+            // Encoded as major * 1000000 + minor * 1000 + patch
+            let number = db_info.sqlite_version;
+            printer.line(&format!(
+                "last written by SQLite {}.{}.{} ({})",
+                number / 1_000_000,
+                number / 1000 % 1000,
+                number % 1000,
+                number
+            ))?;
+        },
+        dot if dot.split_whitespace().next() == Some(".read") => match dot_args(dot).get(1) {
+            Some(path) => read_script(db, session, options, printer, path)?,
+            None => bail!("Usage: .read FILE"),
         },
+        // Every table once, in schema order, on one line
         ".tables" => {
-            // The page size is stored at the 16th byte offset, using 2 bytes in big-endian order
-            get_db_tables(&mut db_info, &buffer, true);
+            let records = db.schema()?;
+            let mut names: Vec<&str> = Vec::new();
+            for record in records.iter() {
+                if !names.iter().any(|name| name.eq_ignore_ascii_case(&record.tbl_name)) {
+                    names.push(&record.tbl_name);
+                }
+            }
+            printer.line(&names.join(" "))?;
+        },
+        ".pagestats" => {
+            let kinds = db.page_kinds()?;
+            for kind in PageKind::ALL {
+                printer.line(&format!("{}: {}", kind.label(), kinds.iter().filter(|&&k| k == kind).count()))?;
+            }
+        },
+        ".freelist" => {
+            let freelist = db.freelist()?;
+            let pages = freelist.page_count();
+            printer.line(&format!("free pages: {}", pages))?;
+            printer.line(&format!("free bytes: {}", pages as u64 * page_size as u64))?;
+            let trunks: Vec<String> = freelist.trunks.iter().map(|page| page.to_string()).collect();
+            printer.line(&format!("trunk pages: {}", if trunks.is_empty() { "none".to_string() } else { trunks.join(" ") }))?;
+            if db.freelist_count() as usize != pages {
+                printer.line(&format!("warning: header counts {} free pages", db.freelist_count()))?;
+            }
+        },
+        ".fkcheck" => {
+            let problems = db.foreign_key_problems()?;
+            for problem in problems.iter() {
+                printer.line(problem)?;
+            }
+            if problems.is_empty() {
+                printer.line("ok")?;
+            }
+        },
+        ".changes" => {
+            printer.line(&format!("change counter: {}", db.change_counter()))?;
+        },
+        ".count" => {
+            let counts = db.table_counts()?;
+            for (name, count) in counts.iter() {
+                printer.line(&format!("{}: {}", name, count))?;
+            }
+            printer.line(&format!("total: {}", counts.iter().map(|(_, count)| count).sum::<u64>()))?;
+        },
+        ".show" => {
+            let on_off = |on: bool| if on { "on" } else { "off" };
+            let widths: Vec<String> = session.widths.iter().map(|width| width.to_string()).collect();
+            // Names right-aligned as sqlite3 lays them out. NULL always
+            // shows as the empty string.
+            let settings = [
+                ("headers", on_off(session.headers).to_string()),
+                ("mode", session.mode.name().to_string()),
+                ("nullvalue", "\"\"".to_string()),
+                ("output", options.output.clone().unwrap_or_else(|| "stdout".to_string())),
+                ("typed", on_off(session.typed).to_string()),
+                ("width", widths.join(" ")),
+                ("filename", options.db_path.clone()),
+            ];
+            for (name, value) in settings.iter() {
+                printer.line(format!("{:>12}: {}", name, value).trim_end())?;
+            }
+        },
+        dot if dot.split_whitespace().next() == Some(".mode") => match dot.split_whitespace().nth(1) {
+            Some(name) => match Mode::from_name(name) {
+                Some(mode) => session.mode = mode,
+                None => bail!("mode should be one of: {}", Mode::NAMES.join(" ")),
+            },
+            None => {
+                printer.line(&format!("current output mode: {}", session.mode.name()))?;
+            }
+        },
+        dot if dot.split_whitespace().next() == Some(".headers") => match dot.split_whitespace().nth(1) {
+            Some("on") => session.headers = true,
+            Some("off") => session.headers = false,
+            _ => bail!("Usage: .headers on|off"),
+        },
+        dot if dot.split_whitespace().next() == Some(".timer") => match dot.split_whitespace().nth(1) {
+            Some("on") => session.timer = true,
+            Some("off") => session.timer = false,
+            _ => bail!("Usage: .timer on|off"),
+        },
+        // With no widths, every column goes back to being sized to fit
+        dot if dot.split_whitespace().next() == Some(".width") => {
+            let widths: Result<Vec<usize>, _> = dot.split_whitespace().skip(1).map(str::parse).collect();
+            match widths {
+                Ok(widths) => session.widths = widths,
+                Err(_) => bail!("Usage: .width N1 N2 ..."),
+            }
+        },
+        dot if dot.split_whitespace().next() == Some(".col") => {
+            let args = dot_args(dot);
+            let (stmt_tbl_name, n) = match &args[1..] {
+                [name, n] => (name.as_str(), n.parse::<usize>()?),
+                _ => bail!("Usage: .col <table> <n>"),
+            };
+            for row in db.rows(stmt_tbl_name)? {
+                let row = row?;
+                let more = match row.values.get(n) {
+                    Some(value) => printer.line(&render(value))?,
+                    None => bail!("column index {} out of range: row {} has {} columns", n, row.rowid, row.values.len()),
+                };
+                if !more {
+                    break;
+                }
+            }
+        },
+        // The record as stored: the header's serial types, then every byte
+        // in hex, 16 to a line
+        dot if dot.split_whitespace().next() == Some(".payload") => {
+            let args = dot_args(dot);
+            let (stmt_tbl_name, rowid) = match &args[1..] {
+                [name, rowid] => (name.as_str(), rowid.parse::<i64>()?),
+                _ => bail!("Usage: .payload <table> <rowid>"),
+            };
+            let payload = match db.record_payload(stmt_tbl_name, rowid)? {
+                Some(payload) => payload,
+                None => bail!("no row {} in {}", rowid, stmt_tbl_name),
+            };
+            let (header_size, serials) = record_header(&payload)?;
+            let serials: Vec<String> = serials.iter().map(|serial| serial.to_string()).collect();
+            printer.line(&format!("payload: {} bytes", payload.len()))?;
+            printer.line(&format!("header: {} bytes", header_size))?;
+            printer.line(&format!("serial types: {}", serials.join(" ")))?;
+            for (line, chunk) in payload.chunks(16).enumerate() {
+                let bytes: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+                if !printer.line(&format!("{:04x}: {}", line * 16, bytes.join(" ")))? {
+                    break;
+                }
+            }
+        },
+        dot if dot.starts_with(".rows") => {
+            let args = dot_args(dot);
+            let stmt_tbl_name = match args.get(1) {
+                Some(name) => name.as_str(),
+                None => bail!("Usage: .rows <table>"),
+            };
+            for row in db.rows(stmt_tbl_name)? {
+                let row: Vec<String> = row?.values.iter().map(render).collect();
+                if !printer.line(&row.join("|"))? {
+                    break;
+                }
+            }
         },
         statement => {
-            let stms: Vec<&str> = statement.split(' ').collect(); 
-            let stmt_tbl_name: String = match stms.last() {
-                Some(word) => {
-                    word.to_string()
-                },
-                None => {
-                    panic!("Please enter a valid table name");
+            let (select, explain) = match parse_statement(statement)? {
+                Statement::Select(select) => (select, false),
+                Statement::Explain(select) => (select, true),
+                Statement::Pragma { schema, name, argument } => {
+                    return pragma(db, session, printer, schema, &name, argument.as_deref())
                 }
             };
-            let tbl_info = get_db_tables(&mut db_info, &buffer, false);
-            if !tbl_info.contains(stmt_tbl_name.clone()){
-                println!("table: {} doesn't exist", &stmt_tbl_name);
-                return Ok(());
+            let (mode, headers, widths, typed) = (session.mode, session.headers, session.widths.clone(), session.typed);
+            let timer = session.timer;
+            let db = match &select.schema {
+                Some(alias) if !alias.eq_ignore_ascii_case("main") => session.attached(alias)?,
+                _ => db,
             };
-            println!("table: {} exists in the db", stmt_tbl_name);
+            let start = Instant::now();
+            let plan = plan(&select, &db.schema()?)?;
+            if explain {
+                printer.line(&plan.to_string())?;
+                return Ok(());
+            }
+            // Only the pages the query itself reads, not the schema
+            let before = db.page_stats();
+            let result = execute_plan(db, &plan, &select)?;
+            let pages = db.page_stats().since(before);
+            // Planning and running the query, but not printing its rows
+            let elapsed = start.elapsed();
+            for line in format_rows(mode, headers, &widths, typed, &result.columns, &result.rows) {
+                if !printer.line(&line)? {
+                    break;
+                }
+            }
+            if options.stats {
+                printer.note(&format!("plan: {}", plan))?;
+                printer.note(&format!("rows: {}", result.rows.len()))?;
+                printer.note(&format!("pages read: {} (table: {}, index: {})", pages.total, pages.table, pages.index))?;
+            }
+            if timer {
+                printer.note(&format!("Run Time: {:.3} ms", elapsed.as_secs_f64() * 1000.0))?;
+            }
         },
     }
 
     Ok(())
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
-struct RecordHeader {
-    size: usize,
-    rowid: usize,
-    header_size: usize,
-    type_size: usize,
-    name_size: usize,
-    tbl_name_size: usize,
-    root_page: usize,
-    sql_size: usize,
-}
-
-impl RecordHeader {
-    fn new(buf: &[u8], payload_size: usize, rowid: usize, header_size: usize) -> Self {
-        let mut cursor = 0;
-        let mut serials = Vec::new();
-        while cursor < buf.len() as usize {
-            let (serial, slen) = decode_varint(&buf[cursor..]);
-            serials.push(serial);
-            cursor += slen;
-        }
-
-        let type_size = ((serials[0] - 13) / 2) as usize;
-        let name_size = ((serials[1] - 13) / 2) as usize;
-        let tbl_name_size = ((serials[2] - 13) / 2) as usize;
-        let root_page = serials[3] as usize;
-        let sql_size = ((serials[4] - 13) / 2) as usize;
-
-        RecordHeader {
-            size: payload_size,
-            rowid, 
-            header_size,
-            type_size,
-            name_size,
-            tbl_name_size,
-            root_page,
-            sql_size,
-        }
-    }
-}
-fn convert_from_ascii(arr: &[u8]) -> String {
-    let mut res = String::new();
-    for i in arr.iter() {
-        res.push(i.clone() as char);
-    }
-    res
-}
-fn decode_varint(buf: &[u8]) -> (u64, usize) {
-    let mut value: u64 = 0;
-    let mut consumed = 0;
-
-    for &b in buf.iter().take(9) {
-        consumed += 1;
-
-        if b < 0x80 {
-            // last byte: full 8 bits
-            value = (value << 7) | (b as u64);
-            break;
+// The statement as parsed, for --parse-only. The database isn't opened.
+fn print_ast(printer: &mut Printer, sql: &str) -> Result<()> {
+    printer.line(&format!("{:#?}", parse_statement(sql)?))?;
+    Ok(())
+}
+
+// A dot command's words, split on whitespace except within '...' or "..."
+// quotes, which are dropped, so `.rows "my table"` names one table
+fn dot_args(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = command.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '"' || c == '\'' {
+            chars.next();
+            arg.extend(chars.by_ref().take_while(|&next| next != c));
         } else {
-            // continuation byte: lower 7 bits only
-            value = (value << 7) | ((b & 0x7F) as u64);
+            while let Some(next) = chars.next_if(|next| !next.is_whitespace()) {
+                arg.push(next);
+            }
         }
+        args.push(arg);
     }
-
-    (value, consumed)
+    args
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
-struct Record {
-    s_type: String,
-    name: String,
-    tbl_name: String,
-    sql: String,
-    header: RecordHeader,
+// Header fields readable with PRAGMA
+fn pragma(
+    db: &mut Database,
+    session: &mut Session,
+    printer: &mut Printer,
+    schema: Option<String>,
+    name: &str,
+    argument: Option<&str>,
+) -> Result<()> {
+    let (mode, headers, widths, typed) = (session.mode, session.headers, session.widths.clone(), session.typed);
+    let db = match &schema {
+        Some(alias) if !alias.eq_ignore_ascii_case("main") => session.attached(alias)?,
+        _ => db,
+    };
+    if name.eq_ignore_ascii_case("index_list") {
+        let table = match argument {
+            Some(table) => table,
+            None => bail!("Usage: PRAGMA index_list(<table>)"),
+        };
+        // Newest first, as sqlite lists them; all came from CREATE INDEX, so
+        // their origin is "c"
+        let indexes: Vec<IndexInfo> =
+            db.indexes()?.into_iter().filter(|index| index.table.eq_ignore_ascii_case(table)).rev().collect();
+        let columns: Vec<String> = ["seq", "name", "unique", "origin", "partial"].iter().map(|c| c.to_string()).collect();
+        let rows: Vec<Vec<Value>> = indexes
+            .into_iter()
+            .enumerate()
+            .map(|(seq, index)| {
+                vec![
+                    Value::Integer(seq as i64),
+                    Value::Text(index.name),
+                    Value::Integer(index.unique as i64),
+                    Value::Text("c".to_string()),
+                    Value::Integer(index.partial as i64),
+                ]
+            })
+            .collect();
+        for line in format_rows(mode, headers, &widths, typed, &columns, &rows) {
+            if !printer.line(&line)? {
+                break;
+            }
+        }
+        return Ok(());
+    }
+    let page_size = db.page_size();
+    let db_info = get_db_info(&db.read_page(1)?, page_size);
+    let value = match name.to_ascii_lowercase().as_str() {
+        "user_version" => db_info.user_version,
+        "application_id" => db_info.application_id,
+        _ => bail!("unsupported pragma {}", name),
+    };
+    printer.line(&value.to_string())?;
+    Ok(())
 }
 
-impl Record {
-    fn new(record_payload: &[u8], record_header: RecordHeader) -> Self {
-        let mut i = 0;
-        let s_type = convert_from_ascii(&record_payload[i..record_header.type_size + i]);
-        i = record_header.type_size + i;
-        let name = convert_from_ascii(&record_payload[i..record_header.name_size + i]);
-        i = record_header.name_size + i;
-        let tbl_name = convert_from_ascii(&record_payload[i..record_header.tbl_name_size + i]);
-        i = record_header.tbl_name_size + i;
-        i = record_header.root_page + i;
-        let sql = convert_from_ascii(&record_payload[i..record_header.sql_size + i]);
-        Record { s_type, name, tbl_name, sql, header: record_header }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codecrafters_sqlite::fixture::FixtureBuilder;
+    use codecrafters_sqlite::value::Value;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A writer whose bytes can still be read once the printer owns it
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
     }
-}
 
-#[derive(Debug)]
-struct Records {
-    records: Vec<Record>
-}
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.borrow()).into_owned()
+        }
+    }
 
-impl Records {
-    fn new() -> Self {
-        Records { records: Vec::new() }
+    fn options(args: &[&str]) -> Options {
+        Options::parse(["test.db"].iter().chain(args.iter()).map(|arg| arg.to_string())).unwrap()
     }
-    fn add_record(self: &mut Self, record: Record) {
-        self.records.push(record);
+
+    // Feeds each line to the database as the REPL would
+    fn feed(mut db: Database, options: Options, printer: &mut Printer, lines: &[&str]) {
+        let mut session = Session { typed: options.typed, timer: options.time, ..Session::default() };
+        let options = Options { bail: true, ..options };
+        let mut pending = String::new();
+        for line in lines.iter() {
+            feed_line(&mut db, &mut session, &options, printer, &mut pending, line).unwrap();
+        }
+        finish_input(&mut db, &mut session, &options, printer, &pending).unwrap();
     }
-    fn contains(self: &Self, tbl_name: String) -> bool {
-        for rec in self.records.iter() {
-            if rec.tbl_name == tbl_name {
-                return true;
-            }
+
+    // What the lines print, as they'd print it to stdout
+    fn run_lines(image: Vec<u8>, lines: &[&str]) -> String {
+        let out = Captured::default();
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        feed(Database::from_bytes(image).unwrap(), options(&[]), &mut printer, lines);
+        out.text()
+    }
+
+    // What the lines print to stdout, and the notices they print to stderr
+    fn run_noting(image: Vec<u8>, args: &[&str], lines: &[&str]) -> (String, String) {
+        let (out, notices) = (Captured::default(), Captured::default());
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        printer.notices_to(Box::new(notices.clone()));
+        feed(Database::from_bytes(image).unwrap(), options(args), &mut printer, lines);
+        (out.text(), notices.text())
+    }
+
+    fn fruit() -> Vec<u8> {
+        let rows = [
+            (1, vec![Value::Text("apple".to_string()), Value::Real(1.5)]),
+            (2, vec![Value::Text("banana".to_string()), Value::Null]),
+            (3, vec![Value::Text("cherry".to_string()), Value::Integer(3)]),
+        ];
+        FixtureBuilder::default().add_table("fruit", "CREATE TABLE fruit(name TEXT, price REAL)", &rows).build()
+    }
+
+    #[test]
+    fn col_prints_one_column_by_position() {
+        assert_eq!(run_lines(fruit(), &[".col fruit 1"]), "1.5\n\n3\n");
+        assert_eq!(run_lines(fruit(), &[".col fruit 0"]), "apple\nbanana\ncherry\n");
+    }
+
+    #[test]
+    fn col_rejects_a_position_past_the_record() {
+        let mut db = Database::from_bytes(fruit()).unwrap();
+        let mut printer = Printer::to_writer(Box::new(Captured::default()), None);
+        let err = run(&mut db, &mut Session::default(), &options(&[]), &mut printer, ".col fruit 2").unwrap_err();
+        assert_eq!(err.to_string(), "column index 2 out of range: row 1 has 2 columns");
+    }
+
+    #[test]
+    fn mode_changes_how_later_results_render() {
+        let lines = ["SELECT name, price FROM fruit WHERE price > 2;", ".mode csv", "SELECT name, price FROM fruit;"];
+        assert_eq!(run_lines(fruit(), &lines), "cherry|3.0\napple,1.5\nbanana,\ncherry,3.0\n");
+        let json = "SELECT name FROM fruit WHERE name = 'banana';";
+        let lines = [".mode json", json, ".mode list", "SELECT name FROM fruit;"];
+        assert_eq!(run_lines(fruit(), &lines), "[{\"name\":\"banana\"}]\napple\nbanana\ncherry\n");
+    }
+
+    #[test]
+    fn headers_row_appears_only_when_on() {
+        let query = "SELECT name, price FROM fruit WHERE price > 1;";
+        assert_eq!(run_lines(fruit(), &[query]), "apple|1.5\ncherry|3.0\n");
+        let lines = [".headers on", query, ".mode csv", query, ".headers off", query];
+        let expected = "name|price\napple|1.5\ncherry|3.0\nname,price\napple,1.5\ncherry,3.0\napple,1.5\ncherry,3.0\n";
+        assert_eq!(run_lines(fruit(), &lines), expected);
+        // No header over an empty result, as in sqlite3
+        assert_eq!(run_lines(fruit(), &[".headers on", "SELECT name FROM fruit WHERE price > 5;"]), "");
+    }
+
+    #[test]
+    fn attached_tables_are_qualified_by_alias() {
+        let options = options(&["--attach", "other.db", "as", "other"]);
+        assert_eq!(options.attach, [("other.db".to_string(), "other".to_string())]);
+        let rows = [(1, vec![Value::Text("leek".to_string())]), (2, vec![Value::Text("kale".to_string())])];
+        let other = FixtureBuilder::default().add_table("veg", "CREATE TABLE veg(name TEXT)", &rows).build();
+        let attached = vec![("other".to_string(), Database::from_bytes(other).unwrap())];
+        let mut session = Session { attached, ..Session::default() };
+        let mut db = Database::from_bytes(fruit()).unwrap();
+        let out = Captured::default();
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        run(&mut db, &mut session, &options, &mut printer, "SELECT name FROM other.veg").unwrap();
+        run(&mut db, &mut session, &options, &mut printer, "SELECT name FROM main.fruit WHERE price > 2").unwrap();
+        assert_eq!(out.text(), "leek\nkale\ncherry\n");
+        let err = run(&mut db, &mut session, &options, &mut printer, "SELECT name FROM other.fruit").unwrap_err();
+        assert!(err.to_string().contains("fruit"), "{}", err);
+        let err = run(&mut db, &mut session, &options, &mut printer, "SELECT name FROM nowhere.veg").unwrap_err();
+        assert_eq!(err.to_string(), "unknown database nowhere");
+    }
+
+    #[test]
+    fn dot_commands_read_a_schema_past_page_1() {
+        let mut builder = FixtureBuilder::default();
+        for i in 0..120 {
+            let name = format!("t{:03}", i);
+            builder.add_table(&name, &format!("CREATE TABLE {}(x)", name), &[]);
         }
-        return false;
+        let image = builder.build();
+        let names: Vec<String> = (0..120).map(|i| format!("t{:03}", i)).collect();
+        assert_eq!(run_lines(image.clone(), &[".tables"]), names.join(" ") + "\n");
+        let out = run_lines(image.clone(), &[".rows sqlite_master"]);
+        assert_eq!(out.lines().count(), 120);
+        assert!(out.ends_with("table|t119|t119|121|CREATE TABLE t119(x)\n"), "{}", out);
+        assert_eq!(run_lines(image.clone(), &[".col sqlite_master 1"]).lines().last(), Some("t119"));
+        assert_eq!(run_lines(image, &[".dbinfo"]), "database page size: 4096\nnumber of tables: 120\n");
     }
-}
 
-struct DbInfo {
-    no_tables: usize,
-    db_page_size: usize,
-    page_header_size: usize,
-    records: Records,
+    #[test]
+    fn pragmas_print_user_version_and_application_id() {
+        let mut image = fruit();
+        image[60..64].copy_from_slice(&7u32.to_be_bytes());
+        image[68..72].copy_from_slice(&0x0f05_5112u32.to_be_bytes());
+        let out = run_lines(image, &["PRAGMA user_version;", "pragma application_id;"]);
+        assert_eq!(out, format!("7\n{}\n", 0x0f05_5112));
+        assert_eq!(run_lines(fruit(), &["PRAGMA user_version;"]), "0\n");
+    }
+
+    #[test]
+    fn tables_lists_each_table_once() {
+        let image = FixtureBuilder::default()
+            .add_table("fruit", "CREATE TABLE fruit(name TEXT, price REAL)", &[])
+            .add_index("fruit_name", "fruit", "CREATE INDEX fruit_name ON fruit(name)", &[])
+            .add_table("veg", "CREATE TABLE veg(name TEXT)", &[])
+            .build();
+        assert_eq!(run_lines(image, &[".tables"]), "fruit veg\n");
+    }
+
+    #[test]
+    fn output_goes_to_the_named_file() {
+        let path = std::env::temp_dir().join(format!("output-test-{}.txt", std::process::id()));
+        let options = options(&["--output", path.to_str().unwrap()]);
+        let mut printer = printer(&options).unwrap();
+        let db = Database::from_bytes(fruit()).unwrap();
+        feed(db, options, &mut printer, &[".tables", "SELECT name FROM fruit WHERE price > 2;"]);
+        printer.flush().unwrap();
+        drop(printer);
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "fruit\ncherry\n");
+    }
+
+    #[test]
+    fn max_bytes_applies_to_dot_commands() {
+        let image = FixtureBuilder::default()
+            .add_table("alpha", "CREATE TABLE alpha(x)", &[])
+            .add_table("beta", "CREATE TABLE beta(x)", &[])
+            .build();
+        let out = Captured::default();
+        let mut printer = Printer::to_writer(Box::new(out.clone()), Some(15));
+        feed(Database::from_bytes(image).unwrap(), options(&[]), &mut printer, &[".tables", ".tables"]);
+        assert_eq!(out.text(), "alpha beta\n");
+    }
+
+    #[test]
+    fn parse_only_prints_the_ast() {
+        let sql = "SELECT name FROM apples WHERE id = 1 LIMIT 2";
+        let options = Options::parse(["--parse-only", sql].iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(options.parse_only.as_deref(), Some(sql));
+        let out = Captured::default();
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        print_ast(&mut printer, sql).unwrap();
+        let compact: String = out.text().split_whitespace().collect();
+        let expected = "Select(SelectStatement{columns:[Column(\"name\",),],schema:None,table:Some(\"apples\",),\
+            where_clause:Some(Binary{op:Eq,left:Column(\"id\",),right:Literal(Integer(1,),),},),\
+            group_by:None,order_by:[],limit:Some(2,),offset:0,},)";
+        assert_eq!(compact, expected);
+        assert!(print_ast(&mut printer, "SELECT name FROM apples WHERE").is_err());
+    }
+
+    #[test]
+    fn width_fixes_table_columns() {
+        let lines = [".mode table", ".width 8 3", "SELECT name, price FROM fruit WHERE price > 1;"];
+        let expected = "\
++----------+-----+
+| name     | pri |
++----------+-----+
+| apple    | 1.5 |
+| cherry   | 3.0 |
++----------+-----+
+";
+        assert_eq!(run_lines(fruit(), &lines), expected);
+        // 0 sizes a column to fit, as does a column past the last width
+        let lines = [".mode table", ".width 0", "SELECT name, price FROM fruit WHERE price > 2;"];
+        let expected = "\
++--------+-------+
+| name   | price |
++--------+-------+
+| cherry | 3.0   |
++--------+-------+
+";
+        assert_eq!(run_lines(fruit(), &lines), expected);
+    }
+
+    #[test]
+    fn pagestats_counts_pointer_map_pages() {
+        // Enough pages, overflow chains included, to need a second
+        // pointer-map page after page 2
+        let rows: Vec<(i64, Vec<Value>)> = (1..=700).map(|id| (id, vec![Value::Text("x".repeat(5000))])).collect();
+        let image = FixtureBuilder::default().auto_vacuum().add_table("big", "CREATE TABLE big(v TEXT)", &rows).build();
+        let pages = image.len() / codecrafters_sqlite::fixture::PAGE_SIZE;
+        assert!(pages > 822);
+        let mut db = Database::from_bytes(image.clone()).unwrap();
+        let kinds = db.page_kinds().unwrap();
+        assert_eq!((kinds[1], kinds[821]), (PageKind::PointerMap, PageKind::PointerMap));
+        let out = run_lines(image.clone(), &[".pagestats"]);
+        assert!(out.contains("pointer-map: 2\n"), "{}", out);
+        assert!(out.contains("unknown: 0\n"), "{}", out);
+        assert_eq!(run_lines(image, &[".rows big"]).lines().count(), 700);
+    }
+
+    // The table and index page counts from a --stats "pages read" notice
+    fn pages_read(notices: &str) -> Vec<(u64, u64, u64)> {
+        notices
+            .lines()
+            .filter_map(|line| line.strip_prefix("pages read: "))
+            .map(|counts| {
+                let counts: Vec<u64> = counts
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter(|word| !word.is_empty())
+                    .map(|word| word.parse().unwrap())
+                    .collect();
+                (counts[0], counts[1], counts[2])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stats_for_a_rowid_lookup_count_only_its_descent() {
+        // Two levels: the root and one leaf
+        let image = codecrafters_sqlite::fixture::table_fixture(2000);
+        let (out, notices) = run_noting(image, &["--stats"], &["SELECT name FROM t WHERE id = 1234;"]);
+        assert_eq!(out, "name 1234\n");
+        assert!(notices.starts_with("plan: SEARCH t USING INTEGER PRIMARY KEY (rowid=?)\n"), "{}", notices);
+        assert_eq!(pages_read(&notices), [(2, 2, 0)]);
+    }
+
+    #[test]
+    fn text_with_a_nul_byte_survives_every_mode() {
+        let rows = [(1, vec![Value::Text("before\0after".to_string())])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        let query = "SELECT v FROM t;";
+        assert_eq!(run_lines(image.clone(), &[query]), "before\0after\n");
+        assert_eq!(run_lines(image.clone(), &[".mode csv", query]), "\"before\0after\"\n");
+        assert_eq!(run_lines(image.clone(), &[".mode json", query]), "[{\"v\":\"before\\u0000after\"}]\n");
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(db.query("SELECT v FROM t", &[]).unwrap().rows, [rows[0].1.clone()]);
+    }
+
+    #[test]
+    fn strict_rejects_a_stale_page_count() {
+        let mut image = fruit();
+        image[28..32].copy_from_slice(&9u32.to_be_bytes());
+        let mut db = Database::from_bytes(image).unwrap();
+        let err = configure(&mut db, &options(&["--strict"]), "test.db").unwrap_err();
+        assert_eq!(err.to_string(), "test.db: header says 9 pages but the file holds 2");
+        // Only a warning otherwise, and the database stays usable
+        configure(&mut db, &options(&["--validate-size"]), "test.db").unwrap();
+        assert_eq!(db.query("SELECT name FROM fruit", &[]).unwrap().rows.len(), 3);
+    }
+
+    #[test]
+    fn freelist_reports_free_pages_and_bytes() {
+        let image = FixtureBuilder::default()
+            .add_table("fruit", "CREATE TABLE fruit(name TEXT)", &[(1, vec![Value::Text("apple".to_string())])])
+            .add_free_pages(3)
+            .add_free_pages(2)
+            .build();
+        assert_eq!(run_lines(image, &[".freelist"]), "free pages: 7\nfree bytes: 28672\ntrunk pages: 7 3\n");
+        assert_eq!(run_lines(fruit(), &[".freelist"]), "free pages: 0\nfree bytes: 0\ntrunk pages: none\n");
+    }
+
+    // What a command given on the command line prints
+    fn run_command(image: Vec<u8>, command: &str) -> String {
+        let mut db = Database::from_bytes(image).unwrap();
+        let out = Captured::default();
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        run(&mut db, &mut Session::default(), &options(&[]), &mut printer, command).unwrap();
+        out.text()
+    }
+
+    #[test]
+    fn statement_may_end_in_semicolons() {
+        assert_eq!(run_command(fruit(), "SELECT COUNT(*) FROM fruit;"), "3\n");
+        assert_eq!(run_command(fruit(), "SELECT COUNT(*) FROM fruit;;"), "3\n");
+        assert_eq!(run_command(fruit(), "PRAGMA user_version;"), "0\n");
+        let err = Database::from_bytes(fruit()).unwrap().query("SELECT name FROM fruit; SELECT 1", &[]).unwrap_err();
+        assert!(err.to_string().starts_with("unexpected Ident(\"SELECT\")"), "{}", err);
+    }
+
+    #[test]
+    fn statement_may_be_surrounded_by_whitespace() {
+        assert_eq!(run_command(fruit(), "  SELECT name FROM fruit WHERE price > 2  "), "cherry\n");
+        assert_eq!(run_command(fruit(), "\n\tSELECT COUNT(*) FROM fruit ;\n"), "3\n");
+        assert_eq!(run_command(fruit(), " .tables "), "fruit\n");
+    }
+
+    #[test]
+    fn version_reports_the_file_format() {
+        let out = run_lines(fruit(), &[".version"]);
+        let schema_format: u32 = out
+            .lines()
+            .find_map(|line| line.strip_prefix("schema format: "))
+            .and_then(|number| number.parse().ok())
+            .unwrap();
+        assert!((1..=4).contains(&schema_format), "{}", out);
+        let expected = format!(
+            "{}\nread version: 1 (legacy)\nwrite version: 1 (legacy)\nschema format: {}\n\
+             last written by SQLite 3.40.1 (3040001)\n",
+            crate_version(),
+            schema_format
+        );
+        assert_eq!(out, expected);
+    }
+
+    // Writes a script to a temporary file, returning its path
+    fn script(name: &str, text: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.sql", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn read_runs_every_statement_in_the_script() {
+        let text = "SELECT COUNT(*) FROM fruit;\n.tables\nSELECT name\n  FROM fruit\n  WHERE price > 2; \
+                    SELECT name FROM fruit WHERE price < 2;\n";
+        let path = script("read-test", text);
+        let out = run_lines(fruit(), &[&format!(".read {}", path.display())]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(out, "3\nfruit\ncherry\napple\n");
+    }
+
+    #[test]
+    fn read_carries_on_past_errors_unless_bailing() {
+        let path = script("read-bail-test", "SELECT name FROM nowhere;\nSELECT COUNT(*) FROM fruit;\n");
+        let command = format!(".read {}", path.display());
+        let (out, mut db) = (Captured::default(), Database::from_bytes(fruit()).unwrap());
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        run(&mut db, &mut Session::default(), &options(&[]), &mut printer, &command).unwrap();
+        assert_eq!(out.text(), "3\n");
+
+        let out = Captured::default();
+        let mut printer = Printer::to_writer(Box::new(out.clone()), None);
+        let err = run(&mut db, &mut Session::default(), &options(&["--bail"]), &mut printer, &command).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("nowhere"), "{}", err);
+        assert_eq!(out.text(), "");
+    }
+
+    #[test]
+    fn typed_annotates_each_value_with_its_storage_class() {
+        let rows = [(1, vec![Value::Integer(42), Value::Text("foo".to_string()), Value::Real(1.5), Value::Null])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(a, b, c, d)", &rows).build();
+        let (out, _) = run_noting(image.clone(), &["--typed"], &["SELECT * FROM t;"]);
+        assert_eq!(out, "42:integer|foo:text|1.5:real|:null\n");
+        let (out, _) = run_noting(image, &["--typed"], &[".mode json", "SELECT a, b FROM t;"]);
+        assert_eq!(out, "[{\"a\":{\"value\":42,\"type\":\"integer\"},\"b\":{\"value\":\"foo\",\"type\":\"text\"}}]\n");
+    }
+
+    #[test]
+    fn show_reports_the_settings_as_changed() {
+        let show = |headers: &str, mode: &str, width: &str| {
+            let lines = [
+                format!("     headers: {}", headers),
+                format!("        mode: {}", mode),
+                "   nullvalue: \"\"".to_string(),
+                "      output: stdout".to_string(),
+                "       typed: off".to_string(),
+                format!("       width: {}", width).trim_end().to_string(),
+                "    filename: test.db".to_string(),
+            ];
+            lines.join("\n") + "\n"
+        };
+        assert_eq!(run_lines(fruit(), &[".show"]), show("off", "list", ""));
+        let out = run_lines(fruit(), &[".mode csv", ".headers on", ".width 4 0 7", ".show"]);
+        assert_eq!(out, show("on", "csv", "4 0 7"));
+    }
+
+    // The elapsed times of the "Run Time: <ms> ms" notices
+    fn run_times(notices: &str) -> Vec<f64> {
+        notices
+            .lines()
+            .filter_map(|line| line.strip_prefix("Run Time: ")?.strip_suffix(" ms")?.parse().ok())
+            .collect()
+    }
+
+    #[test]
+    fn timer_prints_a_timing_line_after_each_query() {
+        let (out, notices) = run_noting(fruit(), &[], &["SELECT name FROM fruit WHERE price > 2;"]);
+        assert_eq!((out.as_str(), run_times(&notices).len()), ("cherry\n", 0));
+
+        let lines =
+            [".timer on", "SELECT name FROM fruit WHERE price > 2;", "SELECT COUNT(*) FROM fruit;", ".timer off"];
+        let (out, notices) = run_noting(fruit(), &[], &lines);
+        assert_eq!(out, "cherry\n3\n");
+        let times = run_times(&notices);
+        assert_eq!(times.len(), 2, "{}", notices);
+        assert!(times.iter().all(|&ms| ms >= 0.0));
+
+        let (_, notices) = run_noting(fruit(), &["--time"], &["SELECT name FROM fruit;"]);
+        assert_eq!(run_times(&notices).len(), 1, "{}", notices);
+    }
+
+    #[test]
+    fn payload_prints_a_rows_record_bytes() {
+        // Header of 3 bytes: serial types 23 (5-byte text) and 7 (float),
+        // then "apple" and 1.5 as a big-endian double
+        let out = run_lines(fruit(), &[".payload fruit 1"]);
+        let expected = "payload: 16 bytes\nheader: 3 bytes\nserial types: 23 7\n\
+                        0000: 03 17 07 61 70 70 6c 65 3f f8 00 00 00 00 00 00\n";
+        assert_eq!(out, expected);
+        let mut db = Database::from_bytes(fruit()).unwrap();
+        let mut printer = Printer::to_writer(Box::new(Captured::default()), None);
+        let err = run(&mut db, &mut Session::default(), &options(&[]), &mut printer, ".payload fruit 9").unwrap_err();
+        assert_eq!(err.to_string(), "no row 9 in fruit");
+    }
+
+    #[test]
+    fn changes_prints_the_file_change_counter() {
+        let mut image = fruit();
+        assert_eq!(Database::from_bytes(image.clone()).unwrap().change_counter(), 1);
+        assert_eq!(run_lines(image.clone(), &[".changes"]), "change counter: 1\n");
+        image[24..28].copy_from_slice(&70_000u32.to_be_bytes());
+        assert_eq!(Database::from_bytes(image.clone()).unwrap().change_counter(), 70_000);
+        assert_eq!(run_lines(image, &[".changes"]), "change counter: 70000\n");
+    }
+
+    #[test]
+    fn dot_commands_take_quoted_table_names() {
+        let rows = [(1, vec![Value::Text("first".to_string())])];
+        let image = FixtureBuilder::default().add_table("order", "CREATE TABLE \"order\" (item TEXT)", &rows).build();
+        assert_eq!(run_lines(image.clone(), &[".rows \"order\""]), "first\n");
+        assert_eq!(run_lines(image, &["SELECT item FROM \"order\";"]), "first\n");
+    }
 }
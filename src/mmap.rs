@@ -0,0 +1,85 @@
+// Read-only memory map of a database file, using the libc calls directly.
+// Only built for 64-bit unix, where off_t is 64 bits everywhere; 32-bit
+// targets differ in whether mmap takes a 32- or 64-bit offset.
+use anyhow::{bail, Result};
+use std::fs::File;
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::AsRawFd;
+
+use crate::database::AccessPattern;
+
+#[allow(non_camel_case_types)]
+type off_t = i64;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: off_t) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn madvise(addr: *mut c_void, len: usize, advice: c_int) -> c_int;
+}
+
+const PROT_READ: c_int = 1;
+const MAP_PRIVATE: c_int = 2;
+const MADV_NORMAL: c_int = 0;
+const MADV_RANDOM: c_int = 1;
+const MADV_SEQUENTIAL: c_int = 2;
+
+pub struct Mmap {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl Mmap {
+    /// Maps the whole of `file` read-only.
+    ///
+    /// # Safety
+    ///
+    /// The mapping is read through `as_ref` as an ordinary `&[u8]`, so the
+    /// file must not be truncated or written to, by this process or any
+    /// other, for as long as the `Mmap` lives. Truncation turns reads of the
+    /// lost pages into SIGBUS, and writes change bytes Rust assumes are
+    /// immutable.
+    pub unsafe fn map(file: &File) -> Result<Mmap> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            bail!("cannot map an empty file");
+        }
+        // SAFETY: a fresh read-only private mapping of an open descriptor,
+        // letting the kernel choose the address; failure is checked below
+        let ptr = unsafe { mmap(std::ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr as isize == -1 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Mmap { ptr, len })
+    }
+
+    pub fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        let advice = match pattern {
+            AccessPattern::Normal => MADV_NORMAL,
+            AccessPattern::Sequential => MADV_SEQUENTIAL,
+            AccessPattern::Random => MADV_RANDOM,
+        };
+        // SAFETY: advice only, over exactly the range mapped in map
+        if unsafe { madvise(self.ptr, self.len, advice) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: ptr is a live mapping of len readable bytes until drop,
+        // and map's caller guarantees the file underneath doesn't change
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: unmaps the range map created, once; no slice handed out
+        // by as_ref can outlive self
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::Result;
+use std::io::{self, Write};
+
+use crate::value::{render, Value};
+
+// How result rows are laid out, as chosen with .mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    // Pipe-separated values
+    #[default]
+    List,
+    Csv,
+    Json,
+    // Columns padded to width inside an ASCII box
+    Table,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::List, Mode::Csv, Mode::Json, Mode::Table];
+    pub const NAMES: [&'static str; 4] = ["list", "csv", "json", "table"];
+
+    pub fn from_name(name: &str) -> Option<Mode> {
+        let index = Mode::NAMES.iter().position(|known| known.eq_ignore_ascii_case(name))?;
+        Some(Mode::ALL[index])
+    }
+
+    pub fn name(&self) -> &'static str {
+        Mode::NAMES[Mode::ALL.iter().position(|mode| mode == self).unwrap_or(0)]
+    }
+}
+
+// Renders a whole result the way sqlite3 does in each mode. JSON and
+// table output need every row up front, for the closing bracket and the
+// column widths. `headers` adds a row of column names to list and CSV
+// output; as in sqlite3, JSON objects carry the names anyway and tables
+// always have a header. `widths` fixes the width of table columns, from
+// the first; 0 or a missing entry sizes the column to fit. `typed` pairs
+// each value in list and JSON output with its storage class.
+pub fn format_rows(
+    mode: Mode,
+    headers: bool,
+    widths: &[usize],
+    typed: bool,
+    columns: &[String],
+    rows: &[Vec<Value>],
+) -> Vec<String> {
+    let list_field = |value: &Value| {
+        if typed {
+            format!("{}:{}", render(value), value.type_name())
+        } else {
+            render(value)
+        }
+    };
+    let json_field = |value: &Value| {
+        if typed {
+            format!("{{\"value\":{},\"type\":{}}}", json_value(value), json_string(value.type_name()))
+        } else {
+            json_value(value)
+        }
+    };
+    let header = |field: fn(&Value) -> String, separator: &str| {
+        let names: Vec<String> = columns.iter().map(|name| field(&Value::Text(name.clone()))).collect();
+        (headers && !rows.is_empty()).then(|| names.join(separator))
+    };
+    match mode {
+        Mode::List => header(render, "|")
+            .into_iter()
+            .chain(rows.iter().map(|row| row.iter().map(list_field).collect::<Vec<_>>().join("|")))
+            .collect(),
+        Mode::Csv => header(csv_field, ",")
+            .into_iter()
+            .chain(rows.iter().map(|row| row.iter().map(csv_field).collect::<Vec<_>>().join(",")))
+            .collect(),
+        Mode::Json => {
+            let last = rows.len().saturating_sub(1);
+            rows.iter()
+                .enumerate()
+                .map(|(i, row)| {
+                    let fields: Vec<String> = columns
+                        .iter()
+                        .zip(row.iter())
+                        .map(|(column, value)| format!("{}:{}", json_string(column), json_field(value)))
+                        .collect();
+                    let open = if i == 0 { "[" } else { "" };
+                    let close = if i == last { "]" } else { "," };
+                    format!("{}{{{}}}{}", open, fields.join(","), close)
+                })
+                .collect()
+        }
+        Mode::Table => format_table(widths, columns, rows),
+    }
+}
+
+fn format_table(fixed: &[usize], columns: &[String], rows: &[Vec<Value>]) -> Vec<String> {
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    let rendered: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(render).collect()).collect();
+    let mut widths: Vec<usize> = columns.iter().map(|column| column.chars().count()).collect();
+    for row in rendered.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for (width, &fixed) in widths.iter_mut().zip(fixed.iter()) {
+        if fixed > 0 {
+            *width = fixed;
+        }
+    }
+    // Cells wider than a fixed width are cut off
+    let fit = |cell: &String, width: usize| cell.chars().take(width).collect::<String>();
+    let border: Vec<String> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+    let border = format!("+{}+", border.join("+"));
+    let line = |cells: &[String]| {
+        let cells: Vec<String> =
+            cells.iter().zip(widths.iter()).map(|(cell, &width)| format!("{:<width$}", fit(cell, width), width = width)).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![border.clone(), line(columns), border.clone()];
+    lines.extend(rendered.iter().map(|row| line(row)));
+    lines.push(border);
+    lines
+}
+
+// Quoted when it contains a separator, quote, space, control or non-ASCII
+// byte, like sqlite3's CSV mode
+pub fn csv_field(value: &Value) -> String {
+    let text = render(value);
+    let needs_quote = matches!(value, Value::Text(_))
+        && text.bytes().any(|b| b <= b' ' || b == b'"' || b == b',' || b == b'\'' || b >= 0x7f);
+    if needs_quote {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        // JSON has no literal for infinity
+        Value::Real(r) if !r.is_finite() => "null".to_string(),
+        Value::Integer(_) | Value::Real(_) => render(value),
+        Value::Text(text) => json_string(text),
+        Value::Blob(_) => json_string(&render(value)),
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Where query output goes: stdout, or a file given with --output. Keeps a
+// running byte count so a --max-bytes limit can cut off runaway dumps.
+// Notices about the output go to stderr, so they never end up in a pipe
+// meant for the rows.
+pub struct Printer {
+    out: Box<dyn Write>,
+    notices: Box<dyn Write>,
+    max_bytes: Option<usize>,
+    written: usize,
+    truncated: bool,
+}
+
+impl Printer {
+    pub fn new(max_bytes: Option<usize>) -> Self {
+        Printer::to_writer(Box::new(io::stdout()), max_bytes)
+    }
+
+    pub fn to_writer(out: Box<dyn Write>, max_bytes: Option<usize>) -> Self {
+        Printer { out, notices: Box::new(io::stderr()), max_bytes, written: 0, truncated: false }
+    }
+
+    pub fn notices_to(&mut self, notices: Box<dyn Write>) -> &mut Self {
+        self.notices = notices;
+        self
+    }
+
+    // Writes a line to the notice stream, whatever the byte limit
+    pub fn note(&mut self, line: &str) -> Result<()> {
+        writeln!(self.notices, "{}", line)?;
+        Ok(())
+    }
+
+    // Buffered writers only report write errors here, so call it before
+    // exiting
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    // Writes a line of output. Returns false once the byte limit has been
+    // reached, after which nothing more is written and callers can stop
+    // producing rows. Output is cut off before the line that would cross
+    // the limit, so it always ends with a whole line.
+    pub fn line(&mut self, line: &str) -> Result<bool> {
+        if self.truncated {
+            return Ok(false);
+        }
+        let mut bytes = Vec::with_capacity(line.len() + 1);
+        bytes.extend_from_slice(line.as_bytes());
+        bytes.push(b'\n');
+        if let Some(max_bytes) = self.max_bytes {
+            if self.written + bytes.len() > max_bytes {
+                self.out.flush()?;
+                self.truncated = true;
+                self.note(&format!("output truncated after {} bytes (--max-bytes {})", self.written, max_bytes))?;
+                return Ok(false);
+            }
+        }
+        self.out.write_all(&bytes)?;
+        self.written += bytes.len();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Captured {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn output_stops_at_the_last_whole_line_under_the_limit() {
+        let (out, notices) = (Captured::default(), Captured::default());
+        let mut printer = Printer::to_writer(Box::new(out.clone()), Some(10));
+        printer.notices_to(Box::new(notices.clone()));
+        assert!(printer.line("abc").unwrap());
+        assert!(printer.line("defg").unwrap());
+        assert!(!printer.line("hij").unwrap());
+        assert!(!printer.line("k").unwrap());
+        assert_eq!(out.text(), "abc\ndefg\n");
+        assert_eq!(notices.text(), "output truncated after 9 bytes (--max-bytes 10)\n");
+    }
+
+    #[test]
+    fn output_that_fits_exactly_is_not_truncated() {
+        let (out, notices) = (Captured::default(), Captured::default());
+        let mut printer = Printer::to_writer(Box::new(out.clone()), Some(8));
+        printer.notices_to(Box::new(notices.clone()));
+        assert!(printer.line("abc").unwrap());
+        assert!(printer.line("def").unwrap());
+        assert_eq!(out.text(), "abc\ndef\n");
+        assert_eq!(notices.text(), "");
+    }
+
+    #[test]
+    fn infinities_are_null_in_json() {
+        assert_eq!(json_value(&Value::Real(f64::INFINITY)), "null");
+        assert_eq!(json_value(&Value::Real(f64::NEG_INFINITY)), "null");
+        assert_eq!(json_value(&Value::Real(1.5)), "1.5");
+    }
+}
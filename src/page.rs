@@ -0,0 +1,209 @@
+use anyhow::Result;
+
+use crate::error::SqliteError;
+
+// Page 1 starts with the 100-byte database header, so its b-tree header
+// follows it; every other page starts its b-tree header at byte 0.
+pub fn btree_header_offset(page_number: u32) -> usize {
+    if page_number == 1 {
+        100
+    } else {
+        0
+    }
+}
+
+// Offsets of the cells on a b-tree page, read from the cell pointer array
+// that follows the page header. Cells live in the content area, which runs
+// from the offset in header bytes 5-6 (0 standing for 65536) to the end of
+// the page; a pointer outside it, or too close to the end for a whole cell,
+// means the page is corrupt.
+pub fn cell_pointers(page: &[u8], page_number: u32) -> Result<Vec<usize>> {
+    let header_offset = btree_header_offset(page_number);
+    let page_type = page[header_offset];
+    let cell_count = u16::from_be_bytes([page[header_offset + 3], page[header_offset + 4]]) as usize;
+    let cell_array = header_offset + if page_type == 2 || page_type == 5 { 12 } else { 8 };
+    let content_start = match u16::from_be_bytes([page[header_offset + 5], page[header_offset + 6]]) {
+        0 => 65536,
+        start => start as usize,
+    };
+    let corrupt = |detail: String| SqliteError::CorruptPage { page: page_number, detail };
+    if cell_array + 2 * cell_count > content_start.min(page.len()) {
+        let detail = format!("{} cell pointers run into the content area at {}", cell_count, content_start);
+        return Err(corrupt(detail).into());
+    }
+    let mut offsets = Vec::with_capacity(cell_count);
+    for i in 0..cell_count {
+        let offset = u16::from_be_bytes([page[cell_array + 2 * i], page[cell_array + 2 * i + 1]]) as usize;
+        if offset < content_start {
+            let detail = format!("cell {} at offset {} is before the content area at {}", i, offset, content_start);
+            return Err(corrupt(detail).into());
+        }
+        // No cell is shorter than four bytes, which also covers an interior
+        // cell's child pointer
+        if offset + 4 > page.len() {
+            return Err(corrupt(format!("cell {} at offset {} runs past the end of the page", i, offset)).into());
+        }
+        offsets.push(offset);
+    }
+    Ok(offsets)
+}
+
+// Interior pages keep their last child out of the cells, in the four bytes
+// at offset 8 of the 12-byte header. Every walk has to descend into it after
+// the cells, or it loses the subtree holding the largest keys.
+pub fn right_most_pointer(page: &[u8], page_number: u32) -> Option<u32> {
+    let header_offset = btree_header_offset(page_number);
+    match page[header_offset] {
+        2 | 5 => Some(u32::from_be_bytes([
+            page[header_offset + 8],
+            page[header_offset + 9],
+            page[header_offset + 10],
+            page[header_offset + 11],
+        ])),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageType {
+    InteriorIndex,
+    InteriorTable,
+    LeafIndex,
+    LeafTable,
+}
+
+impl PageType {
+    pub fn from_byte(byte: u8) -> Option<PageType> {
+        match byte {
+            2 => Some(PageType::InteriorIndex),
+            5 => Some(PageType::InteriorTable),
+            10 => Some(PageType::LeafIndex),
+            13 => Some(PageType::LeafTable),
+            _ => None,
+        }
+    }
+
+    pub fn is_leaf(self) -> bool {
+        matches!(self, PageType::LeafIndex | PageType::LeafTable)
+    }
+}
+
+// What a page is used for, as far as the file's structures tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    Btree(PageType),
+    Overflow,
+    Freelist,
+    // Auto-vacuum bookkeeping: the parent of each page that follows
+    PointerMap,
+    Unknown,
+}
+
+impl PageKind {
+    pub const ALL: [PageKind; 8] = [
+        PageKind::Btree(PageType::LeafTable),
+        PageKind::Btree(PageType::InteriorTable),
+        PageKind::Btree(PageType::LeafIndex),
+        PageKind::Btree(PageType::InteriorIndex),
+        PageKind::Overflow,
+        PageKind::Freelist,
+        PageKind::PointerMap,
+        PageKind::Unknown,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PageKind::Btree(PageType::LeafTable) => "leaf-table",
+            PageKind::Btree(PageType::InteriorTable) => "interior-table",
+            PageKind::Btree(PageType::LeafIndex) => "leaf-index",
+            PageKind::Btree(PageType::InteriorIndex) => "interior-index",
+            PageKind::Overflow => "overflow",
+            PageKind::Freelist => "freelist",
+            PageKind::PointerMap => "pointer-map",
+            PageKind::Unknown => "unknown",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::fixture::{FixtureBuilder, PAGE_SIZE};
+    use crate::value::Value;
+
+    #[test]
+    fn header_offset_is_100_only_on_page_1() {
+        assert_eq!(btree_header_offset(1), 100);
+        assert_eq!(btree_header_offset(2), 0);
+        assert_eq!(btree_header_offset(7), 0);
+    }
+
+    #[test]
+    fn cell_arrays_of_page_1_and_page_2() {
+        let mut db = Database::open("sample.db").unwrap();
+
+        // Page 1's cell pointers start after the file header and its own
+        // 8-byte b-tree header: one cell per schema row
+        let page_1 = db.read_page(1).unwrap();
+        let schema = cell_pointers(&page_1, 1).unwrap();
+        assert_eq!(schema.len(), 3);
+        assert_eq!(usize::from(u16::from_be_bytes([page_1[108], page_1[109]])), schema[0]);
+        // Page 2 holds apples' four rows, its pointers right after its b-tree header
+        let page_2 = db.read_page(2).unwrap();
+        assert_eq!(cell_pointers(&page_2, 2).unwrap(), [4067, 4054, 4029, 4001]);
+        assert_eq!(usize::from(u16::from_be_bytes([page_2[8], page_2[9]])), 4067);
+    }
+
+    #[test]
+    fn cell_pointer_below_the_content_area_is_corrupt() {
+        let rows: Vec<(i64, Vec<Value>)> = (1..=3).map(|id| (id, vec![Value::Integer(id)])).collect();
+        let mut image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(x)", &rows).build();
+        let page_2 = PAGE_SIZE..2 * PAGE_SIZE;
+        let content_start = u16::from_be_bytes([image[PAGE_SIZE + 5], image[PAGE_SIZE + 6]]);
+        assert!(cell_pointers(&image[page_2.clone()], 2).is_ok());
+
+        // Point the second cell one byte before the start of the content area
+        image[PAGE_SIZE + 10..PAGE_SIZE + 12].copy_from_slice(&(content_start - 1).to_be_bytes());
+        let err = cell_pointers(&image[page_2], 2).unwrap_err();
+        let expected = format!("page 2 is corrupt: cell 1 at offset {} is before the content area at {}",
+            content_start - 1, content_start);
+        assert_eq!(err.to_string(), expected);
+        let mut db = Database::from_bytes(image).unwrap();
+        let err = db.query("SELECT x FROM t", &[]).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(SqliteError::CorruptPage { page: 2, .. })), "{}", err);
+    }
+
+    #[test]
+    fn cell_pointer_in_the_last_bytes_of_the_page_is_corrupt() {
+        let rows: Vec<(i64, Vec<Value>)> = (1..=3).map(|id| (id, vec![Value::Integer(id)])).collect();
+        let mut image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(x)", &rows).build();
+        let pointer = (PAGE_SIZE as u16 - 2).to_be_bytes();
+        image[PAGE_SIZE + 10..PAGE_SIZE + 12].copy_from_slice(&pointer);
+        let err = cell_pointers(&image[PAGE_SIZE..2 * PAGE_SIZE], 2).unwrap_err();
+        let expected = format!("page 2 is corrupt: cell 1 at offset {} runs past the end of the page", PAGE_SIZE - 2);
+        assert_eq!(err.to_string(), expected);
+        let mut db = Database::from_bytes(image).unwrap();
+        let err = db.query("SELECT x FROM t", &[]).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(SqliteError::CorruptPage { page: 2, .. })), "{}", err);
+    }
+
+    #[test]
+    fn payload_running_past_the_page_is_an_error() {
+        let rows: Vec<(i64, Vec<Value>)> = (1..=3).map(|id| (id, vec![Value::Integer(id)])).collect();
+        let mut image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(x)", &rows).build();
+        let cells = cell_pointers(&image[PAGE_SIZE..2 * PAGE_SIZE], 2).unwrap();
+        let last = *cells.iter().max().unwrap();
+
+        // Claim a 100-byte payload for the cell that ends the page. Its size
+        // and rowid varints take a byte each, and 100 bytes is under the
+        // page's local limit, so nothing spills to overflow pages.
+        image[PAGE_SIZE + last] = 100;
+        let mut db = Database::from_bytes(image).unwrap();
+        let err = db.query("SELECT x FROM t", &[]).unwrap_err();
+        let start = last + 2;
+        let expected = format!("cell payload at offset {} runs {} bytes past the end of the page",
+            start, start + 100 - PAGE_SIZE);
+        assert_eq!(err.to_string(), expected);
+    }
+}
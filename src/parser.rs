@@ -0,0 +1,962 @@
+use anyhow::{bail, Result};
+use std::fmt;
+
+use crate::tokenizer::{tokenize, Token};
+use crate::value::{render, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultColumn {
+    Star,
+    Column(String),
+    // Anything computed, such as `price * quantity`
+    Expr(Expr),
+    // COUNT, MIN, MAX or SUM over the rows of each group; the argument is
+    // None for COUNT(*)
+    Aggregate { function: AggregateFunction, arg: Option<Expr> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Min,
+    Max,
+    Sum,
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            "SUM" => Some(AggregateFunction::Sum),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+            AggregateFunction::Sum => "SUM",
+        }
+    }
+}
+
+// Functions computed from a single row's values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarFunction {
+    Typeof,
+    Length,
+    Abs,
+    Upper,
+    Lower,
+    Substr,
+    Instr,
+    Hex,
+    Quote,
+}
+
+impl ScalarFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "typeof" => Some(ScalarFunction::Typeof),
+            "length" => Some(ScalarFunction::Length),
+            "abs" => Some(ScalarFunction::Abs),
+            "upper" => Some(ScalarFunction::Upper),
+            "lower" => Some(ScalarFunction::Lower),
+            "substr" => Some(ScalarFunction::Substr),
+            "instr" => Some(ScalarFunction::Instr),
+            "hex" => Some(ScalarFunction::Hex),
+            "quote" => Some(ScalarFunction::Quote),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScalarFunction::Typeof => "typeof",
+            ScalarFunction::Length => "length",
+            ScalarFunction::Abs => "abs",
+            ScalarFunction::Upper => "upper",
+            ScalarFunction::Lower => "lower",
+            ScalarFunction::Substr => "substr",
+            ScalarFunction::Instr => "instr",
+            ScalarFunction::Hex => "hex",
+            ScalarFunction::Quote => "quote",
+        }
+    }
+
+    // Fewest and most arguments the function takes
+    fn arity(self) -> (usize, usize) {
+        match self {
+            ScalarFunction::Substr => (2, 3),
+            ScalarFunction::Instr => (2, 2),
+            _ => (1, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // Equality under which NULL is NULL and nothing else, never unknown
+    Is,
+    IsNot,
+    And,
+    Or,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // `a || b`, the operands' text joined
+    Concat,
+    // `x GLOB pattern`; the pattern is the right operand
+    Glob,
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Eq => "=",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Is => "IS",
+            BinaryOp::IsNot => "IS NOT",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Concat => "||",
+            BinaryOp::Glob => "GLOB",
+        }
+    }
+
+    // Binding strength, matching the levels of the parse_* functions below
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div => 6,
+            BinaryOp::Concat => 7,
+            _ => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Value),
+    // A `?` placeholder, numbered from 0 in order of appearance
+    Parameter(usize),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+    // `x IN (a, b, ...)`; NOT IN is the negation, which keeps its NULLs
+    In { expr: Box<Expr>, list: Vec<Expr> },
+    // `x IN (SELECT ...)`, a single-column subquery that doesn't refer to
+    // the outer row. It is run before the outer query and replaced by an
+    // In of its values.
+    InSelect { expr: Box<Expr>, select: Box<SelectStatement> },
+    // A call such as `length(name)`
+    Function { function: ScalarFunction, args: Vec<Expr> },
+}
+
+impl Expr {
+    // Every column the expression refers to, in order of appearance
+    pub fn column_names(&self, names: &mut Vec<String>) {
+        match self {
+            Expr::Column(name) => names.push(name.clone()),
+            Expr::Literal(_) | Expr::Parameter(_) => {}
+            Expr::Not(inner) | Expr::Negate(inner) => inner.column_names(names),
+            Expr::Binary { left, right, .. } => {
+                left.column_names(names);
+                right.column_names(names);
+            }
+            Expr::In { expr, list } => {
+                expr.column_names(names);
+                for item in list.iter() {
+                    item.column_names(names);
+                }
+            }
+            // The subquery's columns belong to its own table
+            Expr::InSelect { expr, .. } => expr.column_names(names),
+            Expr::Function { args, .. } => {
+                for arg in args.iter() {
+                    arg.column_names(names);
+                }
+            }
+        }
+    }
+
+    // Replaces each subquery with an In of the values `run` returns for it
+    pub fn resolve_subqueries(&mut self, run: &mut dyn FnMut(&SelectStatement) -> Result<Vec<Value>>) -> Result<()> {
+        match self {
+            Expr::InSelect { expr, select } => {
+                expr.resolve_subqueries(run)?;
+                let list = run(select)?.into_iter().map(Expr::Literal).collect();
+                let expr = std::mem::replace(expr, Box::new(Expr::Literal(Value::Null)));
+                *self = Expr::In { expr, list };
+            }
+            Expr::Column(_) | Expr::Literal(_) | Expr::Parameter(_) => {}
+            Expr::Not(inner) | Expr::Negate(inner) => inner.resolve_subqueries(run)?,
+            Expr::Binary { left, right, .. } => {
+                left.resolve_subqueries(run)?;
+                right.resolve_subqueries(run)?;
+            }
+            Expr::In { expr, list } => {
+                expr.resolve_subqueries(run)?;
+                for item in list.iter_mut() {
+                    item.resolve_subqueries(run)?;
+                }
+            }
+            Expr::Function { args, .. } => {
+                for arg in args.iter_mut() {
+                    arg.resolve_subqueries(run)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_subquery(&self) -> bool {
+        match self {
+            Expr::InSelect { .. } => true,
+            Expr::Column(_) | Expr::Literal(_) | Expr::Parameter(_) => false,
+            Expr::Not(inner) | Expr::Negate(inner) => inner.has_subquery(),
+            Expr::Binary { left, right, .. } => left.has_subquery() || right.has_subquery(),
+            Expr::In { expr, list } => expr.has_subquery() || list.iter().any(Expr::has_subquery),
+            Expr::Function { args, .. } => args.iter().any(Expr::has_subquery),
+        }
+    }
+
+    fn parameter_count(&self) -> usize {
+        match self {
+            Expr::Parameter(_) => 1,
+            Expr::Column(_) | Expr::Literal(_) => 0,
+            Expr::Not(inner) | Expr::Negate(inner) => inner.parameter_count(),
+            Expr::Binary { left, right, .. } => left.parameter_count() + right.parameter_count(),
+            Expr::In { expr, list } => expr.parameter_count() + list.iter().map(Expr::parameter_count).sum::<usize>(),
+            Expr::InSelect { expr, select } => expr.parameter_count() + select.parameter_count(),
+            Expr::Function { args, .. } => args.iter().map(Expr::parameter_count).sum(),
+        }
+    }
+
+    // Replaces every placeholder with its value from `params`
+    fn bind(&mut self, params: &[Value]) {
+        match self {
+            Expr::Parameter(index) => *self = Expr::Literal(params[*index].clone()),
+            Expr::Column(_) | Expr::Literal(_) => {}
+            Expr::Not(inner) | Expr::Negate(inner) => inner.bind(params),
+            Expr::Binary { left, right, .. } => {
+                left.bind(params);
+                right.bind(params);
+            }
+            Expr::In { expr, list } => {
+                expr.bind(params);
+                for item in list.iter_mut() {
+                    item.bind(params);
+                }
+            }
+            Expr::InSelect { expr, select } => {
+                expr.bind(params);
+                for inner in select.expressions() {
+                    inner.bind(params);
+                }
+            }
+            Expr::Function { args, .. } => {
+                for arg in args.iter_mut() {
+                    arg.bind(params);
+                }
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary { op, .. } => op.precedence(),
+            Expr::Not(_) => 3,
+            Expr::In { .. } | Expr::InSelect { .. } => 4,
+            _ => 7,
+        }
+    }
+}
+
+// SQL text for the expression, which also names computed result columns
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Parenthesise operands that bind more loosely than their parent
+        let operand = |f: &mut fmt::Formatter, expr: &Expr, min: u8| {
+            if expr.precedence() < min {
+                write!(f, "({})", expr)
+            } else {
+                write!(f, "{}", expr)
+            }
+        };
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(Value::Null) => write!(f, "NULL"),
+            Expr::Literal(Value::Text(text)) => write!(f, "'{}'", text.replace('\'', "''")),
+            Expr::Literal(value @ Value::Blob(_)) => write!(f, "X'{}'", render(value)),
+            Expr::Literal(value) => write!(f, "{}", render(value)),
+            Expr::Parameter(_) => write!(f, "?"),
+            Expr::Not(inner) => {
+                write!(f, "NOT ")?;
+                operand(f, inner, 3)
+            }
+            Expr::Negate(inner) => {
+                write!(f, "-")?;
+                operand(f, inner, 8)
+            }
+            Expr::Binary { op, left, right } => {
+                operand(f, left, op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                // Right operands of equal precedence need parentheses too, as
+                // in a - (b - c)
+                operand(f, right, op.precedence() + 1)
+            }
+            Expr::In { expr, list } => {
+                operand(f, expr, 5)?;
+                write!(f, " IN (")?;
+                for (i, item) in list.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { "" }, item)?;
+                }
+                write!(f, ")")
+            }
+            Expr::InSelect { expr, select } => {
+                operand(f, expr, 5)?;
+                write!(f, " IN ({})", select)
+            }
+            Expr::Function { function, args } => {
+                write!(f, "{}(", function.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { "" }, arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderTerm {
+    pub expr: Expr,
+    pub descending: bool,
+    // Set by NULLS FIRST / NULLS LAST; otherwise NULLs sort as the smallest
+    // value, first when ascending and last when descending
+    pub nulls_first: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectStatement {
+    pub columns: Vec<ResultColumn>,
+    // Database alias qualifying the table, as in `other.t`
+    pub schema: Option<String>,
+    // None with no FROM clause, as in `SELECT 1 + 1`
+    pub table: Option<String>,
+    pub where_clause: Option<Expr>,
+    // A single grouping key
+    pub group_by: Option<Expr>,
+    // Sort keys, each consulted only when the ones before it tie
+    pub order_by: Vec<OrderTerm>,
+    // Rows kept after sorting, if capped, and how many are skipped first
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl fmt::Display for ResultColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResultColumn::Star => write!(f, "*"),
+            ResultColumn::Column(name) => write!(f, "{}", name),
+            ResultColumn::Expr(expr) => write!(f, "{}", expr),
+            ResultColumn::Aggregate { function, arg: None } => write!(f, "{}(*)", function.name()),
+            ResultColumn::Aggregate { function, arg: Some(arg) } => write!(f, "{}({})", function.name(), arg),
+        }
+    }
+}
+
+// SQL text for the statement, as shown for subqueries
+impl fmt::Display for SelectStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SELECT ")?;
+        for (i, column) in self.columns.iter().enumerate() {
+            write!(f, "{}{}", if i > 0 { ", " } else { "" }, column)?;
+        }
+        if let Some(table) = &self.table {
+            write!(f, " FROM ")?;
+            if let Some(schema) = &self.schema {
+                write!(f, "{}.", schema)?;
+            }
+            write!(f, "{}", table)?;
+        }
+        if let Some(condition) = &self.where_clause {
+            write!(f, " WHERE {}", condition)?;
+        }
+        if let Some(key) = &self.group_by {
+            write!(f, " GROUP BY {}", key)?;
+        }
+        for (i, term) in self.order_by.iter().enumerate() {
+            write!(f, "{}", if i > 0 { ", " } else { " ORDER BY " })?;
+            write!(f, "{}{}", term.expr, if term.descending { " DESC" } else { "" })?;
+            match term.nulls_first {
+                Some(true) => write!(f, " NULLS FIRST")?,
+                Some(false) => write!(f, " NULLS LAST")?,
+                None => {}
+            }
+        }
+        if let Some(limit) = self.limit {
+            write!(f, " LIMIT {}", limit)?;
+        }
+        if self.offset > 0 {
+            write!(f, "{} OFFSET {}", if self.limit.is_none() { " LIMIT -1" } else { "" }, self.offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl SelectStatement {
+    fn expressions_ref(&self) -> impl Iterator<Item = &Expr> {
+        let columns = self.columns.iter().filter_map(|column| match column {
+            ResultColumn::Expr(expr) | ResultColumn::Aggregate { arg: Some(expr), .. } => Some(expr),
+            _ => None,
+        });
+        columns
+            .chain(self.where_clause.as_ref())
+            .chain(self.group_by.as_ref())
+            .chain(self.order_by.iter().map(|term| &term.expr))
+    }
+
+    // Whether rows are folded into groups, by GROUP BY or by an aggregate
+    // over the whole table
+    pub fn is_aggregate(&self) -> bool {
+        self.group_by.is_some() || self.columns.iter().any(|column| matches!(column, ResultColumn::Aggregate { .. }))
+    }
+
+    fn parameter_count(&self) -> usize {
+        self.expressions_ref().map(Expr::parameter_count).sum()
+    }
+
+    pub fn has_subquery(&self) -> bool {
+        self.expressions_ref().any(Expr::has_subquery)
+    }
+
+    // The expressions of the result columns, WHERE, GROUP BY and ORDER BY
+    pub fn expressions(&mut self) -> impl Iterator<Item = &mut Expr> {
+        let columns = self.columns.iter_mut().filter_map(|column| match column {
+            ResultColumn::Expr(expr) | ResultColumn::Aggregate { arg: Some(expr), .. } => Some(expr),
+            _ => None,
+        });
+        columns
+            .chain(self.where_clause.as_mut())
+            .chain(self.group_by.as_mut())
+            .chain(self.order_by.iter_mut().map(|term| &mut term.expr))
+    }
+
+    // Substitutes `params` for the statement's `?` placeholders, in order.
+    // Bound values become literals, so the planner can search an index on them.
+    pub fn bind(&mut self, params: &[Value]) -> Result<()> {
+        let expected = self.parameter_count();
+        if expected != params.len() {
+            bail!("statement has {} parameters but {} values were given", expected, params.len());
+        }
+        for expr in self.expressions() {
+            expr.bind(params);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(SelectStatement),
+    // EXPLAIN [QUERY PLAN] SELECT ..., which reports the plan instead of
+    // running the query
+    Explain(SelectStatement),
+    // PRAGMA [schema.]name[(argument)], reading a setting or listing
+    // something about the named object
+    Pragma { schema: Option<String>, name: String, argument: Option<String> },
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // Placeholders seen so far
+    parameters: usize,
+    // (qualifier, column) of each `table.column` reference not yet checked
+    // against its SELECT's FROM table
+    qualified: Vec<(String, String)>,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Parser { tokens, pos: 0, parameters: 0, qualified: Vec::new() }
+    }
+
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    // A statement may end in any number of semicolons, and nothing else
+    fn expect_end(&mut self) -> Result<()> {
+        while self.eat_symbol(";") {}
+        if !self.at_end() {
+            bail!("unexpected {} in statement", self.describe_next());
+        }
+        Ok(())
+    }
+
+    pub fn peek_keyword(&self, keyword: &str) -> bool {
+        self.peek().is_some_and(|token| token.is_keyword(keyword))
+    }
+
+    // Looks `n` tokens past the next one
+    pub fn peek_keyword_after(&self, n: usize, keyword: &str) -> bool {
+        self.tokens.get(self.pos + n).is_some_and(|token| token.is_keyword(keyword))
+    }
+
+    pub fn peek_symbol_after(&self, n: usize, symbol: &str) -> bool {
+        matches!(self.tokens.get(self.pos + n), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    pub fn peek_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    // Consumes the keyword if it comes next
+    pub fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    pub fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = self.peek_symbol(symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    pub fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if !self.eat_keyword(keyword) {
+            bail!("expected {}, found {}", keyword, self.describe_next());
+        }
+        Ok(())
+    }
+
+    pub fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if !self.eat_symbol(symbol) {
+            bail!("expected '{}', found {}", symbol, self.describe_next());
+        }
+        Ok(())
+    }
+
+    pub fn identifier(&mut self) -> Result<String> {
+        match self.next_token() {
+            Some(Token::Ident(name)) | Some(Token::QuotedIdent(name)) => Ok(name),
+            Some(other) => bail!("expected identifier, found {:?}", other),
+            None => bail!("expected identifier, found end of statement"),
+        }
+    }
+
+    pub fn describe_next(&self) -> String {
+        match self.peek() {
+            Some(token) => format!("{:?}", token),
+            None => "end of statement".to_string(),
+        }
+    }
+}
+
+pub fn parse_statement(sql: &str) -> Result<Statement> {
+    let mut parser = Parser::new(tokenize(sql)?);
+    if parser.eat_keyword("EXPLAIN") {
+        if parser.eat_keyword("QUERY") {
+            parser.expect_keyword("PLAN")?;
+        }
+        return Ok(Statement::Explain(parse_select_from(&mut parser)?));
+    }
+    if parser.eat_keyword("PRAGMA") {
+        let mut schema = None;
+        let mut name = parser.identifier()?;
+        if parser.eat_symbol(".") {
+            schema = Some(name);
+            name = parser.identifier()?;
+        }
+        let mut argument = None;
+        if parser.eat_symbol("(") {
+            argument = Some(match parser.next_token() {
+                Some(Token::Ident(value)) | Some(Token::QuotedIdent(value)) | Some(Token::Str(value)) => value,
+                Some(other) => bail!("expected pragma argument, found {:?}", other),
+                None => bail!("expected pragma argument, found end of statement"),
+            });
+            parser.expect_symbol(")")?;
+        }
+        parser.expect_end()?;
+        return Ok(Statement::Pragma { schema, name, argument });
+    }
+    Ok(Statement::Select(parse_select_from(&mut parser)?))
+}
+
+pub fn parse_select(sql: &str) -> Result<SelectStatement> {
+    parse_select_from(&mut Parser::new(tokenize(sql)?))
+}
+
+// Parses a SELECT running to the end of the tokens
+pub fn parse_select_from(parser: &mut Parser) -> Result<SelectStatement> {
+    let select = parse_select_body(parser)?;
+    parser.expect_end()?;
+    Ok(select)
+}
+
+// A SELECT up to wherever its clauses end, as for a subquery
+fn parse_select_body(parser: &mut Parser) -> Result<SelectStatement> {
+    parser.expect_keyword("SELECT")?;
+    // Subqueries check and drop their own qualified columns before returning
+    let qualified_from = parser.qualified.len();
+    let mut columns = Vec::new();
+    loop {
+        let aggregate = match parser.peek() {
+            Some(Token::Ident(name)) if parser.peek_symbol_after(1, "(") => AggregateFunction::from_name(name),
+            _ => None,
+        };
+        if parser.eat_symbol("*") {
+            columns.push(ResultColumn::Star);
+        } else if let Some(function) = aggregate {
+            parser.next_token();
+            parser.expect_symbol("(")?;
+            let arg = if function == AggregateFunction::Count && parser.eat_symbol("*") {
+                None
+            } else {
+                Some(parse_expr(parser)?)
+            };
+            parser.expect_symbol(")")?;
+            let end = parser.at_end() || parser.peek_symbol(";");
+            if !parser.peek_symbol(",") && !parser.peek_keyword("FROM") && !end {
+                bail!("{} is only supported as a whole result column", function.name());
+            }
+            columns.push(ResultColumn::Aggregate { function, arg });
+        } else {
+            columns.push(match parse_expr(parser)? {
+                Expr::Column(name) => ResultColumn::Column(name),
+                expr => ResultColumn::Expr(expr),
+            });
+        }
+        if !parser.eat_symbol(",") {
+            break;
+        }
+    }
+    let mut schema = None;
+    let mut table = None;
+    if parser.eat_keyword("FROM") {
+        let mut name = parser.identifier()?;
+        if parser.eat_symbol(".") {
+            schema = Some(name);
+            name = parser.identifier()?;
+        }
+        table = Some(name);
+    } else if columns.contains(&ResultColumn::Star) {
+        bail!("no tables specified");
+    }
+    let where_clause = if parser.eat_keyword("WHERE") { Some(parse_expr(parser)?) } else { None };
+    let mut group_by = None;
+    if parser.eat_keyword("GROUP") {
+        parser.expect_keyword("BY")?;
+        group_by = Some(parse_expr(parser)?);
+    }
+    let mut order_by = Vec::new();
+    if parser.eat_keyword("ORDER") {
+        parser.expect_keyword("BY")?;
+        loop {
+            order_by.push(parse_order_term(parser)?);
+            if !parser.eat_symbol(",") {
+                break;
+            }
+        }
+    }
+    // `LIMIT count [OFFSET skip]`, or the older `LIMIT skip, count`
+    let (mut limit, mut offset) = (None, 0);
+    if parser.eat_keyword("LIMIT") {
+        let first = parse_limit_value(parser)?;
+        let (count, skip) = if parser.eat_symbol(",") {
+            (parse_limit_value(parser)?, first)
+        } else if parser.eat_keyword("OFFSET") {
+            (first, parse_limit_value(parser)?)
+        } else {
+            (first, 0)
+        };
+        // As in SQLite, a negative count means no limit and a negative
+        // offset skips nothing
+        limit = usize::try_from(count).ok();
+        offset = usize::try_from(skip).unwrap_or(0);
+    }
+    // There's only ever the one table to qualify a column with
+    for (qualifier, column) in parser.qualified.drain(qualified_from..) {
+        if !table.as_deref().is_some_and(|table| qualifier.eq_ignore_ascii_case(table)) {
+            bail!("no such column: {}.{}", qualifier, column);
+        }
+    }
+    Ok(SelectStatement { columns, schema, table, where_clause, group_by, order_by, limit, offset })
+}
+
+// An integer literal, possibly negated, for LIMIT or OFFSET
+fn parse_limit_value(parser: &mut Parser) -> Result<i64> {
+    let negative = parser.eat_symbol("-");
+    match parser.next_token() {
+        Some(Token::Integer(i)) if negative => Ok(i.saturating_neg()),
+        Some(Token::Integer(i)) => Ok(i),
+        Some(other) => bail!("LIMIT and OFFSET take an integer, not {:?}", other),
+        None => bail!("unexpected end of statement after LIMIT"),
+    }
+}
+
+// `expr [ASC|DESC] [NULLS FIRST|LAST]`
+fn parse_order_term(parser: &mut Parser) -> Result<OrderTerm> {
+    let expr = parse_expr(parser)?;
+    let descending = parser.eat_keyword("DESC");
+    if !descending {
+        parser.eat_keyword("ASC");
+    }
+    let mut nulls_first = None;
+    if parser.eat_keyword("NULLS") {
+        if parser.eat_keyword("FIRST") {
+            nulls_first = Some(true);
+        } else {
+            parser.expect_keyword("LAST")?;
+            nulls_first = Some(false);
+        }
+    }
+    Ok(OrderTerm { expr, descending, nulls_first })
+}
+
+// Precedence from loosest to tightest: OR, AND, NOT, comparisons, + and -,
+// * and /, unary minus
+pub fn parse_expr(parser: &mut Parser) -> Result<Expr> {
+    let mut left = parse_and(parser)?;
+    while parser.eat_keyword("OR") {
+        let right = parse_and(parser)?;
+        left = Expr::Binary { op: BinaryOp::Or, left: Box::new(left), right: Box::new(right) };
+    }
+    Ok(left)
+}
+
+fn parse_and(parser: &mut Parser) -> Result<Expr> {
+    let mut left = parse_not(parser)?;
+    while parser.eat_keyword("AND") {
+        let right = parse_not(parser)?;
+        left = Expr::Binary { op: BinaryOp::And, left: Box::new(left), right: Box::new(right) };
+    }
+    Ok(left)
+}
+
+fn parse_not(parser: &mut Parser) -> Result<Expr> {
+    if parser.eat_keyword("NOT") {
+        return Ok(Expr::Not(Box::new(parse_not(parser)?)));
+    }
+    parse_comparison(parser)
+}
+
+fn parse_comparison(parser: &mut Parser) -> Result<Expr> {
+    let left = parse_additive(parser)?;
+    let negated = parser.peek_keyword("NOT")
+        && ["BETWEEN", "GLOB", "IN"].iter().any(|keyword| parser.peek_keyword_after(1, keyword));
+    if negated {
+        parser.next_token();
+    }
+    if parser.eat_keyword("BETWEEN") {
+        return parse_between(parser, left, negated);
+    }
+    if parser.eat_keyword("IN") {
+        let membership = parse_in(parser, left)?;
+        return Ok(if negated { Expr::Not(Box::new(membership)) } else { membership });
+    }
+    if parser.eat_keyword("GLOB") {
+        let pattern = parse_additive(parser)?;
+        let glob = Expr::Binary { op: BinaryOp::Glob, left: Box::new(left), right: Box::new(pattern) };
+        return Ok(if negated { Expr::Not(Box::new(glob)) } else { glob });
+    }
+    if parser.eat_keyword("IS") {
+        let op = if parser.eat_keyword("NOT") { BinaryOp::IsNot } else { BinaryOp::Is };
+        let right = parse_additive(parser)?;
+        return Ok(Expr::Binary { op, left: Box::new(left), right: Box::new(right) });
+    }
+    let op = match parser.peek() {
+        Some(Token::Symbol("=")) | Some(Token::Symbol("==")) => BinaryOp::Eq,
+        Some(Token::Symbol("!=")) | Some(Token::Symbol("<>")) => BinaryOp::Ne,
+        Some(Token::Symbol("<")) => BinaryOp::Lt,
+        Some(Token::Symbol("<=")) => BinaryOp::Le,
+        Some(Token::Symbol(">")) => BinaryOp::Gt,
+        Some(Token::Symbol(">=")) => BinaryOp::Ge,
+        _ => return Ok(left),
+    };
+    parser.next_token();
+    let right = parse_additive(parser)?;
+    Ok(Expr::Binary { op, left: Box::new(left), right: Box::new(right) })
+}
+
+fn parse_additive(parser: &mut Parser) -> Result<Expr> {
+    let mut left = parse_multiplicative(parser)?;
+    loop {
+        let op = match parser.peek() {
+            Some(Token::Symbol("+")) => BinaryOp::Add,
+            Some(Token::Symbol("-")) => BinaryOp::Sub,
+            _ => return Ok(left),
+        };
+        parser.next_token();
+        let right = parse_multiplicative(parser)?;
+        left = Expr::Binary { op, left: Box::new(left), right: Box::new(right) };
+    }
+}
+
+fn parse_multiplicative(parser: &mut Parser) -> Result<Expr> {
+    let mut left = parse_concat(parser)?;
+    loop {
+        let op = match parser.peek() {
+            Some(Token::Symbol("*")) => BinaryOp::Mul,
+            Some(Token::Symbol("/")) => BinaryOp::Div,
+            _ => return Ok(left),
+        };
+        parser.next_token();
+        let right = parse_concat(parser)?;
+        left = Expr::Binary { op, left: Box::new(left), right: Box::new(right) };
+    }
+}
+
+// `||` binds tighter than any other binary operator, as in SQLite
+fn parse_concat(parser: &mut Parser) -> Result<Expr> {
+    let mut left = parse_unary(parser)?;
+    while parser.eat_symbol("||") {
+        let right = parse_unary(parser)?;
+        left = Expr::Binary { op: BinaryOp::Concat, left: Box::new(left), right: Box::new(right) };
+    }
+    Ok(left)
+}
+
+// A minus in front of a number folds into the literal
+fn parse_unary(parser: &mut Parser) -> Result<Expr> {
+    if parser.eat_symbol("+") {
+        return parse_unary(parser);
+    }
+    if !parser.eat_symbol("-") {
+        return parse_primary(parser);
+    }
+    Ok(match parse_unary(parser)? {
+        Expr::Literal(Value::Integer(i)) => Expr::Literal(Value::Integer(-i)),
+        Expr::Literal(Value::Real(r)) => Expr::Literal(Value::Real(-r)),
+        expr => Expr::Negate(Box::new(expr)),
+    })
+}
+
+// The parenthesised list or subquery after `x IN`
+fn parse_in(parser: &mut Parser, expr: Expr) -> Result<Expr> {
+    parser.expect_symbol("(")?;
+    let expr = Box::new(expr);
+    if parser.peek_keyword("SELECT") {
+        let select = Box::new(parse_select_body(parser)?);
+        parser.expect_symbol(")")?;
+        return Ok(Expr::InSelect { expr, select });
+    }
+    let mut list = Vec::new();
+    if !parser.eat_symbol(")") {
+        list.push(parse_expr(parser)?);
+        while parser.eat_symbol(",") {
+            list.push(parse_expr(parser)?);
+        }
+        parser.expect_symbol(")")?;
+    }
+    Ok(Expr::In { expr, list })
+}
+
+// `x BETWEEN lo AND hi` is `x >= lo AND x <= hi`, and NOT BETWEEN its negation
+fn parse_between(parser: &mut Parser, expr: Expr, negated: bool) -> Result<Expr> {
+    let low = parse_additive(parser)?;
+    parser.expect_keyword("AND")?;
+    let high = parse_additive(parser)?;
+    let range = Expr::Binary {
+        op: BinaryOp::And,
+        left: Box::new(Expr::Binary { op: BinaryOp::Ge, left: Box::new(expr.clone()), right: Box::new(low) }),
+        right: Box::new(Expr::Binary { op: BinaryOp::Le, left: Box::new(expr), right: Box::new(high) }),
+    };
+    Ok(if negated { Expr::Not(Box::new(range)) } else { range })
+}
+
+fn parse_primary(parser: &mut Parser) -> Result<Expr> {
+    match parser.next_token() {
+        Some(Token::Symbol("(")) => {
+            let expr = parse_expr(parser)?;
+            parser.expect_symbol(")")?;
+            Ok(expr)
+        }
+        Some(Token::Integer(i)) => Ok(Expr::Literal(Value::Integer(i))),
+        Some(Token::Real(r)) => Ok(Expr::Literal(Value::Real(r))),
+        Some(Token::Str(s)) => Ok(Expr::Literal(Value::Text(s))),
+        Some(token) if token.is_keyword("NULL") => Ok(Expr::Literal(Value::Null)),
+        Some(Token::Symbol("?")) => {
+            parser.parameters += 1;
+            Ok(Expr::Parameter(parser.parameters - 1))
+        }
+        Some(Token::Ident(name)) if parser.peek_symbol("(") => parse_function(parser, &name),
+        Some(Token::Ident(name)) | Some(Token::QuotedIdent(name)) => {
+            if !parser.eat_symbol(".") {
+                return Ok(Expr::Column(name));
+            }
+            let column = parser.identifier()?;
+            parser.qualified.push((name, column.clone()));
+            Ok(Expr::Column(column))
+        }
+        Some(other) => bail!("unexpected {:?} in expression", other),
+        None => bail!("unexpected end of statement in expression"),
+    }
+}
+
+// A scalar function call, from just after its name
+fn parse_function(parser: &mut Parser, name: &str) -> Result<Expr> {
+    let function = match ScalarFunction::from_name(name) {
+        Some(function) => function,
+        None if AggregateFunction::from_name(name).is_some() => {
+            bail!("{} is only supported as a whole result column", name.to_ascii_uppercase())
+        }
+        None => bail!("no such function: {}", name),
+    };
+    parser.expect_symbol("(")?;
+    let mut args = Vec::new();
+    if !parser.eat_symbol(")") {
+        loop {
+            args.push(parse_expr(parser)?);
+            if !parser.eat_symbol(",") {
+                break;
+            }
+        }
+        parser.expect_symbol(")")?;
+    }
+    let (min, max) = function.arity();
+    if args.len() < min || args.len() > max {
+        bail!("wrong number of arguments to function {}()", function.name());
+    }
+    Ok(Expr::Function { function, args })
+}
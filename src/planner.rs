@@ -0,0 +1,370 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::parser::{BinaryOp, Expr, ResultColumn, SelectStatement};
+use crate::schema::{
+    is_schema_table, parse_create_index, parse_create_table, parse_create_view, schema_table, untyped_column,
+    TableSchema,
+};
+use crate::value::{compare_values, Collation, Value};
+use crate::Records;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    FullScan,
+    // A single row, found by descending the table b-tree on its rowid
+    RowidLookup { rowid: i64 },
+    // Index entries whose leading columns equal `key` (every entry, when
+    // it's empty) and whose next column falls within `range`. Unless the
+    // index covers every column the query uses, each row is then fetched
+    // from the table by rowid.
+    IndexSearch {
+        index: String,
+        root_page: u32,
+        columns: Vec<String>,
+        key: Vec<Value>,
+        range: KeyRange,
+        covering: bool,
+    },
+    // Rows produced by running the view's own SELECT
+    View { select: Box<SelectStatement>, plan: Box<QueryPlan> },
+    // A single row with no columns, for a SELECT without a FROM clause
+    Constant,
+}
+
+// Bounds on a single index column, each with whether it's inclusive. An
+// unbounded range matches every value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct KeyRange {
+    pub lower: Option<(Value, bool)>,
+    pub upper: Option<(Value, bool)>,
+}
+
+impl KeyRange {
+    pub fn is_unbounded(&self) -> bool {
+        self.lower.is_none() && self.upper.is_none()
+    }
+
+    // Where a value falls relative to the range: Less below it, Greater
+    // above it and Equal within it
+    pub fn position(&self, value: &Value) -> Ordering {
+        if let Some((lower, inclusive)) = &self.lower {
+            match compare_values(value, lower) {
+                Ordering::Less => return Ordering::Less,
+                Ordering::Equal if !inclusive => return Ordering::Less,
+                _ => {}
+            }
+        }
+        if let Some((upper, inclusive)) = &self.upper {
+            match compare_values(value, upper) {
+                Ordering::Greater => return Ordering::Greater,
+                Ordering::Equal if !inclusive => return Ordering::Greater,
+                _ => {}
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryPlan {
+    pub table: TableSchema,
+    pub root_page: u32,
+    pub access: Access,
+}
+
+// Written the way sqlite3's EXPLAIN QUERY PLAN words it
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.access {
+            Access::FullScan => write!(f, "SCAN {}", self.table.name),
+            Access::RowidLookup { .. } => write!(f, "SEARCH {} USING INTEGER PRIMARY KEY (rowid=?)", self.table.name),
+            Access::IndexSearch { index, columns, key, range, covering, .. } => {
+                let kind = if *covering { "COVERING INDEX" } else { "INDEX" };
+                if key.is_empty() && range.is_unbounded() {
+                    return write!(f, "SCAN {} USING {} {}", self.table.name, kind, index);
+                }
+                let mut terms: Vec<String> =
+                    columns[..key.len()].iter().map(|column| format!("{}=?", column)).collect();
+                // sqlite3 writes bounds as > and < whether or not they're inclusive
+                if range.lower.is_some() {
+                    terms.push(format!("{}>?", columns[key.len()]));
+                }
+                if range.upper.is_some() {
+                    terms.push(format!("{}<?", columns[key.len()]));
+                }
+                write!(f, "SEARCH {} USING {} {} ({})", self.table.name, kind, index, terms.join(" AND "))
+            }
+            Access::View { plan, .. } => write!(f, "{}", plan),
+            Access::Constant => write!(f, "SCAN CONSTANT ROW"),
+        }
+    }
+}
+
+pub fn plan(select: &SelectStatement, records: &Records) -> Result<QueryPlan> {
+    let name = match &select.table {
+        Some(name) => name,
+        None => {
+            let table = TableSchema { name: String::new(), columns: Vec::new(), rowid_alias: None };
+            return Ok(QueryPlan { table, root_page: 0, access: Access::Constant });
+        }
+    };
+    if is_schema_table(name) {
+        return Ok(QueryPlan { table: schema_table(name), root_page: 1, access: Access::FullScan });
+    }
+    if let Some(record) = records.find_view(name) {
+        return plan_view(&record.sql, records);
+    }
+    let (root_page, table) = match records.find(name) {
+        Some(record) => (record.root_page as u32, parse_create_table(&record.sql)?),
+        None => return Err(records.not_found(name).into()),
+    };
+    let access = choose_index(records, &table, select);
+    Ok(QueryPlan { table, root_page, access })
+}
+
+// A view reads as a table whose columns are its SELECT's result columns
+fn plan_view(sql: &str, records: &Records) -> Result<QueryPlan> {
+    let view = parse_create_view(sql)?;
+    let inner = plan(&view.select, records)?;
+    let mut names: Vec<String> = Vec::new();
+    for column in view.select.columns.iter() {
+        match column {
+            ResultColumn::Star => names.extend(inner.table.columns.iter().map(|column| column.name.clone())),
+            ResultColumn::Column(name) => names.push(name.clone()),
+            ResultColumn::Expr(expr) => names.push(expr.to_string()),
+            aggregate @ ResultColumn::Aggregate { .. } => names.push(aggregate.to_string()),
+        }
+    }
+    if let Some(columns) = view.columns {
+        if columns.len() != names.len() {
+            bail!("view {} names {} columns but its SELECT has {}", view.name, columns.len(), names.len());
+        }
+        names = columns;
+    }
+    let table = TableSchema {
+        name: view.name,
+        columns: names.iter().map(|name| untyped_column(name, "")).collect(),
+        rowid_alias: None,
+    };
+    Ok(QueryPlan { table, root_page: 0, access: Access::View { select: Box::new(view.select), plan: Box::new(inner) } })
+}
+
+// An equality on the INTEGER PRIMARY KEY, which is the rowid, beats any
+// index: it's answered by a single root-to-leaf descent.
+// Otherwise picks the index whose leading key columns are covered by the most
+// `column = literal` terms ANDed together in the WHERE clause, preferring
+// covering indexes on a tie. Failing more equalities, bounds on the next
+// key column (from <, <=, >, >= or BETWEEN) narrow the search to a range of
+// the index. An equality or a bound on the leading column is taken to be
+// selective enough. With none, a covering index is still scanned in
+// place of the table, as its entries are smaller than the rows; otherwise
+// the whole table is scanned.
+fn choose_index(records: &Records, table: &TableSchema, select: &SelectStatement) -> Access {
+    let mut equalities = Vec::new();
+    let mut bounds = Vec::new();
+    let mut used = Vec::new();
+    if let Some(condition) = &select.where_clause {
+        collect_equalities(condition, &mut equalities);
+        collect_bounds(condition, &mut bounds);
+        condition.column_names(&mut used);
+    }
+    if let Some(key) = &select.group_by {
+        key.column_names(&mut used);
+    }
+    for term in select.order_by.iter() {
+        term.expr.column_names(&mut used);
+    }
+    for column in select.columns.iter() {
+        match column {
+            ResultColumn::Star => used.extend(table.columns.iter().map(|column| column.name.clone())),
+            ResultColumn::Column(name) => used.push(name.clone()),
+            ResultColumn::Expr(expr) | ResultColumn::Aggregate { arg: Some(expr), .. } => expr.column_names(&mut used),
+            ResultColumn::Aggregate { arg: None, .. } => {}
+        }
+    }
+
+    for (name, value) in equalities.iter() {
+        let is_rowid = table.column_index(name).is_some_and(|index| table.rowid_alias == Some(index));
+        if let (true, Value::Integer(rowid)) = (is_rowid, value) {
+            return Access::RowidLookup { rowid: *rowid };
+        }
+    }
+
+    let mut best = Access::FullScan;
+    let mut best_rank = (0, false, false);
+    for record in records.indexes(&table.name) {
+        let index = match parse_create_index(&record.sql) {
+            Ok(index) => index,
+            Err(_) => continue,
+        };
+        let mut key = Vec::new();
+        let mut range = KeyRange::default();
+        for column in index.columns.iter() {
+            // The index is ordered by the column's collation, which a search
+            // comparing values as BINARY would get wrong
+            if table.collation(column) != Collation::Binary {
+                break;
+            }
+            match equalities.iter().find(|(name, _)| name.eq_ignore_ascii_case(column)) {
+                Some((_, value)) => key.push(value.clone()),
+                None => {
+                    range = key_range(column, &bounds);
+                    break;
+                }
+            }
+        }
+        // Entries end in the rowid, so they cover the INTEGER PRIMARY KEY too
+        let covering = used.iter().all(|name| {
+            index.columns.iter().any(|column| column.eq_ignore_ascii_case(name))
+                || table.rowid_alias.is_some_and(|alias| table.columns[alias].name.eq_ignore_ascii_case(name))
+        });
+        let rank = (key.len(), !range.is_unbounded(), covering);
+        if (!key.is_empty() || !range.is_unbounded() || covering) && rank > best_rank {
+            best_rank = rank;
+            best = Access::IndexSearch {
+                index: index.name,
+                root_page: record.root_page as u32,
+                columns: index.columns,
+                key,
+                range,
+                covering,
+            };
+        }
+    }
+    best
+}
+
+fn collect_equalities(expr: &Expr, equalities: &mut Vec<(String, Value)>) {
+    if let Expr::Binary { op, left, right } = expr {
+        match (op, left.as_ref(), right.as_ref()) {
+            (BinaryOp::And, _, _) => {
+                collect_equalities(left, equalities);
+                collect_equalities(right, equalities);
+            }
+            (BinaryOp::Eq, Expr::Column(name), Expr::Literal(value))
+            | (BinaryOp::Eq, Expr::Literal(value), Expr::Column(name))
+                // Nothing equals NULL, so there is no key to search for
+                if *value != Value::Null =>
+            {
+                equalities.push((name.clone(), value.clone()));
+            }
+            // NULL IS NULL, and the index keeps NULLs together like any value
+            (BinaryOp::Is, Expr::Column(name), Expr::Literal(value))
+            | (BinaryOp::Is, Expr::Literal(value), Expr::Column(name)) => {
+                equalities.push((name.clone(), value.clone()));
+            }
+            _ => {}
+        }
+    }
+}
+
+// `column op literal` terms ANDed together in the WHERE clause, with the
+// column on the left
+fn collect_bounds(expr: &Expr, bounds: &mut Vec<(String, BinaryOp, Value)>) {
+    if let Expr::Binary { op, left, right } = expr {
+        let flipped = match op {
+            BinaryOp::Lt => BinaryOp::Gt,
+            BinaryOp::Le => BinaryOp::Ge,
+            BinaryOp::Gt => BinaryOp::Lt,
+            BinaryOp::Ge => BinaryOp::Le,
+            BinaryOp::And => {
+                collect_bounds(left, bounds);
+                collect_bounds(right, bounds);
+                return;
+            }
+            _ => return,
+        };
+        match (left.as_ref(), right.as_ref()) {
+            // Nothing compares with NULL, so it bounds nothing
+            (_, Expr::Literal(Value::Null)) | (Expr::Literal(Value::Null), _) => {}
+            (Expr::Column(name), Expr::Literal(value)) => bounds.push((name.clone(), *op, value.clone())),
+            (Expr::Literal(value), Expr::Column(name)) => bounds.push((name.clone(), flipped, value.clone())),
+            _ => {}
+        }
+    }
+}
+
+// The tightest range the bounds on `column` allow. Rows outside it can't
+// match; the WHERE clause is still checked against those inside.
+fn key_range(column: &str, bounds: &[(String, BinaryOp, Value)]) -> KeyRange {
+    let mut range = KeyRange::default();
+    for (_, op, value) in bounds.iter().filter(|(name, _, _)| name.eq_ignore_ascii_case(column)) {
+        let inclusive = matches!(op, BinaryOp::Ge | BinaryOp::Le);
+        let (bound, tighter) = match op {
+            BinaryOp::Gt | BinaryOp::Ge => (&mut range.lower, Ordering::Greater),
+            _ => (&mut range.upper, Ordering::Less),
+        };
+        let replace = match bound {
+            None => true,
+            Some((current, current_inclusive)) => match compare_values(value, current) {
+                Ordering::Equal => *current_inclusive && !inclusive,
+                ordering => ordering == tighter,
+            },
+        };
+        if replace {
+            *bound = Some((value.clone(), inclusive));
+        }
+    }
+    range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::fixture::{index_fixture, FixtureBuilder};
+    use crate::parser::{parse_select, parse_statement, Statement};
+
+    // `people(id INTEGER PRIMARY KEY, name TEXT, city TEXT)` with an index
+    // on city alone
+    fn people() -> Records {
+        let image = FixtureBuilder::default()
+            .add_table("people", "CREATE TABLE people(id INTEGER PRIMARY KEY, name TEXT, city TEXT)", &[])
+            .add_index("idx_city", "people", "CREATE INDEX idx_city ON people(city)", &[])
+            .build();
+        Database::from_bytes(image).unwrap().schema().unwrap()
+    }
+
+    fn access(sql: &str) -> Access {
+        plan(&parse_select(sql).unwrap(), &people()).unwrap().access
+    }
+
+    #[test]
+    fn an_indexed_equality_searches_the_index() {
+        match access("SELECT name FROM people WHERE city = 'Oslo'") {
+            Access::IndexSearch { index, key, covering, .. } => {
+                assert_eq!(index, "idx_city");
+                assert_eq!(key, [Value::Text("Oslo".to_string())]);
+                assert!(!covering);
+            }
+            other => panic!("expected an index search, got {:?}", other),
+        }
+        let plan = plan(&parse_select("SELECT id FROM people WHERE city = 'Oslo'").unwrap(), &people()).unwrap();
+        assert_eq!(plan.to_string(), "SEARCH people USING COVERING INDEX idx_city (city=?)");
+    }
+
+    #[test]
+    fn other_filters_scan_the_table() {
+        assert_eq!(access("SELECT city FROM people WHERE name = 'Ada'"), Access::FullScan);
+        assert_eq!(access("SELECT name FROM people WHERE city != 'Oslo'"), Access::FullScan);
+        assert_eq!(access("SELECT name FROM people"), Access::FullScan);
+    }
+
+    // What EXPLAIN prints for `sql` on the two-column index fixture
+    fn explain(sql: &str) -> String {
+        let records = Database::from_bytes(index_fixture(100)).unwrap().schema().unwrap();
+        match parse_statement(sql).unwrap() {
+            Statement::Explain(select) => plan(&select, &records).unwrap().to_string(),
+            other => panic!("expected EXPLAIN, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn explain_names_the_index_it_would_search() {
+        let sql = "EXPLAIN SELECT id FROM t WHERE a = 'apple' AND b = 5";
+        assert_eq!(explain(sql), "SEARCH t USING COVERING INDEX idx_ab (a=? AND b=?)");
+        assert_eq!(explain("explain query plan SELECT id FROM t WHERE b = 5"), "SCAN t USING COVERING INDEX idx_ab");
+    }
+}
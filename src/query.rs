@@ -0,0 +1,997 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+use crate::database::{AccessPattern, Database};
+use crate::eval::{eval_collated, is_true};
+use crate::parser::{AggregateFunction, Expr, OrderTerm, ResultColumn, SelectStatement};
+use crate::planner::{plan, Access, QueryPlan};
+use crate::value::{render, Collation, Value};
+use crate::Records;
+
+#[derive(Debug)]
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+pub fn execute_select(db: &mut Database, records: &Records, select: &SelectStatement) -> Result<ResultSet> {
+    let plan = plan(select, records)?;
+    execute_plan(db, &plan, select)
+}
+
+// Runs each IN (SELECT ...) subquery once, up front, substituting the list
+// of values it returned
+fn run_subqueries(db: &mut Database, select: &SelectStatement) -> Result<SelectStatement> {
+    let mut select = select.clone();
+    let mut run = |subquery: &SelectStatement| -> Result<Vec<Value>> {
+        if subquery.schema.as_ref().is_some_and(|alias| !alias.eq_ignore_ascii_case("main")) {
+            bail!("subqueries on attached databases are not supported");
+        }
+        let records = db.schema()?;
+        let result = execute_select(db, &records, subquery)?;
+        if result.columns.len() != 1 {
+            bail!("sub-select returns {} columns - expected 1", result.columns.len());
+        }
+        Ok(result.rows.into_iter().filter_map(|row| row.into_iter().next()).collect())
+    };
+    for expr in select.expressions() {
+        expr.resolve_subqueries(&mut run)?;
+    }
+    Ok(select)
+}
+
+pub fn execute_plan(db: &mut Database, plan: &QueryPlan, select: &SelectStatement) -> Result<ResultSet> {
+    // Result columns are named after the query as written, not after the
+    // values its subqueries returned
+    let written = select;
+    let resolved;
+    let select = if select.has_subquery() {
+        resolved = run_subqueries(db, select)?;
+        &resolved
+    } else {
+        select
+    };
+    let table = &plan.table;
+    let root_page = plan.root_page;
+
+    let mut projection = Vec::new();
+    let mut columns = Vec::new();
+    let mut aggregates = Vec::new();
+    for (column, as_written) in select.columns.iter().zip(written.columns.iter()) {
+        match column {
+            ResultColumn::Star => {
+                projection.extend((0..table.columns.len()).map(Output::Column));
+                columns.extend(table.columns.iter().map(|column| column.name.clone()));
+            }
+            ResultColumn::Column(name) => {
+                // Unknown names are reported below, together with the rest
+                let index = table.column_index(name);
+                projection.push(Output::Column(index.unwrap_or(0)));
+                columns.push(index.map_or_else(|| name.clone(), |index| table.columns[index].name.clone()));
+            }
+            ResultColumn::Expr(expr) => {
+                projection.push(Output::Expr(expr));
+                columns.push(as_written.to_string());
+            }
+            ResultColumn::Aggregate { function, arg } => {
+                // The slot is filled in once the group is complete
+                aggregates.push((*function, arg.as_ref(), projection.len()));
+                projection.push(Output::Aggregate);
+                columns.push(as_written.to_string());
+            }
+        }
+    }
+    // Report every unknown column up front rather than only the first, and
+    // before any row is read
+    let mut names = Vec::new();
+    for column in select.columns.iter() {
+        match column {
+            ResultColumn::Star => {}
+            ResultColumn::Column(name) => names.push(name.clone()),
+            ResultColumn::Expr(expr) | ResultColumn::Aggregate { arg: Some(expr), .. } => expr.column_names(&mut names),
+            ResultColumn::Aggregate { arg: None, .. } => {}
+        }
+    }
+    if let Some(condition) = &select.where_clause {
+        condition.column_names(&mut names);
+    }
+    if let Some(key) = &select.group_by {
+        key.column_names(&mut names);
+    }
+    for term in select.order_by.iter() {
+        term.expr.column_names(&mut names);
+    }
+    let mut unknown: Vec<&str> = Vec::new();
+    for name in names.iter().filter(|name| table.column_index(name).is_none()) {
+        if !unknown.iter().any(|seen| seen.eq_ignore_ascii_case(name)) {
+            unknown.push(name);
+        }
+    }
+    match unknown.as_slice() {
+        [] => {}
+        [name] => bail!("no such column: {}", name),
+        names => bail!("no such columns: {}", names.join(", ")),
+    }
+
+    let log = db.log();
+    // Rows paired with their ORDER BY keys
+    let mut rows: Vec<(Vec<Value>, Vec<Value>)> = Vec::new();
+    // For aggregate queries, each row's GROUP BY key and aggregate arguments
+    let mut inputs: Vec<(Value, Vec<Value>)> = Vec::new();
+    let is_aggregate = select.is_aggregate();
+    let collation = |name: &str| table.collation(name);
+    // ORDER BY, GROUP BY and MIN/MAX sort text by the collation of the
+    // column they name
+    let collation_of = |expr: Option<&Expr>| match expr {
+        Some(Expr::Column(name)) => table.collation(name),
+        _ => Collation::Binary,
+    };
+    let mut visit = |rowid: i64, values: Vec<Value>| {
+        let column_value = |index: usize| {
+            if table.rowid_alias == Some(index) {
+                Value::Integer(rowid)
+            } else {
+                let column = &table.columns[index];
+                match values.get(index) {
+                    Some(Value::Integer(i)) if column.has_real_affinity() => Value::Real(*i as f64),
+                    Some(value) => value.clone(),
+                    None => column.default.clone(),
+                }
+            }
+        };
+        let lookup = |name: &str| match table.column_index(name) {
+            Some(index) => Ok(column_value(index)),
+            None => bail!("no such column: {}", name),
+        };
+        if let Some(condition) = &select.where_clause {
+            let result = eval_collated(condition, &lookup, &collation)?;
+            if let Some(log) = log {
+                let shown = if result == Value::Null { "NULL".to_string() } else { render(&result) };
+                log(&format!("row {}: WHERE {} is {}", rowid, condition, shown));
+            }
+            if !is_true(&result) {
+                return Ok(());
+            }
+        }
+        let mut key = Vec::with_capacity(select.order_by.len());
+        for term in select.order_by.iter() {
+            key.push(eval_collated(&term.expr, &lookup, &collation)?);
+        }
+        let mut row = Vec::with_capacity(projection.len());
+        for output in projection.iter() {
+            row.push(match output {
+                Output::Column(index) => column_value(*index),
+                Output::Expr(expr) => eval_collated(expr, &lookup, &collation)?,
+                Output::Aggregate => Value::Null,
+            });
+        }
+        if is_aggregate {
+            let group = match &select.group_by {
+                Some(key) => eval_collated(key, &lookup, &collation)?,
+                None => Value::Null,
+            };
+            let mut arguments = Vec::with_capacity(aggregates.len());
+            for (_, arg, _) in aggregates.iter() {
+                // COUNT(*) counts every row, as if over a column never NULL
+                arguments.push(match arg {
+                    Some(arg) => eval_collated(arg, &lookup, &collation)?,
+                    None => Value::Integer(1),
+                });
+            }
+            inputs.push((group, arguments));
+        }
+        rows.push((key, row));
+        Ok(())
+    };
+    match &plan.access {
+        Access::IndexSearch { root_page: index_root, columns, key, range, covering: true, .. } => {
+            // Rebuild each row from the entry; columns outside the index
+            // are never looked at
+            let positions: Vec<Option<usize>> = columns.iter().map(|column| table.column_index(column)).collect();
+            for entry in db.index_entries_in_range(*index_root, key, range)? {
+                let rowid = match entry.last() {
+                    Some(Value::Integer(rowid)) => *rowid,
+                    _ => bail!("index entry doesn't end in a rowid"),
+                };
+                let mut values = vec![Value::Null; table.columns.len()];
+                for (value, position) in entry.into_iter().zip(positions.iter()) {
+                    if let Some(position) = position {
+                        values[*position] = value;
+                    }
+                }
+                visit(rowid, values)?;
+            }
+        }
+        Access::IndexSearch { root_page: index_root, key, range, .. } => {
+            // The WHERE clause is still checked against every row fetched
+            db.set_access_pattern(AccessPattern::Random)?;
+            for rowid in db.search_index_range(*index_root, key, range)? {
+                if let Some(row) = db.find_row(root_page, rowid)? {
+                    visit(row.rowid, row.values)?;
+                }
+            }
+        }
+        Access::RowidLookup { rowid } => {
+            if let Some(row) = db.find_row(root_page, *rowid)? {
+                visit(row.rowid, row.values)?;
+            }
+        }
+        Access::FullScan => {
+            db.set_access_pattern(AccessPattern::Sequential)?;
+            db.scan_table(root_page, &mut visit)?;
+        }
+        Access::View { select, plan } => {
+            for row in execute_plan(db, plan, select)?.rows {
+                visit(0, row)?;
+            }
+        }
+        Access::Constant => visit(0, Vec::new())?,
+    }
+    if is_aggregate {
+        let grouping = select.group_by.as_ref().map(|key| collation_of(Some(key)));
+        let aggregates: Vec<(AggregateFunction, Collation, usize)> =
+            aggregates.iter().map(|(function, arg, slot)| (*function, collation_of(*arg), *slot)).collect();
+        rows = fold_groups(rows, inputs, &aggregates, grouping, projection.len())?;
+    }
+    if !select.order_by.is_empty() {
+        let terms: Vec<(&OrderTerm, Collation)> =
+            select.order_by.iter().map(|term| (term, collation_of(Some(&term.expr)))).collect();
+        rows.sort_by(|(a, _), (b, _)| {
+            let mut ordering = Ordering::Equal;
+            for ((term, order), (a, b)) in terms.iter().zip(a.iter().zip(b.iter())) {
+                ordering = compare_sort_keys(term, *order, a, b);
+                if ordering != Ordering::Equal {
+                    break;
+                }
+            }
+            ordering
+        });
+    }
+    let limit = select.limit.unwrap_or(usize::MAX);
+    let rows = rows.into_iter().skip(select.offset).take(limit).map(|(_, row)| row).collect();
+    Ok(ResultSet { columns, rows })
+}
+
+// One ORDER BY term's comparison, with NULLs placed as the term asks
+fn compare_sort_keys(term: &OrderTerm, order: Collation, a: &Value, b: &Value) -> Ordering {
+    let nulls_first = term.nulls_first.unwrap_or(!term.descending);
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) if nulls_first => Ordering::Less,
+        (Value::Null, _) => Ordering::Greater,
+        (_, Value::Null) if nulls_first => Ordering::Greater,
+        (_, Value::Null) => Ordering::Less,
+        _ if term.descending => order.compare(a, b).reverse(),
+        _ => order.compare(a, b),
+    }
+}
+
+// A result column: stored columns are copied straight from the row
+enum Output<'a> {
+    Column(usize),
+    Expr(&'a Expr),
+    Aggregate,
+}
+
+// Folds the rows of each group into one, in group key order. Columns that
+// aren't aggregates take their values from the group's first row, which is
+// what SQLite shows for them too, except that with a single MIN or MAX
+// among the aggregates they come from the row holding that value.
+// Without GROUP BY (no `grouping` collation) the whole table is a single
+// group, which still gives a row when it is empty.
+fn fold_groups(
+    rows: Vec<(Vec<Value>, Vec<Value>)>,
+    inputs: Vec<(Value, Vec<Value>)>,
+    aggregates: &[(AggregateFunction, Collation, usize)],
+    grouping: Option<Collation>,
+    width: usize,
+) -> Result<Vec<(Vec<Value>, Vec<Value>)>> {
+    let order = grouping.unwrap_or_default();
+    let mut items: Vec<_> = inputs.into_iter().zip(rows).collect();
+    items.sort_by(|((a, _), _), ((b, _), _)| order.compare(a, b));
+    if items.is_empty() && grouping.is_none() {
+        let mut row = vec![Value::Null; width];
+        for (function, collation, slot) in aggregates.iter() {
+            row[*slot] = aggregate(*function, *collation, &[])?;
+        }
+        return Ok(vec![(Vec::new(), row)]);
+    }
+
+    let extremes: Vec<(usize, AggregateFunction, Collation)> = aggregates
+        .iter()
+        .enumerate()
+        .filter(|(_, (function, _, _))| matches!(function, AggregateFunction::Min | AggregateFunction::Max))
+        .map(|(i, (function, collation, _))| (i, *function, *collation))
+        .collect();
+    let mut groups = Vec::new();
+    let mut start = 0;
+    while start < items.len() {
+        let group = &items[start].0 .0;
+        let end = start + items[start..].iter().take_while(|((key, _), _)| order.compare(key, group) == Ordering::Equal).count();
+        let source = match extremes.as_slice() {
+            [(i, function, collation)] => {
+                let values: Vec<&Value> = items[start..end].iter().map(|((_, arguments), _)| &arguments[*i]).collect();
+                start + extreme(*function, *collation, &values).unwrap_or(0)
+            }
+            _ => start,
+        };
+        let (key, mut row) = items[source].1.clone();
+        for (i, (function, collation, slot)) in aggregates.iter().enumerate() {
+            let values: Vec<&Value> = items[start..end].iter().map(|((_, arguments), _)| &arguments[i]).collect();
+            row[*slot] = aggregate(*function, *collation, &values)?;
+        }
+        groups.push((key, row));
+        start = end;
+    }
+    Ok(groups)
+}
+
+// Position of the first smallest (MIN) or largest (MAX) value that isn't NULL
+fn extreme(function: AggregateFunction, collation: Collation, values: &[&Value]) -> Option<usize> {
+    let wanted = if function == AggregateFunction::Min { Ordering::Less } else { Ordering::Greater };
+    let mut best: Option<usize> = None;
+    for (at, value) in values.iter().enumerate().filter(|(_, value)| ***value != Value::Null) {
+        if best.map_or(true, |best| collation.compare(value, values[best]) == wanted) {
+            best = Some(at);
+        }
+    }
+    best
+}
+
+// NULLs are skipped by every aggregate. SUM stays an integer while every
+// value is one, and like SQLite reads text as a number where it can.
+fn aggregate(function: AggregateFunction, collation: Collation, values: &[&Value]) -> Result<Value> {
+    let present = values.iter().copied().filter(|value| **value != Value::Null);
+    match function {
+        AggregateFunction::Count => Ok(Value::Integer(present.count() as i64)),
+        AggregateFunction::Min | AggregateFunction::Max => {
+            Ok(extreme(function, collation, values).map_or(Value::Null, |at| values[at].clone()))
+        }
+        AggregateFunction::Sum => {
+            let mut total: Option<i64> = Some(0);
+            let mut real = 0.0;
+            let mut any = false;
+            for value in present {
+                any = true;
+                let number = match value {
+                    Value::Integer(i) => Value::Integer(*i),
+                    Value::Real(r) => Value::Real(*r),
+                    Value::Text(text) => match (text.trim().parse::<i64>(), text.trim().parse::<f64>()) {
+                        (Ok(i), _) => Value::Integer(i),
+                        (_, Ok(r)) => Value::Real(r),
+                        _ => Value::Real(0.0),
+                    },
+                    _ => Value::Real(0.0),
+                };
+                match number {
+                    Value::Integer(i) => {
+                        real += i as f64;
+                        if let Some(sum) = total {
+                            total = match sum.checked_add(i) {
+                                Some(sum) => Some(sum),
+                                None => bail!("integer overflow"),
+                            };
+                        }
+                    }
+                    Value::Real(r) => {
+                        real += r;
+                        total = None;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(match (any, total) {
+                (false, _) => Value::Null,
+                (true, Some(sum)) => Value::Integer(sum),
+                (true, None) => Value::Real(real),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SqliteError;
+    use crate::fixture::{index_fixture, FixtureBuilder};
+    use crate::database::PageStats;
+    use crate::planner::{plan, Access};
+    use crate::parser::parse_select;
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    // Runs `sql` against sample.db, whose apples table is
+    // `apples(id integer primary key, name text, color text)`
+    fn query(sql: &str) -> Result<ResultSet> {
+        let mut db = Database::open("sample.db")?;
+        let records = db.schema()?;
+        execute_select(&mut db, &records, &parse_select(sql)?)
+    }
+
+    #[test]
+    fn names_resolve_whatever_their_case() {
+        let result = query("SELECT NAME FROM APPLES").unwrap();
+        assert_eq!(result.columns, ["name"]);
+        assert_eq!(result.rows[1], [text("Fuji")]);
+        let result = query("select ID, nAmE from ApPlEs").unwrap();
+        assert_eq!(result.rows[2], [Value::Integer(3), text("Honeycrisp")]);
+    }
+
+    #[test]
+    fn missing_table_error_lists_the_tables() {
+        let err = query("SELECT name FROM aples").unwrap_err();
+        assert!(err.to_string().contains("apples"), "{}", err);
+        match err.downcast_ref::<SqliteError>() {
+            Some(SqliteError::TableNotFound { name, available }) => {
+                assert_eq!(name, "aples");
+                assert_eq!(available, &["apples", "oranges", "sqlite_sequence"]);
+            }
+            other => panic!("expected TableNotFound, got {:?}", other),
+        }
+    }
+
+    // `apples(id INTEGER PRIMARY KEY, name TEXT, color TEXT)`, as in the
+    // sample database, plus one with no color
+    fn apples() -> Database {
+        Database::from_bytes(apples_builder().build()).unwrap()
+    }
+
+    fn apples_builder() -> FixtureBuilder {
+        let rows = [
+            (1, vec![Value::Null, text("Granny Smith"), text("Light Green")]),
+            (2, vec![Value::Null, text("Fuji"), text("Red")]),
+            (3, vec![Value::Null, text("Honeycrisp"), text("Blush Red")]),
+            (4, vec![Value::Null, text("Golden Delicious"), text("Yellow")]),
+            (5, vec![Value::Null, text("Jazz"), Value::Null]),
+        ];
+        let mut builder = FixtureBuilder::default();
+        builder.add_table("apples", "CREATE TABLE apples(id integer primary key, name text, color text)", &rows);
+        builder
+    }
+
+    fn select(db: &mut Database, sql: &str) -> Vec<Vec<Value>> {
+        let records = db.schema().unwrap();
+        execute_select(db, &records, &parse_select(sql).unwrap()).unwrap().rows
+    }
+
+    // The first result column of every row, in order
+    fn column(db: &mut Database, sql: &str) -> Vec<Value> {
+        select(db, sql).into_iter().map(|row| row[0].clone()).collect()
+    }
+
+    #[test]
+    fn not_negates_its_predicate() {
+        let mut db = apples();
+        let others = [text("Granny Smith"), text("Honeycrisp"), text("Golden Delicious")];
+        // NULL = 'Red' is unknown, and so is its negation, so Jazz is left out
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE NOT (color = 'Red')"), others);
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE color != 'Red'"), others);
+        let sql = "SELECT name FROM apples WHERE NOT color = 'Yellow' AND NOT id > 2";
+        assert_eq!(column(&mut db, sql), [text("Granny Smith"), text("Fuji")]);
+    }
+
+    #[test]
+    fn not_binds_looser_than_comparisons_and_tighter_than_and() {
+        let mut db = apples();
+        // NOT (id = 1) AND (color = 'Red'), not NOT (id = 1 AND color = 'Red')
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE NOT id = 1 AND color = 'Red'"), [text("Fuji")]);
+        let sql = "SELECT id FROM apples WHERE NOT (id = 1 OR id = 2) AND NOT id = 5";
+        assert_eq!(column(&mut db, sql), [Value::Integer(3), Value::Integer(4)]);
+    }
+
+    // `mixed(v)`, an untyped column holding a value of every storage class
+    fn mixed() -> Database {
+        let values = [text("b"), Value::Integer(3), Value::Null, Value::Real(2.5), Value::Blob(vec![1]), text("a")];
+        let rows: Vec<(i64, Vec<Value>)> =
+            values.into_iter().zip(1..).map(|(value, rowid)| (rowid, vec![value])).collect();
+        let image = FixtureBuilder::default().add_table("mixed", "CREATE TABLE mixed(v)", &rows).build();
+        Database::from_bytes(image).unwrap()
+    }
+
+    #[test]
+    fn order_by_follows_the_storage_class_order() {
+        let mut db = mixed();
+        let ascending = [Value::Null, Value::Real(2.5), Value::Integer(3), text("a"), text("b"), Value::Blob(vec![1])];
+        assert_eq!(column(&mut db, "SELECT v FROM mixed ORDER BY v"), ascending);
+        let descending: Vec<Value> = ascending.iter().rev().cloned().collect();
+        assert_eq!(column(&mut db, "SELECT v FROM mixed ORDER BY v DESC"), descending);
+        // Text and blobs sort after every number, so they pass a numeric bound
+        assert_eq!(column(&mut db, "SELECT v FROM mixed WHERE v > 2 ORDER BY v"), ascending[1..]);
+    }
+
+    #[test]
+    fn the_schema_table_is_queryable_by_either_name() {
+        let image = FixtureBuilder::default()
+            .add_table("apples", "CREATE TABLE apples(id integer primary key, name text)", &[])
+            .add_table("oranges", "CREATE TABLE oranges(name text)", &[])
+            .add_index("idx_name", "apples", "CREATE INDEX idx_name ON apples(name)", &[])
+            .build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let names = [text("apples"), text("oranges"), text("idx_name")];
+        assert_eq!(column(&mut db, "SELECT name FROM sqlite_master"), names);
+        assert_eq!(column(&mut db, "SELECT name FROM sqlite_schema"), names);
+        let sql = "SELECT type, tbl_name, rootpage FROM sqlite_master WHERE name = 'idx_name'";
+        assert_eq!(select(&mut db, sql), [vec![text("index"), text("apples"), Value::Integer(4)]]);
+    }
+
+    // Rows written before ALTER TABLE ADD COLUMN keep their shorter records
+    #[test]
+    fn columns_missing_from_a_short_record_read_as_their_default() {
+        let rows = [
+            (1, vec![Value::Integer(1)]),
+            (2, vec![Value::Integer(2), text("b"), Value::Integer(3), text("d"), text("e")]),
+        ];
+        let sql = "CREATE TABLE t(a, b, c INTEGER DEFAULT 7, d TEXT DEFAULT 'x', e)";
+        let image = FixtureBuilder::default().add_table("t", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(
+            select(&mut db, "SELECT a, b, c, d, e FROM t"),
+            [
+                vec![Value::Integer(1), Value::Null, Value::Integer(7), text("x"), Value::Null],
+                vec![Value::Integer(2), text("b"), Value::Integer(3), text("d"), text("e")],
+            ]
+        );
+        assert_eq!(column(&mut db, "SELECT a FROM t WHERE c = 7"), [Value::Integer(1)]);
+    }
+
+    #[test]
+    fn equality_on_both_columns_of_a_two_column_index() {
+        let mut db = Database::from_bytes(index_fixture(2000)).unwrap();
+        let select = parse_select("SELECT id, a, b FROM t WHERE a = 'cherry' AND b = 7").unwrap();
+        let plan = plan(&select, &db.schema().unwrap()).unwrap();
+        let root_page = match &plan.access {
+            Access::IndexSearch { index, key, root_page, .. } => {
+                assert_eq!(index, "idx_ab");
+                assert_eq!(key, &[text("cherry"), Value::Integer(7)]);
+                *root_page
+            }
+            other => panic!("expected an index search, got {:?}", other),
+        };
+        // The fixture gives row id the name at id % 5 and b = id % 10
+        let ids: Vec<i64> = (7..=2000).step_by(10).collect();
+        assert_eq!(db.search_index(root_page, &[text("cherry"), Value::Integer(7)]).unwrap(), ids);
+        let expected: Vec<Vec<Value>> =
+            ids.iter().map(|&id| vec![Value::Integer(id), text("cherry"), Value::Integer(7)]).collect();
+        assert_eq!(execute_plan(&mut db, &plan, &select).unwrap().rows, expected);
+        assert!(column(&mut db, "SELECT id FROM t WHERE a = 'cherry' AND b = 8").is_empty());
+    }
+
+    #[test]
+    fn between_is_an_inclusive_range() {
+        let mut db = apples();
+        let sql = "SELECT name FROM apples WHERE id BETWEEN 2 AND 4";
+        assert_eq!(column(&mut db, sql), [text("Fuji"), text("Honeycrisp"), text("Golden Delicious")]);
+        assert_eq!(column(&mut db, "SELECT id FROM apples WHERE id BETWEEN 4 AND 2"), []);
+        let sql = "SELECT name FROM apples WHERE color BETWEEN 'Blush Red' AND 'Red'";
+        assert_eq!(column(&mut db, sql), [text("Granny Smith"), text("Fuji"), text("Honeycrisp")]);
+    }
+
+    #[test]
+    fn not_between_excludes_the_range() {
+        let mut db = apples();
+        let sql = "SELECT name FROM apples WHERE id NOT BETWEEN 2 AND 4";
+        assert_eq!(column(&mut db, sql), [text("Granny Smith"), text("Jazz")]);
+        // A NULL is neither in nor out of the range
+        let sql = "SELECT name FROM apples WHERE color NOT BETWEEN 'Blush Red' AND 'Red'";
+        assert_eq!(column(&mut db, sql), [text("Golden Delicious")]);
+        let sql = "SELECT id FROM apples WHERE id NOT BETWEEN 2 AND 4 AND id > 1";
+        assert_eq!(column(&mut db, sql), [Value::Integer(5)]);
+    }
+
+    #[test]
+    fn views_run_their_select() {
+        let reds = "CREATE VIEW reds AS SELECT id, name FROM apples WHERE color = 'Red' OR color = 'Blush Red'";
+        let image = apples_builder()
+            .add_view("reds", reds)
+            .add_view("named", "CREATE VIEW named(label) AS SELECT name FROM apples WHERE id < 3")
+            .build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let records = db.schema().unwrap();
+        let result = execute_select(&mut db, &records, &parse_select("SELECT * FROM reds").unwrap()).unwrap();
+        assert_eq!(result.columns, ["id", "name"]);
+        assert_eq!(result.rows, [vec![Value::Integer(2), text("Fuji")], vec![Value::Integer(3), text("Honeycrisp")]]);
+        assert_eq!(column(&mut db, "SELECT name FROM reds WHERE id > 2"), [text("Honeycrisp")]);
+        assert_eq!(column(&mut db, "SELECT label FROM named"), [text("Granny Smith"), text("Fuji")]);
+    }
+
+    #[test]
+    fn views_beyond_a_single_table_are_rejected() {
+        let sql = "CREATE VIEW pairs AS SELECT a.name FROM apples a JOIN apples b ON a.id = b.id";
+        let image = apples_builder().add_view("pairs", sql).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let records = db.schema().unwrap();
+        let err = execute_select(&mut db, &records, &parse_select("SELECT * FROM pairs").unwrap()).unwrap_err();
+        assert!(err.to_string().starts_with("unsupported view pairs: "), "{}", err);
+    }
+
+    // The plan for `sql`, its row count and the pages running it read
+    fn run_counting(db: &mut Database, sql: &str) -> (String, usize, PageStats) {
+        let select = parse_select(sql).unwrap();
+        let plan = plan(&select, &db.schema().unwrap()).unwrap();
+        let before = db.page_stats();
+        let rows = execute_plan(db, &plan, &select).unwrap().rows.len();
+        (plan.to_string(), rows, db.page_stats().since(before))
+    }
+
+    #[test]
+    fn a_covering_index_query_reads_no_table_pages() {
+        let mut db = numbers(true);
+        let (plan, rows, pages) = run_counting(&mut db, "SELECT a FROM t WHERE a > 5");
+        assert_eq!(plan, "SEARCH t USING COVERING INDEX idx_a (a>?)");
+        assert_eq!(rows, 2820);
+        assert_eq!(pages.table, 0);
+        assert!(pages.index > 1);
+        assert_eq!(pages.total, pages.index);
+        // Fetching b from the table for each entry does read table pages
+        let (_, _, pages) = run_counting(&mut db, "SELECT b FROM t WHERE a = 96");
+        assert!(pages.table > 0 && pages.index > 0, "{:?}", pages);
+        // Each run is counted from its own snapshot
+        let (_, _, again) = run_counting(&mut db, "SELECT b FROM t WHERE a = 96");
+        assert_eq!(again, pages);
+    }
+
+    fn orders() -> Database {
+        let rows = [
+            (1, vec![Value::Real(2.5), Value::Integer(4)]),
+            (2, vec![Value::Integer(3), Value::Integer(7)]),
+            (3, vec![Value::Null, Value::Integer(1)]),
+        ];
+        let image = FixtureBuilder::default()
+            .add_table("orders", "CREATE TABLE orders(price, quantity INTEGER)", &rows)
+            .build();
+        Database::from_bytes(image).unwrap()
+    }
+
+    #[test]
+    fn projections_compute_arithmetic_per_row() {
+        let mut db = orders();
+        let products = [Value::Real(10.0), Value::Integer(21), Value::Null];
+        assert_eq!(column(&mut db, "SELECT price * quantity FROM orders"), products);
+        // Integers stay integers, and division between them truncates
+        let sql = "SELECT quantity / 2, quantity / 2.0, quantity - 1 + 10 * 2 FROM orders WHERE quantity = 7";
+        assert_eq!(select(&mut db, sql), [vec![Value::Integer(3), Value::Real(3.5), Value::Integer(26)]]);
+    }
+
+    #[test]
+    fn division_by_zero_is_null() {
+        let mut db = orders();
+        assert_eq!(column(&mut db, "SELECT quantity / 0 FROM orders"), [Value::Null, Value::Null, Value::Null]);
+        assert_eq!(column(&mut db, "SELECT price / (quantity - 1) FROM orders WHERE quantity = 1"), [Value::Null]);
+        assert_eq!(column(&mut db, "SELECT 2.5 / 0.0 FROM orders WHERE quantity = 4"), [Value::Null]);
+    }
+
+    #[test]
+    fn glob_filters_rows() {
+        let mut db = apples();
+        let sql = "SELECT name FROM apples WHERE name GLOB 'G*'";
+        assert_eq!(column(&mut db, sql), [text("Granny Smith"), text("Golden Delicious")]);
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE name GLOB 'g*'"), []);
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE name GLOB '[F-J]???'"), [text("Fuji"), text("Jazz")]);
+    }
+
+    #[test]
+    fn every_unknown_column_is_reported_before_the_scan() {
+        let mut db = apples();
+        let before = db.page_stats();
+        let err = db.query("SELECT nmae, id, colour FROM apples", &[]).unwrap_err();
+        assert_eq!(err.to_string(), "no such columns: nmae, colour");
+        assert_eq!(db.page_stats().since(before).table, 0);
+        let err = db.query("SELECT name FROM apples WHERE colour = 'Red'", &[]).unwrap_err();
+        assert_eq!(err.to_string(), "no such column: colour");
+    }
+
+    #[test]
+    fn a_table_level_integer_primary_key_is_the_rowid() {
+        let rows = [(10, vec![Value::Null, text("ten")]), (20, vec![Value::Null, text("twenty")])];
+        let sql = "CREATE TABLE t(id INTEGER, name TEXT, CONSTRAINT pk PRIMARY KEY (id))";
+        let image = FixtureBuilder::default().add_table("t", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(column(&mut db, "SELECT id FROM t"), [Value::Integer(10), Value::Integer(20)]);
+        assert_eq!(column(&mut db, "SELECT name FROM t WHERE id = 20"), [text("twenty")]);
+        // A key over two columns leaves the rowid hidden
+        let rows = [(1, vec![Value::Integer(7), text("a")])];
+        let sql = "CREATE TABLE pairs(id INTEGER, name TEXT, PRIMARY KEY (id, name))";
+        let image = FixtureBuilder::default().add_table("pairs", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(column(&mut db, "SELECT id FROM pairs"), [Value::Integer(7)]);
+    }
+
+    #[test]
+    fn in_runs_a_single_column_subquery() {
+        let picks = [(1, vec![Value::Integer(2), text("gift")]), (2, vec![Value::Integer(4), text("pie")])];
+        let image = apples_builder()
+            .add_table("picks", "CREATE TABLE picks(apple_id INTEGER, reason TEXT)", &picks)
+            .build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let sql = "SELECT name FROM apples WHERE id IN (SELECT apple_id FROM picks)";
+        assert_eq!(column(&mut db, sql), [text("Fuji"), text("Golden Delicious")]);
+        let sql = "SELECT name FROM apples WHERE id NOT IN (SELECT apple_id FROM picks WHERE reason = 'pie')";
+        assert_eq!(column(&mut db, sql).len(), 4);
+        let sql = "SELECT name FROM apples WHERE id IN (SELECT apple_id FROM picks WHERE reason = 'none')";
+        assert_eq!(column(&mut db, sql), []);
+        let sql = "SELECT name FROM apples WHERE id IN (SELECT apple_id, reason FROM picks)";
+        let err = db.query(sql, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "sub-select returns 2 columns - expected 1");
+    }
+
+    #[test]
+    fn nulls_first_and_last_override_the_default() {
+        let mut db = apples();
+        let cases = [
+            // NULLs sort first ascending and last descending by default
+            ("ORDER BY color", [5, 3, 1, 2, 4]),
+            ("ORDER BY color DESC", [4, 2, 1, 3, 5]),
+            ("ORDER BY color NULLS LAST", [3, 1, 2, 4, 5]),
+            ("ORDER BY color DESC NULLS FIRST", [5, 4, 2, 1, 3]),
+            ("ORDER BY color ASC NULLS FIRST", [5, 3, 1, 2, 4]),
+            ("ORDER BY color DESC NULLS LAST", [4, 2, 1, 3, 5]),
+        ];
+        for (order_by, ids) in cases {
+            let expected: Vec<Value> = ids.into_iter().map(Value::Integer).collect();
+            assert_eq!(column(&mut db, &format!("SELECT id FROM apples {}", order_by)), expected, "{}", order_by);
+        }
+    }
+
+    #[test]
+    fn group_by_counts_each_group() {
+        let rows = [
+            (1, vec![Value::Null, text("Fuji"), text("Red"), Value::Integer(3)]),
+            (2, vec![Value::Null, text("Gala"), text("Red"), Value::Integer(5)]),
+            (3, vec![Value::Null, text("Granny Smith"), text("Green"), Value::Integer(2)]),
+            (4, vec![Value::Null, text("Jazz"), Value::Null, Value::Integer(4)]),
+            (5, vec![Value::Null, text("Braeburn"), text("Red"), Value::Null]),
+        ];
+        let sql = "CREATE TABLE apples(id INTEGER PRIMARY KEY, name TEXT, color TEXT, stock INTEGER)";
+        let image = FixtureBuilder::default().add_table("apples", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let result = db.query("SELECT color, COUNT(*) FROM apples GROUP BY color", &[]).unwrap();
+        assert_eq!(result.columns, ["color", "COUNT(*)"]);
+        // One row per group in key order, NULL first, as SQLite does
+        assert_eq!(
+            result.rows,
+            [
+                vec![Value::Null, Value::Integer(1)],
+                vec![text("Green"), Value::Integer(1)],
+                vec![text("Red"), Value::Integer(3)],
+            ]
+        );
+        let sql = "SELECT color, MIN(stock), MAX(stock), SUM(stock) FROM apples WHERE color != '' GROUP BY color";
+        assert_eq!(
+            db.query(sql, &[]).unwrap().rows,
+            [
+                vec![text("Green"), Value::Integer(2), Value::Integer(2), Value::Integer(2)],
+                vec![text("Red"), Value::Integer(3), Value::Integer(5), Value::Integer(8)],
+            ]
+        );
+    }
+
+    #[test]
+    fn nocase_column_compares_text_without_case() {
+        let rows = [(1, vec![text("abc"), text("abc")]), (2, vec![text("Abd"), text("Abd")])];
+        let sql = "CREATE TABLE words(plain TEXT, folded TEXT COLLATE NOCASE)";
+        let mut db = Database::from_bytes(FixtureBuilder::default().add_table("words", sql, &rows).build()).unwrap();
+        assert_eq!(column(&mut db, "SELECT folded FROM words WHERE folded = 'ABC'"), [text("abc")]);
+        assert_eq!(column(&mut db, "SELECT plain FROM words WHERE plain = 'ABC'"), Vec::<Value>::new());
+        // Ordering goes through the collation as well: 'abc' < 'Abd' once case is ignored
+        assert_eq!(column(&mut db, "SELECT folded FROM words ORDER BY folded"), [text("abc"), text("Abd")]);
+        assert_eq!(column(&mut db, "SELECT plain FROM words ORDER BY plain"), [text("Abd"), text("abc")]);
+    }
+
+    #[test]
+    fn columns_qualified_with_the_from_table() {
+        let mut db = apples();
+        let sql = "SELECT apples.name FROM apples WHERE Apples.color = 'Red' ORDER BY apples.id";
+        assert_eq!(column(&mut db, sql), [text("Fuji")]);
+    }
+
+    #[test]
+    fn qualifier_naming_another_table_is_an_error() {
+        let err = apples().query("SELECT oranges.name FROM apples", &[]).unwrap_err();
+        assert_eq!(err.to_string(), "no such column: oranges.name");
+    }
+
+    #[test]
+    fn typeof_names_each_storage_class() {
+        let rows = [
+            (1, vec![Value::Null]),
+            (2, vec![Value::Integer(7)]),
+            (3, vec![Value::Real(2.5)]),
+            (4, vec![text("seven")]),
+            (5, vec![Value::Blob(vec![0, 1, 2])]),
+        ];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let types = [text("null"), text("integer"), text("real"), text("text"), text("blob")];
+        assert_eq!(column(&mut db, "SELECT typeof(v) FROM t"), types);
+        // length counts bytes of a blob, and is NULL for NULL
+        let lengths = [Value::Null, Value::Integer(1), Value::Integer(3), Value::Integer(5), Value::Integer(3)];
+        assert_eq!(column(&mut db, "SELECT length(v) FROM t"), lengths);
+    }
+
+    #[test]
+    fn length_of_a_text_column_counts_characters() {
+        let sql = "SELECT length(name) FROM apples WHERE id < 3 OR id = 5";
+        assert_eq!(column(&mut apples(), sql), [Value::Integer(12), Value::Integer(4), Value::Integer(4)]);
+        let rows = [(1, vec![text("héllo")])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(v TEXT)", &rows).build();
+        assert_eq!(column(&mut Database::from_bytes(image).unwrap(), "SELECT length(v) FROM t"), [Value::Integer(5)]);
+    }
+
+    #[test]
+    fn upper_and_lower_convert_text_and_pass_null_through() {
+        let mut db = apples();
+        let sql = "SELECT upper(color) FROM apples WHERE id IN (1, 5)";
+        assert_eq!(column(&mut db, sql), [text("LIGHT GREEN"), Value::Null]);
+        let sql = "SELECT lower(color) FROM apples WHERE id IN (3, 5)";
+        assert_eq!(column(&mut db, sql), [text("blush red"), Value::Null]);
+        // Numbers are converted to text first, as sqlite3 does
+        assert_eq!(column(&mut db, "SELECT upper(id) FROM apples WHERE id = 2"), [text("2")]);
+    }
+
+    #[test]
+    fn substr_counts_from_one_or_back_from_the_end() {
+        let mut db = apples();
+        assert_eq!(column(&mut db, "SELECT substr(name, 1, 3) FROM apples WHERE id = 1"), [text("Gra")]);
+        assert_eq!(column(&mut db, "SELECT substr(name, 3) FROM apples WHERE id = 2"), [text("ji")]);
+        assert_eq!(column(&mut db, "SELECT substr(name, -5, 3) FROM apples WHERE id = 1"), [text("Smi")]);
+        assert_eq!(column(&mut db, "SELECT substr(name, -4) FROM apples WHERE id = 5"), [text("Jazz")]);
+    }
+
+    #[test]
+    fn instr_finds_a_one_based_position_or_zero() {
+        let mut db = apples();
+        assert_eq!(column(&mut db, "SELECT instr(name, 'Smith') FROM apples WHERE id = 1"), [Value::Integer(8)]);
+        assert_eq!(column(&mut db, "SELECT instr(name, 'x') FROM apples WHERE id = 1"), [Value::Integer(0)]);
+        assert_eq!(column(&mut db, "SELECT instr(color, 'x') FROM apples WHERE id = 5"), [Value::Null]);
+    }
+
+    #[test]
+    fn count_of_a_column_skips_nulls() {
+        let mut db = apples();
+        // Jazz has no color, so COUNT(color) comes out below COUNT(*)
+        let result = db.query("SELECT COUNT(color), COUNT(*) FROM apples", &[]).unwrap();
+        assert_eq!(result.rows, [vec![Value::Integer(4), Value::Integer(5)]]);
+        // The rowid alias is never NULL, even though its record column is
+        assert_eq!(column(&mut db, "SELECT COUNT(id) FROM apples"), [Value::Integer(5)]);
+    }
+
+    #[test]
+    fn second_order_term_breaks_ties_in_its_own_direction() {
+        let rows = [
+            (1, vec![text("red"), Value::Integer(1)]),
+            (2, vec![text("green"), Value::Integer(5)]),
+            (3, vec![text("red"), Value::Integer(3)]),
+            (4, vec![text("green"), Value::Integer(2)]),
+            (5, vec![text("red"), Value::Integer(2)]),
+        ];
+        let sql = "CREATE TABLE box(color TEXT, size INTEGER)";
+        let image = FixtureBuilder::default().add_table("box", sql, &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        let result = db.query("SELECT color, size FROM box ORDER BY color ASC, size DESC", &[]).unwrap();
+        let expected = [("green", 5), ("green", 2), ("red", 3), ("red", 2), ("red", 1)];
+        let expected: Vec<Vec<Value>> =
+            expected.iter().map(|&(color, size)| vec![text(color), Value::Integer(size)]).collect();
+        assert_eq!(result.rows, expected);
+        let sizes = column(&mut db, "SELECT size FROM box ORDER BY color DESC, size");
+        assert_eq!(sizes, [1, 2, 3, 2, 5].map(Value::Integer));
+    }
+
+    #[test]
+    fn hex_of_a_blob_and_quote_of_text_with_a_quote() {
+        let rows = [(1, vec![Value::Blob(vec![0x00, 0xab, 0x10]), text("it's")])];
+        let image = FixtureBuilder::default().add_table("t", "CREATE TABLE t(data BLOB, note TEXT)", &rows).build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(column(&mut db, "SELECT hex(data) FROM t"), [text("00AB10")]);
+        assert_eq!(column(&mut db, "SELECT hex(note) FROM t"), [text("69742773")]);
+        assert_eq!(column(&mut db, "SELECT quote(note) FROM t"), [text("'it''s'")]);
+        assert_eq!(column(&mut db, "SELECT quote(data) FROM t"), [text("X'00AB10'")]);
+    }
+
+    // `t(id INTEGER PRIMARY KEY, a INTEGER, b TEXT)` with a = id % 100, and
+    // with or without an index on a
+    fn numbers(indexed: bool) -> Database {
+        let rows: Vec<(i64, Vec<Value>)> = (1..=3000)
+            .map(|id| (id, vec![Value::Null, Value::Integer(id % 100), text(&format!("row {}", id))]))
+            .collect();
+        let mut builder = FixtureBuilder::default();
+        builder.add_table("t", "CREATE TABLE t(id INTEGER PRIMARY KEY, a INTEGER, b TEXT)", &rows);
+        if indexed {
+            let entries: Vec<Vec<Value>> =
+                rows.iter().map(|(id, values)| vec![values[1].clone(), Value::Integer(*id)]).collect();
+            builder.add_index("idx_a", "t", "CREATE INDEX idx_a ON t(a)", &entries);
+        }
+        Database::from_bytes(builder.build()).unwrap()
+    }
+
+    #[test]
+    fn index_range_scan_matches_a_full_scan_reading_fewer_pages() {
+        // The index covers the query, so the range scan never reads the table
+        let sql = "SELECT id, a FROM t WHERE a BETWEEN 10 AND 12";
+        let mut results = Vec::new();
+        for indexed in [true, false] {
+            let mut db = numbers(indexed);
+            let select = parse_select(sql).unwrap();
+            let plan = plan(&select, &db.schema().unwrap()).unwrap();
+            match &plan.access {
+                Access::IndexSearch { range, covering, .. } if indexed => assert!(*covering && !range.is_unbounded()),
+                Access::FullScan if !indexed => {}
+                other => panic!("unexpected access {:?}", other),
+            }
+            let before = db.page_stats();
+            let mut rows = execute_plan(&mut db, &plan, &select).unwrap().rows;
+            rows.sort_by_key(|row| match row[0] {
+                Value::Integer(id) => id,
+                _ => unreachable!(),
+            });
+            results.push((rows, db.page_stats().since(before).total));
+        }
+        let (indexed, full) = (&results[0], &results[1]);
+        assert_eq!(indexed.0.len(), 90);
+        assert_eq!(indexed.0, full.0);
+        assert!(indexed.1 < full.1, "index range read {} pages, full scan {}", indexed.1, full.1);
+    }
+
+    #[test]
+    fn select_without_from_evaluates_once() {
+        let mut db = apples();
+        let result = db.query("SELECT 1+1", &[]).unwrap();
+        assert_eq!(result.rows, [vec![Value::Integer(2)]]);
+        assert_eq!(column(&mut db, "SELECT 'a' || 'b'"), [text("ab")]);
+        let result = db.query("SELECT 'hello', 2 * 3.5, NULL", &[]).unwrap();
+        assert_eq!(result.rows, [vec![text("hello"), Value::Real(7.0), Value::Null]]);
+    }
+
+    #[test]
+    fn concatenation_of_two_text_columns() {
+        let sql = "SELECT name || ' (' || color || ')' FROM apples WHERE id = 2";
+        assert_eq!(column(&mut apples(), sql), [text("Fuji (Red)")]);
+        // Numbers are converted to text
+        assert_eq!(column(&mut apples(), "SELECT id || name FROM apples WHERE id = 4"), [text("4Golden Delicious")]);
+    }
+
+    #[test]
+    fn concatenation_with_null_is_null() {
+        let mut db = apples();
+        assert_eq!(column(&mut db, "SELECT name || color FROM apples WHERE id = 5"), [Value::Null]);
+        assert_eq!(column(&mut db, "SELECT NULL || 'x'"), [Value::Null]);
+        // A NULL comparison is never true, so Jazz is left out
+        let ids: Vec<Value> = (1..=4).map(Value::Integer).collect();
+        assert_eq!(column(&mut db, "SELECT id FROM apples WHERE name || color != ''"), ids);
+    }
+
+    #[test]
+    fn is_compares_nulls_as_equal() {
+        let mut db = apples();
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE color IS NULL"), [text("Jazz")]);
+        assert_eq!(column(&mut db, "SELECT name FROM apples WHERE color IS 'Red'"), [text("Fuji")]);
+        // Unlike !=, IS NOT is true rather than unknown against NULL
+        assert_eq!(column(&mut db, "SELECT id FROM apples WHERE color IS NOT 'Red'"), [1, 3, 4, 5].map(Value::Integer));
+        assert_eq!(column(&mut db, "SELECT id FROM apples WHERE color != 'Red'"), [1, 3, 4].map(Value::Integer));
+        let result = db.query("SELECT NULL IS NULL, NULL = NULL, 1 IS NOT NULL", &[]).unwrap();
+        assert_eq!(result.rows, [vec![Value::Integer(1), Value::Null, Value::Integer(1)]]);
+    }
+
+    #[test]
+    fn tables_named_with_reserved_words_or_spaces() {
+        let rows = [(1, vec![text("first")]), (2, vec![text("second")])];
+        let image = FixtureBuilder::default()
+            .add_table("order", "CREATE TABLE \"order\" (item TEXT)", &rows)
+            .add_table("my table", "CREATE TABLE [my table] (item TEXT)", &rows[..1])
+            .build();
+        let mut db = Database::from_bytes(image).unwrap();
+        assert_eq!(column(&mut db, "SELECT item FROM \"order\" WHERE item = 'second'"), [text("second")]);
+        assert_eq!(column(&mut db, "SELECT COUNT(*) FROM `order`"), [Value::Integer(2)]);
+        assert_eq!(column(&mut db, "SELECT item FROM [my table]"), [text("first")]);
+    }
+
+    #[test]
+    fn legacy_limit_gives_the_offset_first() {
+        let mut db = Database::from_bytes(crate::fixture::table_fixture(30)).unwrap();
+        let legacy = column(&mut db, "SELECT id FROM t LIMIT 5, 10");
+        assert_eq!(legacy, (6..=15).map(Value::Integer).collect::<Vec<_>>());
+        assert_eq!(legacy, column(&mut db, "SELECT id FROM t LIMIT 10 OFFSET 5"));
+        assert_eq!(column(&mut db, "SELECT id FROM t LIMIT 28, 10"), [Value::Integer(29), Value::Integer(30)]);
+    }
+}
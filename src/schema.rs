@@ -0,0 +1,418 @@
+use anyhow::{bail, Result};
+
+use crate::parser::{parse_select_from, Parser, SelectStatement};
+use crate::tokenizer::{tokenize, Token};
+use crate::value::{Collation, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub type_name: String,
+    pub primary_key: bool,
+    // Value for rows written before the column was added with ALTER TABLE,
+    // whose records stop short of it
+    pub default: Value,
+    pub collation: Collation,
+}
+
+impl Column {
+    // Columns with REAL affinity (by SQLite's rules, a type naming REAL,
+    // FLOA or DOUB but not INT) store whole numbers as integers to save
+    // space, and read them back as reals.
+    pub fn has_real_affinity(&self) -> bool {
+        let type_name = self.type_name.to_ascii_uppercase();
+        !type_name.contains("INT") && ["REAL", "FLOA", "DOUB"].iter().any(|word| type_name.contains(word))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<Column>,
+    // Index of the INTEGER PRIMARY KEY column, whose value is the rowid
+    // rather than anything stored in the record
+    pub rowid_alias: Option<usize>,
+}
+
+impl TableSchema {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name.eq_ignore_ascii_case(name))
+    }
+
+    // BINARY for names that aren't columns, such as the rowid
+    pub fn collation(&self, name: &str) -> Collation {
+        self.column_index(name).map_or(Collation::Binary, |index| self.columns[index].collation)
+    }
+}
+
+// The schema table itself, queryable under either of its names
+pub fn is_schema_table(name: &str) -> bool {
+    name.eq_ignore_ascii_case("sqlite_master") || name.eq_ignore_ascii_case("sqlite_schema")
+}
+
+// A plain column, as found in sqlite_master and views
+pub fn untyped_column(name: &str, type_name: &str) -> Column {
+    Column {
+        name: name.to_string(),
+        type_name: type_name.to_string(),
+        primary_key: false,
+        default: Value::Null,
+        collation: Collation::Binary,
+    }
+}
+
+pub fn schema_table(name: &str) -> TableSchema {
+    let column = untyped_column;
+    TableSchema {
+        name: name.to_string(),
+        columns: vec![
+            column("type", "text"),
+            column("name", "text"),
+            column("tbl_name", "text"),
+            column("rootpage", "int"),
+            column("sql", "text"),
+        ],
+        rowid_alias: None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexSchema {
+    pub name: String,
+    pub table: String,
+    // Key columns in index order; the rowid follows them in every entry
+    pub columns: Vec<String>,
+}
+
+// An index as listed for schema tools, whatever its key order or collation
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexInfo {
+    pub name: String,
+    pub table: String,
+    // Key columns in index order, without their ASC/DESC or COLLATE suffixes
+    pub columns: Vec<String>,
+    pub root_page: u32,
+    pub unique: bool,
+    // Has a WHERE clause, so only some of the table's rows are indexed
+    pub partial: bool,
+    // Has a DESC key, which needs schema format 4
+    pub descending: bool,
+}
+
+// A FOREIGN KEY table constraint or a column's REFERENCES clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    // Columns of the referencing table
+    pub columns: Vec<String>,
+    pub parent: String,
+    // Columns of the parent table; none for its primary key
+    pub parent_columns: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ViewSchema {
+    pub name: String,
+    // Names given in `CREATE VIEW v(a, b) AS ...`, overriding the SELECT's
+    pub columns: Option<Vec<String>>,
+    pub select: SelectStatement,
+}
+
+const CONSTRAINT_KEYWORDS: [&str; 5] = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"];
+const COLUMN_CONSTRAINT_KEYWORDS: [&str; 9] =
+    ["CONSTRAINT", "PRIMARY", "NOT", "NULL", "UNIQUE", "CHECK", "DEFAULT", "COLLATE", "REFERENCES"];
+
+pub fn parse_create_table(sql: &str) -> Result<TableSchema> {
+    let mut parser = Parser::new(tokenize(sql)?);
+    let name = parse_table_header(&mut parser)?;
+    let mut columns = Vec::new();
+    let mut key_columns = Vec::new();
+    for definition in split_definitions(&mut parser)? {
+        let first = match definition.first() {
+            Some(token) => token,
+            None => bail!("empty column definition in {}", name),
+        };
+        if CONSTRAINT_KEYWORDS.iter().any(|keyword| first.is_keyword(keyword)) {
+            key_columns.extend(primary_key_columns(&definition));
+            continue;
+        }
+        columns.push(parse_column(&definition)?);
+    }
+    // A table constraint `PRIMARY KEY (a, b)` marks its columns as if they
+    // had been declared inline
+    for key in key_columns.iter() {
+        if let Some(column) = columns.iter_mut().find(|column| column.name.eq_ignore_ascii_case(key)) {
+            column.primary_key = true;
+        }
+    }
+
+    // Only a single-column key can alias the rowid
+    let rowid_alias = columns
+        .iter()
+        .position(|column| column.primary_key && column.type_name.eq_ignore_ascii_case("INTEGER"))
+        .filter(|_| key_columns.len() <= 1);
+    Ok(TableSchema { name, columns, rowid_alias })
+}
+
+// `CREATE [TEMP] TABLE [IF NOT EXISTS] [schema.]name (`, giving the name
+fn parse_table_header(parser: &mut Parser) -> Result<String> {
+    parser.expect_keyword("CREATE")?;
+    if !parser.eat_keyword("TEMP") {
+        parser.eat_keyword("TEMPORARY");
+    }
+    parser.expect_keyword("TABLE")?;
+    if parser.eat_keyword("IF") {
+        parser.expect_keyword("NOT")?;
+        parser.expect_keyword("EXISTS")?;
+    }
+    let mut name = parser.identifier()?;
+    if parser.eat_symbol(".") {
+        name = parser.identifier()?;
+    }
+    parser.expect_symbol("(")?;
+    Ok(name)
+}
+
+// Column names of a `[CONSTRAINT name] PRIMARY KEY (a [ASC|DESC], ...)` table
+// constraint; other constraints name none
+fn primary_key_columns(definition: &[Token]) -> Vec<String> {
+    match definition.windows(3).position(|window| {
+        window[0].is_keyword("PRIMARY") && window[1].is_keyword("KEY") && window[2] == Token::Symbol("(")
+    }) {
+        Some(at) => column_list(&definition[at + 3..]),
+        None => Vec::new(),
+    }
+}
+
+// The names in a parenthesised column list, given the tokens after its `(`.
+// Anything after a name, such as ASC or COLLATE, is skipped.
+fn column_list(tokens: &[Token]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut expect_name = true;
+    for token in tokens.iter() {
+        match token {
+            Token::Symbol(")") => break,
+            Token::Symbol(",") => expect_name = true,
+            Token::Ident(name) | Token::QuotedIdent(name) if expect_name => {
+                names.push(name.clone());
+                expect_name = false;
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+// The table's foreign keys: `FOREIGN KEY (a, b) REFERENCES parent (x, y)`
+// table constraints, and `REFERENCES parent [(x)]` on single columns.
+// ON DELETE, MATCH and DEFERRABLE clauses are ignored.
+pub fn parse_foreign_keys(sql: &str) -> Result<Vec<ForeignKey>> {
+    let mut parser = Parser::new(tokenize(sql)?);
+    parse_table_header(&mut parser)?;
+    let mut foreign_keys = Vec::new();
+    for definition in split_definitions(&mut parser)? {
+        let references = match definition.iter().position(|token| token.is_keyword("REFERENCES")) {
+            Some(at) => at,
+            None => continue,
+        };
+        let columns = match definition.first() {
+            Some(first) if CONSTRAINT_KEYWORDS.iter().any(|keyword| first.is_keyword(keyword)) => {
+                match definition.windows(3).position(|window| {
+                    window[0].is_keyword("FOREIGN") && window[1].is_keyword("KEY") && window[2] == Token::Symbol("(")
+                }) {
+                    Some(at) => column_list(&definition[at + 3..]),
+                    None => bail!("REFERENCES outside a FOREIGN KEY constraint"),
+                }
+            }
+            _ => vec![parse_column(&definition)?.name],
+        };
+        let (parent, parent_columns) = match &definition[references + 1..] {
+            [Token::Ident(name) | Token::QuotedIdent(name) | Token::Str(name), Token::Symbol("("), rest @ ..] => {
+                (name.clone(), column_list(rest))
+            }
+            [Token::Ident(name) | Token::QuotedIdent(name) | Token::Str(name), ..] => (name.clone(), Vec::new()),
+            _ => bail!("REFERENCES without a table name"),
+        };
+        foreign_keys.push(ForeignKey { columns, parent, parent_columns });
+    }
+    Ok(foreign_keys)
+}
+
+// Only plain ascending column lists are understood. Expression, partial,
+// descending and collated indexes are rejected, as searching them by
+// comparing values would give wrong answers.
+pub fn parse_create_index(sql: &str) -> Result<IndexSchema> {
+    let mut parser = Parser::new(tokenize(sql)?);
+    let (name, table, _) = parse_index_header(&mut parser)?;
+    let mut columns = Vec::new();
+    for definition in split_definitions(&mut parser)? {
+        match definition.as_slice() {
+            [Token::Ident(column)] | [Token::QuotedIdent(column)] => columns.push(column.clone()),
+            [Token::Ident(column), order] | [Token::QuotedIdent(column), order] if order.is_keyword("ASC") => {
+                columns.push(column.clone())
+            }
+            _ => bail!("unsupported key in index {}", name),
+        }
+    }
+    if !parser.at_end() {
+        bail!("unsupported partial index {}", name);
+    }
+    Ok(IndexSchema { name, table, columns })
+}
+
+// Unlike parse_create_index, accepts keys with ASC/DESC and COLLATE
+// suffixes and partial indexes, since nothing is searched through them.
+// Expression keys have no column name and are still rejected.
+pub fn parse_index_info(sql: &str, root_page: u32) -> Result<IndexInfo> {
+    let mut parser = Parser::new(tokenize(sql)?);
+    let (name, table, unique) = parse_index_header(&mut parser)?;
+    let mut columns = Vec::new();
+    let mut descending = false;
+    for definition in split_definitions(&mut parser)? {
+        match definition.first() {
+            Some(Token::Ident(column)) | Some(Token::QuotedIdent(column)) if is_key_suffix(&definition[1..]) => {
+                columns.push(column.clone());
+                descending |= definition.last().is_some_and(|order| order.is_keyword("DESC"));
+            }
+            _ => bail!("unsupported key in index {}", name),
+        }
+    }
+    let partial = parser.eat_keyword("WHERE");
+    Ok(IndexInfo { name, table, columns, root_page, unique, partial, descending })
+}
+
+// `CREATE [UNIQUE] INDEX [IF NOT EXISTS] [schema.]name ON table (`, giving
+// the index name, its table and whether it is unique
+fn parse_index_header(parser: &mut Parser) -> Result<(String, String, bool)> {
+    parser.expect_keyword("CREATE")?;
+    let unique = parser.eat_keyword("UNIQUE");
+    parser.expect_keyword("INDEX")?;
+    if parser.eat_keyword("IF") {
+        parser.expect_keyword("NOT")?;
+        parser.expect_keyword("EXISTS")?;
+    }
+    let mut name = parser.identifier()?;
+    if parser.eat_symbol(".") {
+        name = parser.identifier()?;
+    }
+    parser.expect_keyword("ON")?;
+    let table = parser.identifier()?;
+    parser.expect_symbol("(")?;
+    Ok((name, table, unique))
+}
+
+// What may follow a key column: `[COLLATE name] [ASC | DESC]`
+fn is_key_suffix(tokens: &[Token]) -> bool {
+    let rest = match tokens {
+        [collate, _, rest @ ..] if collate.is_keyword("COLLATE") => rest,
+        _ => tokens,
+    };
+    match rest {
+        [] => true,
+        [order] => order.is_keyword("ASC") || order.is_keyword("DESC"),
+        _ => false,
+    }
+}
+
+// Views are limited to what parse_select understands: a single table, no
+// joins or compound SELECTs.
+pub fn parse_create_view(sql: &str) -> Result<ViewSchema> {
+    let mut parser = Parser::new(tokenize(sql)?);
+    parser.expect_keyword("CREATE")?;
+    if !parser.eat_keyword("TEMP") {
+        parser.eat_keyword("TEMPORARY");
+    }
+    parser.expect_keyword("VIEW")?;
+    if parser.eat_keyword("IF") {
+        parser.expect_keyword("NOT")?;
+        parser.expect_keyword("EXISTS")?;
+    }
+    let mut name = parser.identifier()?;
+    if parser.eat_symbol(".") {
+        name = parser.identifier()?;
+    }
+    let mut columns = None;
+    if parser.eat_symbol("(") {
+        let mut names = vec![parser.identifier()?];
+        while parser.eat_symbol(",") {
+            names.push(parser.identifier()?);
+        }
+        parser.expect_symbol(")")?;
+        columns = Some(names);
+    }
+    parser.expect_keyword("AS")?;
+    match parse_select_from(&mut parser) {
+        Ok(select) => Ok(ViewSchema { name, columns, select }),
+        Err(err) => bail!("unsupported view {}: {}", name, err),
+    }
+}
+
+// Splits the parenthesised body of a CREATE statement into its top-level
+// comma-separated definitions, consuming the closing parenthesis.
+fn split_definitions(parser: &mut Parser) -> Result<Vec<Vec<Token>>> {
+    let mut definitions = vec![Vec::new()];
+    let mut depth = 0;
+    loop {
+        let token = match parser.next_token() {
+            Some(token) => token,
+            None => bail!("unterminated column list"),
+        };
+        match token {
+            Token::Symbol(")") if depth == 0 => break,
+            Token::Symbol(",") if depth == 0 => {
+                definitions.push(Vec::new());
+                continue;
+            }
+            Token::Symbol("(") => depth += 1,
+            Token::Symbol(")") => depth -= 1,
+            _ => {}
+        }
+        if let Some(definition) = definitions.last_mut() {
+            definition.push(token);
+        }
+    }
+    Ok(definitions)
+}
+
+fn parse_column(definition: &[Token]) -> Result<Column> {
+    let name = match &definition[0] {
+        Token::Ident(name) | Token::QuotedIdent(name) | Token::Str(name) => name.clone(),
+        other => bail!("invalid column name {:?}", other),
+    };
+    let mut type_words = Vec::new();
+    for token in definition[1..].iter() {
+        match token {
+            Token::Ident(word) if !COLUMN_CONSTRAINT_KEYWORDS.iter().any(|k| word.eq_ignore_ascii_case(k)) => {
+                type_words.push(word.clone())
+            }
+            _ => break,
+        }
+    }
+    let primary_key = definition.windows(2).any(|pair| pair[0].is_keyword("PRIMARY") && pair[1].is_keyword("KEY"));
+    let default = match definition.iter().position(|token| token.is_keyword("DEFAULT")) {
+        Some(at) => parse_default(&definition[at + 1..]),
+        None => Value::Null,
+    };
+    // Collations other than the built-in ones compare as BINARY
+    let collation = match definition.windows(2).find(|pair| pair[0].is_keyword("COLLATE")) {
+        Some([_, Token::Ident(name)]) | Some([_, Token::QuotedIdent(name)]) => {
+            Collation::from_name(name).unwrap_or_default()
+        }
+        _ => Collation::Binary,
+    };
+    Ok(Column { name, type_name: type_words.join(" "), primary_key, default, collation })
+}
+
+// Only literal defaults are understood; expressions such as
+// DEFAULT (random()) or CURRENT_TIMESTAMP read as NULL
+fn parse_default(tokens: &[Token]) -> Value {
+    let negate = matches!(tokens.first(), Some(Token::Symbol("-")));
+    let literal = if negate || matches!(tokens.first(), Some(Token::Symbol("+"))) { tokens.get(1) } else { tokens.first() };
+    match literal {
+        Some(Token::Integer(i)) => Value::Integer(if negate { -i } else { *i }),
+        Some(Token::Real(r)) => Value::Real(if negate { -r } else { *r }),
+        Some(Token::Str(s)) => Value::Text(s.clone()),
+        Some(token) if token.is_keyword("TRUE") => Value::Integer(1),
+        Some(token) if token.is_keyword("FALSE") => Value::Integer(0),
+        _ => Value::Null,
+    }
+}
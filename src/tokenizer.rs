@@ -0,0 +1,110 @@
+use anyhow::{bail, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    // Bare identifiers and keywords
+    Ident(String),
+    // "name", [name] or `name`
+    QuotedIdent(String),
+    Str(String),
+    Integer(i64),
+    Real(f64),
+    Symbol(&'static str),
+}
+
+impl Token {
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Ident(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+const SYMBOLS: [&str; 19] = [
+    "||", "<=", ">=", "<>", "!=", "==", "(", ")", ",", ";", ".", "*", "=", "<", ">", "+", "-", "/", "?",
+];
+
+pub fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '\'' {
+            let (text, end) = read_quoted(&chars, i, '\'')?;
+            tokens.push(Token::Str(text));
+            i = end;
+        } else if c == '"' || c == '`' {
+            let (text, end) = read_quoted(&chars, i, c)?;
+            tokens.push(Token::QuotedIdent(text));
+            i = end;
+        } else if c == '[' {
+            let start = i + 1;
+            while i < chars.len() && chars[i] != ']' {
+                i += 1;
+            }
+            if i == chars.len() {
+                bail!("unterminated identifier starting at offset {}", start - 1);
+            }
+            tokens.push(Token::QuotedIdent(chars[start..i].iter().collect()));
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                // Exponent sign, as in 1e-5
+                if (chars[i] == 'e' || chars[i] == 'E') && matches!(chars.get(i + 1), Some('+') | Some('-')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            match number.parse::<i64>() {
+                Ok(n) => tokens.push(Token::Integer(n)),
+                Err(_) => match number.parse::<f64>() {
+                    Ok(r) => tokens.push(Token::Real(r)),
+                    Err(_) => bail!("invalid number: {}", number),
+                },
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                Some(symbol) => {
+                    tokens.push(Token::Symbol(symbol));
+                    i += symbol.len();
+                }
+                None => bail!("unexpected character '{}' in statement", c),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Reads a quoted run starting at `start`, where a doubled quote stands for a
+// literal one. Returns the unquoted text and the index just past the closing quote.
+fn read_quoted(chars: &[char], start: usize, quote: char) -> Result<(String, usize)> {
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                text.push(quote);
+                i += 2;
+                continue;
+            }
+            return Ok((text, i + 1));
+        }
+        text.push(chars[i]);
+        i += 1;
+    }
+    bail!("unterminated quoted text starting at offset {}", start)
+}